@@ -0,0 +1,25 @@
+//! The cost of a record, which is dropped by the target filters, for a growing number of filters.
+//!
+//! The filters are checked with a binary search, so the time should barely grow with the count.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use v_log::Color;
+
+fn filtered_out(c: &mut Criterion) {
+    web_vlog::validate_init();
+    let mut group = c.benchmark_group("filtered_out");
+    for count in [1, 10, 100, 1000, 10000] {
+        let filters: Vec<String> = (0..count).map(|i| format!("crate_{i}::module")).collect();
+        let filters: Vec<&str> = filters.iter().map(String::as_str).collect();
+        web_vlog::set_targets(&filters);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| {
+                web_vlog::circle!(target: black_box("other::module"), "surface", [0.0, 0.0], 1.0, Color::Base)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, filtered_out);
+criterion_main!(benches);
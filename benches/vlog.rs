@@ -0,0 +1,144 @@
+//! The cost of vlogging a record on the vlogging thread and the throughput of the websocket send loop.
+//!
+//! A websocket client in the benchmark reads and counts all frames, so the records don't pile up
+//! in the queues and the send loop can be timed until the last record arrived at the client.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Once,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use v_log::Color;
+
+/// The number of frames received by the client.
+static FRAMES: AtomicU64 = AtomicU64::new(0);
+
+static INIT: Once = Once::new();
+
+/// Start the vlogger and connect the counting client once for all benchmarks.
+fn setup() {
+    INIT.call_once(|| {
+        // large enough for the records of a whole sample, so the client doesn't drop any.
+        let port = web_vlog::Builder::new()
+            .port(0)
+            .client_queue(1 << 20)
+            .init()
+            .unwrap();
+        connect(port);
+        web_vlog::wait_for_connection();
+    });
+}
+
+/// Connect a websocket client, which reads and counts all frames on its own thread.
+fn connect(port: u16) {
+    let mut stream = TcpStream::connect(("localhost", port)).unwrap();
+    stream
+        .write_all(b"GET /ws HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n")
+        .unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    while line != "\r\n" {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+    }
+    // acknowledge the hello message, so the compact encoding is used like in the viewer.
+    assert!(read_frame(&mut reader));
+    let ack = br#"{"ack":2}"#;
+    stream
+        .write_all(&[0x81, 0x80 | ack.len() as u8, 0, 0, 0, 0])
+        .unwrap();
+    stream.write_all(ack).unwrap();
+    thread::spawn(move || {
+        while read_frame(&mut reader) {
+            FRAMES.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+}
+
+/// Wait until the client received everything vlogged before.
+fn settle() {
+    loop {
+        let frames = FRAMES.load(Ordering::Relaxed);
+        thread::sleep(Duration::from_millis(20));
+        if FRAMES.load(Ordering::Relaxed) == frames && web_vlog::stats().queued == 0 {
+            return;
+        }
+    }
+}
+
+/// Read and discard a single frame. Returns false when the connection was closed.
+fn read_frame(reader: &mut impl Read) -> bool {
+    let mut header = [0; 2];
+    if reader.read_exact(&mut header).is_err() || header[0] & 0x0f == 0x8 {
+        return false;
+    }
+    let len = match header[1] & 0x7f {
+        126 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len).unwrap();
+            u16::from_be_bytes(len) as u64
+        }
+        127 => {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len).unwrap();
+            u64::from_be_bytes(len)
+        }
+        len => len as u64,
+    };
+    io::copy(&mut reader.take(len), &mut io::sink()).is_ok()
+}
+
+fn vlog(c: &mut Criterion) {
+    setup();
+    let mut group = c.benchmark_group("vlog");
+    let mut i = 0u64;
+    group.bench_function("point", |b| {
+        b.iter(|| {
+            i += 1;
+            v_log::point!("bench", [black_box(1.0), 2.0], 3.0, Base, "{}", i);
+        })
+    });
+    group.bench_function("message", |b| {
+        b.iter(|| {
+            i += 1;
+            v_log::message!("bench", "message number {}", i);
+        })
+    });
+    group.bench_function("circle", |b| {
+        b.iter(|| {
+            i += 1;
+            web_vlog::circle!("bench", [black_box(1.0), 2.0], 3.0, Color::Base, "{}", i);
+        })
+    });
+    group.finish();
+}
+
+fn send_loop(c: &mut Criterion) {
+    setup();
+    let mut group = c.benchmark_group("send_loop");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("point", |b| {
+        b.iter_custom(|iters| {
+            settle();
+            let target = FRAMES.load(Ordering::Relaxed) + iters;
+            let start = Instant::now();
+            for i in 0..iters {
+                v_log::point!("bench", [1.0, 2.0], 3.0, Base, "{}", i);
+            }
+            while FRAMES.load(Ordering::Relaxed) < target {
+                std::hint::spin_loop();
+            }
+            start.elapsed()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, vlog, send_loop);
+criterion_main!(benches);
@@ -0,0 +1,157 @@
+//! The audit trail of the connections to the vlogging server.
+
+use crate::json::Escape;
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    fmt::Write as _,
+    io::{self, Read, Write},
+    net::SocketAddr,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The maximal number of connections kept in the audit log. Older connections are forgotten.
+const MAX_CONNECTIONS: usize = 1024;
+
+static CONNECTIONS: Mutex<VecDeque<Connection>> = Mutex::new(VecDeque::new());
+
+/// The result of the authentication of a connection.
+///
+/// This is reserved for the authentication of clients. The server doesn't authenticate its clients yet,
+/// so every connection is [`NotRequired`](Self::NotRequired). More variants will be added with the authentication.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AuthResult {
+    /// The server doesn't require authentication.
+    NotRequired,
+}
+
+impl AuthResult {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::NotRequired => "not required",
+        }
+    }
+}
+
+/// A finished connection to the vlogging server, see [`connections`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Connection {
+    /// The time the connection was accepted.
+    pub time: SystemTime,
+    /// The address of the client.
+    pub peer: Option<SocketAddr>,
+    /// The requested path, e.g. `/` for the webpage.
+    pub path: String,
+    /// The `User-Agent` header of the request.
+    pub user_agent: String,
+    /// If the connection was upgraded to a websocket.
+    pub websocket: bool,
+    /// The HTTP status code of the response.
+    pub status: u16,
+    /// The result of the authentication. This is reserved and always [`AuthResult::NotRequired`],
+    /// as the server doesn't authenticate its clients yet.
+    pub auth: AuthResult,
+    /// The time until the connection was closed.
+    pub duration: Duration,
+    /// The number of bytes read from the client, including the request.
+    pub bytes_received: u64,
+    /// The number of bytes written to the client, including the response.
+    pub bytes_sent: u64,
+}
+
+impl Connection {
+    pub(crate) fn new(peer: Option<SocketAddr>) -> Self {
+        Self {
+            time: SystemTime::now(),
+            peer,
+            path: String::new(),
+            user_agent: String::new(),
+            websocket: false,
+            status: 0,
+            auth: AuthResult::NotRequired,
+            duration: Duration::ZERO,
+            bytes_received: 0,
+            bytes_sent: 0,
+        }
+    }
+}
+
+/// Get the audit log of the last 1024 connections to the vlogging server, oldest first.
+///
+/// Connections are added once they are closed. The log is also served as JSON on the `/connections` path.
+pub fn connections() -> Vec<Connection> {
+    CONNECTIONS.lock().unwrap().iter().cloned().collect()
+}
+
+/// Add a finished connection to the audit log.
+pub(crate) fn push(connection: Connection) {
+    let mut connections = CONNECTIONS.lock().unwrap();
+    if connections.len() >= MAX_CONNECTIONS {
+        connections.pop_front();
+    }
+    connections.push_back(connection);
+}
+
+/// The audit log as JSON array.
+pub(crate) fn to_json() -> String {
+    let connections = CONNECTIONS.lock().unwrap();
+    let mut out = String::from("[");
+    for (i, c) in connections.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let time = c
+            .time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis());
+        let peer = c.peer.map_or_else(String::new, |peer| peer.to_string());
+        write!(
+            &mut out,
+            "{{\"time\":{time},\"peer\":\"{peer}\",\"path\":\"{}\",\"agent\":\"{}\",\"websocket\":{},\"status\":{},\"auth\":\"{}\",\"duration\":{},\"received\":{},\"sent\":{}}}",
+            Escape(&c.path),
+            Escape(&c.user_agent),
+            c.websocket,
+            c.status,
+            c.auth.as_str(),
+            c.duration.as_secs_f64(),
+            c.bytes_received,
+            c.bytes_sent,
+        )
+        .unwrap();
+    }
+    out.push(']');
+    out
+}
+
+/// Counts the bytes read from or written to the inner stream.
+pub(crate) struct Counted<'a, T> {
+    pub inner: T,
+    pub bytes: &'a Cell<u64>,
+}
+
+impl<T: Read> Read for Counted<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes.set(self.bytes.get() + n as u64);
+        Ok(n)
+    }
+}
+
+impl<T: Write> Write for Counted<'_, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bytes.set(self.bytes.get() + n as u64);
+        Ok(n)
+    }
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        let n = self.inner.write_vectored(bufs)?;
+        self.bytes.set(self.bytes.get() + n as u64);
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
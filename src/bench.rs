@@ -0,0 +1,267 @@
+//! Live plots of benchmark timings, see [`BenchPlot`].
+//!
+//! The timing samples of every benchmark are drawn as a violin of their distribution with a line
+//! at the median on the surface `benchmarks`, so the benchmarks can be compared side by side.
+//! Every sample is also appended to a line chart on the surface `benchmarks/samples`,
+//! which shows the timings in microseconds over the iterations, e.g. to spot warm-up or thermal throttling.
+//!
+//! ```
+//! use std::time::Instant;
+//! use web_vlog::bench::BenchPlot;
+//!
+//! let mut plot = BenchPlot::new();
+//! for _ in 0..100 {
+//!     plot.time("collect", || (0..1000u64).collect::<Vec<_>>());
+//!     let start = Instant::now();
+//!     std::hint::black_box((0..1000u64).sum::<u64>());
+//!     plot.record("sum", start.elapsed());
+//! }
+//! plot.draw();
+//! ```
+//!
+//! With `criterion`, the samples can be recorded in `iter_custom`, which times the iterations itself.
+
+use crate::shape::{self, Location, PathSegment, Shape, ShapeStyle, TextBox};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+use v_log::Color;
+
+/// The target of the plots.
+const TARGET: &str = "bench";
+
+/// The minimal time between two redraws of the violins, when samples are recorded.
+const REDRAW: Duration = Duration::from_millis(200);
+
+/// The width of a violin and the space between the centers of two violins.
+const WIDTH: f64 = 80.0;
+const SPACING: f64 = 120.0;
+
+/// The height of the violins for the slowest sample.
+const HEIGHT: f64 = 300.0;
+
+/// The number of points along each side of a violin.
+const RESOLUTION: usize = 48;
+
+/// The most samples the density of a violin is estimated from. Larger sets are thinned to
+/// evenly spaced quantiles, so a redraw takes the same time however long the benchmark runs.
+const DENSITY_SAMPLES: usize = 512;
+
+/// The colors of the benchmarks in the order they were first recorded.
+const COLORS: [Color; 7] = [
+    Color::Info,
+    Color::Healthy,
+    Color::Warn,
+    Color::Error,
+    Color::X,
+    Color::Y,
+    Color::Z,
+];
+
+/// Collects the timing samples of benchmarks and plots them live, see the [module documentation](self).
+#[derive(Clone, Debug)]
+pub struct BenchPlot {
+    surface: String,
+    /// The samples in seconds by the name of the benchmark. They are sorted when the violins are drawn.
+    samples: BTreeMap<String, Vec<f64>>,
+    /// The names of the benchmarks in the order they were first recorded.
+    order: Vec<String>,
+    drawn: Option<Instant>,
+    file: &'static str,
+    line: u32,
+}
+
+impl Default for BenchPlot {
+    #[track_caller]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BenchPlot {
+    /// Create a plot on the surface `benchmarks`.
+    #[track_caller]
+    pub fn new() -> Self {
+        let caller = std::panic::Location::caller();
+        Self {
+            surface: "benchmarks".to_owned(),
+            samples: BTreeMap::new(),
+            order: vec![],
+            drawn: None,
+            file: caller.file(),
+            line: caller.line(),
+        }
+    }
+    /// Draw on the surface instead. The line chart is drawn on the surface with `/samples` appended.
+    pub fn surface(mut self, surface: &str) -> Self {
+        self.surface = surface.to_owned();
+        self
+    }
+    /// Add a timing sample of the benchmark `name`.
+    ///
+    /// The violins are redrawn at most every few hundred milliseconds, so a fast benchmark isn't
+    /// slowed down by the plots. Call [`draw`](Self::draw) after the last sample to show all samples.
+    pub fn record(&mut self, name: &str, sample: Duration) {
+        let samples = match self.samples.get_mut(name) {
+            Some(samples) => samples,
+            None => {
+                self.order.push(name.to_owned());
+                self.samples.entry(name.to_owned()).or_default()
+            }
+        };
+        // sorting is left to `draw`, so recording a sample stays cheap.
+        samples.push(sample.as_secs_f64());
+        let x = samples.len() as f64;
+        let color = self.color(name);
+        crate::plot!(
+            target: TARGET,
+            format!("{}/samples", self.surface),
+            name,
+            x,
+            sample.as_secs_f64() * 1e6,
+            color
+        );
+        if self.drawn.map_or(true, |drawn| drawn.elapsed() >= REDRAW) {
+            self.draw();
+        }
+    }
+    /// Time a single run of `f` as sample of the benchmark `name` and return its result.
+    pub fn time<R>(&mut self, name: &str, f: impl FnOnce() -> R) -> R {
+        let start = Instant::now();
+        let result = std::hint::black_box(f());
+        self.record(name, start.elapsed());
+        result
+    }
+    /// Redraw the violins of all benchmarks.
+    pub fn draw(&mut self) {
+        self.drawn = Some(Instant::now());
+        for samples in self.samples.values_mut() {
+            samples.sort_unstable_by(f64::total_cmp);
+        }
+        v_log::clear!(self.surface.as_str());
+        let slowest = self
+            .samples
+            .values()
+            .filter_map(|samples| samples.last())
+            .fold(0.0, |max: f64, &s| max.max(s));
+        if slowest <= 0.0 {
+            return;
+        }
+        // the time grows upwards from the base line at y = HEIGHT.
+        let y = |seconds: f64| HEIGHT * (1.0 - seconds / slowest);
+        self.vlog(
+            Shape::Path {
+                start: [-SPACING / 2.0, HEIGHT],
+                segments: vec![PathSegment::Line([
+                    SPACING * (self.order.len() as f64 - 0.5),
+                    HEIGHT,
+                ])],
+            },
+            Color::Base,
+            None,
+            format_args!(""),
+        );
+        self.vlog(
+            Shape::TextBox(TextBox::new([-SPACING / 2.0, -24.0])),
+            Color::Base,
+            None,
+            format_args!("{:.2?}", Duration::from_secs_f64(slowest)),
+        );
+        for (i, name) in self.order.iter().enumerate() {
+            let samples = &self.samples[name];
+            let center = i as f64 * SPACING;
+            let color = self.color(name);
+            let (min, max) = (samples[0], samples[samples.len() - 1]);
+            let median = samples[samples.len() / 2];
+            let bandwidth = bandwidth(samples);
+            let quantiles = quantiles(samples, DENSITY_SAMPLES);
+            let density: Vec<_> = (0..=RESOLUTION)
+                .map(|k| {
+                    let t = min + (max - min) * k as f64 / RESOLUTION as f64;
+                    (t, kde(&quantiles, bandwidth, t))
+                })
+                .collect();
+            let peak = density.iter().fold(0.0, |max: f64, &(_, d)| max.max(d));
+            if peak > 0.0 && max > min {
+                let half = |d: f64| d / peak * WIDTH / 2.0;
+                let points: Vec<_> = density
+                    .iter()
+                    .map(|&(t, d)| [center + half(d), y(t)])
+                    .chain(density.iter().rev().map(|&(t, d)| [center - half(d), y(t)]))
+                    .collect();
+                self.vlog(Shape::Polygon(points), color, Some(color), format_args!(""));
+            }
+            self.vlog(
+                Shape::Path {
+                    start: [center - WIDTH / 2.0, y(median)],
+                    segments: vec![PathSegment::Line([center + WIDTH / 2.0, y(median)])],
+                },
+                Color::Base,
+                None,
+                format_args!(""),
+            );
+            self.vlog(
+                Shape::TextBox(TextBox::new([center - WIDTH / 2.0, HEIGHT + 8.0]).wrap(SPACING)),
+                color,
+                None,
+                format_args!(
+                    "{name}\nmedian {:.2?}\n{} samples",
+                    Duration::from_secs_f64(median),
+                    samples.len()
+                ),
+            );
+        }
+    }
+    fn color(&self, name: &str) -> Color {
+        let i = self.order.iter().position(|n| n == name).unwrap_or(0);
+        COLORS[i % COLORS.len()]
+    }
+    fn vlog(&self, shape: Shape, stroke: Color, fill: Option<Color>, label: std::fmt::Arguments) {
+        shape::vlog(
+            &Location {
+                target: TARGET,
+                module: "",
+                file: self.file,
+                line: self.line,
+            },
+            &self.surface,
+            shape,
+            &ShapeStyle {
+                stroke,
+                fill,
+                width: 0.0,
+            },
+            label,
+        );
+    }
+}
+
+/// The bandwidth of the kernel density estimate by Silverman's rule of thumb.
+fn bandwidth(samples: &[f64]) -> f64 {
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let deviation = (samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n).sqrt();
+    1.06 * deviation * n.powf(-0.2)
+}
+
+/// At most `n` evenly spaced quantiles of the sorted samples, including the smallest and largest one.
+fn quantiles(sorted: &[f64], n: usize) -> Cow<'_, [f64]> {
+    if sorted.len() <= n {
+        return Cow::Borrowed(sorted);
+    }
+    let last = sorted.len() - 1;
+    Cow::Owned((0..n).map(|k| sorted[k * last / (n - 1)]).collect())
+}
+
+/// The kernel density estimate with a gaussian kernel at `t`.
+fn kde(samples: &[f64], bandwidth: f64, t: f64) -> f64 {
+    if bandwidth <= 0.0 {
+        return 0.0;
+    }
+    samples
+        .iter()
+        .map(|s| (-0.5 * ((t - s) / bandwidth).powi(2)).exp())
+        .sum::<f64>()
+}
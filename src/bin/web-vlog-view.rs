@@ -0,0 +1,82 @@
+//! Serve a recorded vlog session, so it can be inspected without the program which recorded it.
+//!
+//! ```text
+//! web-vlog-view [--port PORT] [--speed SPEED] [--quiet] RECORDING
+//! ```
+//!
+//! The recording is created with `web_vlog::Builder::record_to`. It is replayed with its
+//! original timing divided by `SPEED` once a browser connects. A speed of `inf` shows all
+//! messages at once. By default the server runs on a free port.
+
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: web-vlog-view [--port PORT] [--speed SPEED] [--quiet] RECORDING";
+
+struct Args {
+    path: String,
+    port: u16,
+    speed: f64,
+    quiet: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = std::env::args().skip(1);
+    let mut path = None;
+    let mut port = 0;
+    let mut speed = 1.0;
+    let mut quiet = false;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("missing value for {name}"));
+        match arg.as_str() {
+            "-p" | "--port" => {
+                let value = value("--port")?;
+                port = value.parse().map_err(|_| format!("invalid port {value}"))?;
+            }
+            "-s" | "--speed" => {
+                let value = value("--speed")?;
+                speed = value
+                    .parse()
+                    .ok()
+                    .filter(|speed: &f64| *speed > 0.0)
+                    .ok_or_else(|| format!("invalid speed {value}"))?;
+            }
+            "-q" | "--quiet" => quiet = true,
+            "-h" | "--help" => return Err(USAGE.to_owned()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {arg}\n{USAGE}")),
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(format!("unexpected argument {arg}\n{USAGE}")),
+        }
+    }
+    Ok(Args {
+        path: path.ok_or_else(|| USAGE.to_owned())?,
+        port,
+        speed,
+        quiet,
+    })
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let port = match web_vlog::Builder::new()
+        .port(args.port)
+        .quiet(args.quiet)
+        .replay(&args.path, args.speed)
+    {
+        Ok(port) => port,
+        Err(err) => {
+            eprintln!("failed to replay {}: {err}", args.path);
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("serving {} on http://localhost:{port}/", args.path);
+    // the server and the replay run on their own threads until the process is stopped.
+    loop {
+        std::thread::park();
+    }
+}
@@ -0,0 +1,12 @@
+//! The channel from the vlogging threads to the server thread.
+//!
+//! With the `fast-channel` feature, the channel of `crossbeam-channel` is used instead of
+//! [`std::sync::mpsc`], which scales better when many threads vlog at a high rate.
+//! Both have the same API for the parts used here.
+
+#[cfg(feature = "fast-channel")]
+pub(crate) use crossbeam_channel::{
+    unbounded as channel, Receiver, RecvTimeoutError, Sender, TryRecvError,
+};
+#[cfg(not(feature = "fast-channel"))]
+pub(crate) use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError};
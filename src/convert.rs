@@ -0,0 +1,460 @@
+//! Conversion of structured text logs into [recordings](crate::recording), so historic logs
+//! can be shown in the viewer with [`replay`](crate::replay).
+//!
+//! Every line of the log is split into fields. A line with a JSON object has its keys as fields,
+//! where nested objects and arrays are flattened to `outer.inner` and `array.0`. Any other line has its
+//! `key=value` pairs as fields, which are separated by whitespace or commas. Values can be quoted.
+//!
+//! Each [`MappingRule`] turns the matching lines into a visual on its surface. A line can match
+//! several rules and lines without a matching rule are skipped. The records link to the line of the log.
+//!
+//! ```
+//! use web_vlog::convert::{LogConverter, MappingRule};
+//! use web_vlog::recording::Recorder;
+//!
+//! let log = r#"12:00:01 INFO robot moved x=1.5, y=2 t=0.1
+//! 12:00:02 INFO robot moved x=2.5, y=3 t=0.2
+//! {"event":"hit","pos":{"x":4,"y":1},"t":0.3}
+//! "#;
+//! let mut converter = LogConverter::new();
+//! converter
+//!     .rule(MappingRule::point("path", "x", "y").when("moved").time("t"))
+//!     .rule(MappingRule::point("hits", "pos.x", "pos.y").label("event").time("t"));
+//! let mut recorder = Recorder::new(Vec::new()).unwrap();
+//! let summary = converter.convert(log.as_bytes(), &mut recorder).unwrap();
+//! assert_eq!(summary.lines, 3);
+//! assert_eq!(summary.records, 3);
+//! ```
+
+use crate::{
+    json::{self, Escape, Num, Value},
+    recording::Recorder,
+    wire::{self, Message},
+};
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    time::Duration,
+};
+use v_log::{Color, PointStyle};
+
+/// The visual created by a [`MappingRule`].
+#[derive(Clone, Debug)]
+enum Visual {
+    Point { x: String, y: String },
+    Line { from: [String; 2], to: [String; 2] },
+    Message,
+}
+
+/// A rule, which turns matching log lines into visuals, see the [module documentation](self).
+#[derive(Clone, Debug)]
+pub struct MappingRule {
+    surface: String,
+    visual: Visual,
+    filter: Option<String>,
+    label: Option<String>,
+    time: Option<String>,
+    size: f64,
+    color: Color,
+    style: PointStyle,
+}
+
+impl MappingRule {
+    fn new(surface: &str, visual: Visual) -> Self {
+        Self {
+            surface: surface.to_owned(),
+            visual,
+            filter: None,
+            label: None,
+            time: None,
+            size: 5.0,
+            color: Color::Base,
+            style: PointStyle::Circle,
+        }
+    }
+    /// Draw a point at the numbers in the fields `x` and `y`.
+    pub fn point(surface: &str, x: &str, y: &str) -> Self {
+        Self::new(
+            surface,
+            Visual::Point {
+                x: x.to_owned(),
+                y: y.to_owned(),
+            },
+        )
+    }
+    /// Draw a line between the numbers in the fields `[x, y]` of `from` and `to`.
+    pub fn line(surface: &str, from: [&str; 2], to: [&str; 2]) -> Self {
+        Self::new(
+            surface,
+            Visual::Line {
+                from: from.map(str::to_owned),
+                to: to.map(str::to_owned),
+            },
+        )
+    }
+    /// Show the line as text message, or the label if one is set.
+    pub fn message(surface: &str) -> Self {
+        Self::new(surface, Visual::Message)
+    }
+    /// Only apply the rule to lines containing the text.
+    pub fn when(mut self, text: &str) -> Self {
+        self.filter = Some(text.to_owned());
+        self
+    }
+    /// Label the visual with the value of the field.
+    pub fn label(mut self, field: &str) -> Self {
+        self.label = Some(field.to_owned());
+        self
+    }
+    /// Take the time of the record in seconds from the field. By default the records are at the
+    /// start of the recording, so a replay shows them all at once.
+    pub fn time(mut self, field: &str) -> Self {
+        self.time = Some(field.to_owned());
+        self
+    }
+    /// Set the size of points and the width of lines. The default is 5.
+    pub fn size(mut self, size: f64) -> Self {
+        self.size = size;
+        self
+    }
+    /// Set the color of the visual. The default is [`Color::Base`].
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+    /// Set the style of points. The default is [`PointStyle::Circle`].
+    pub fn point_style(mut self, style: PointStyle) -> Self {
+        self.style = style;
+        self
+    }
+}
+
+/// The result of a conversion with [`LogConverter::convert`].
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct ConvertSummary {
+    /// The number of read lines.
+    pub lines: usize,
+    /// The number of written records.
+    pub records: usize,
+}
+
+/// Converts log files into recordings with a list of [`MappingRule`]s.
+#[derive(Clone, Debug, Default)]
+pub struct LogConverter {
+    rules: Vec<MappingRule>,
+}
+
+impl LogConverter {
+    /// Create a converter without rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add a rule. Every line is checked against all rules in the order they were added.
+    pub fn rule(&mut self, rule: MappingRule) -> &mut Self {
+        self.rules.push(rule);
+        self
+    }
+    /// Convert the log file at `input` into a recording at `output`.
+    pub fn convert_file(
+        &self,
+        input: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+    ) -> io::Result<ConvertSummary> {
+        let input = input.as_ref();
+        let reader = BufReader::new(File::open(input)?);
+        let mut recorder = Recorder::create(output)?;
+        let summary = self.convert_from(reader, &input.to_string_lossy(), &mut recorder)?;
+        recorder.flush()?;
+        Ok(summary)
+    }
+    /// Convert the log from `reader` and write the records to `recorder`.
+    pub fn convert<W: Write>(
+        &self,
+        reader: impl BufRead,
+        recorder: &mut Recorder<W>,
+    ) -> io::Result<ConvertSummary> {
+        self.convert_from(reader, "", recorder)
+    }
+    fn convert_from<W: Write>(
+        &self,
+        reader: impl BufRead,
+        file: &str,
+        recorder: &mut Recorder<W>,
+    ) -> io::Result<ConvertSummary> {
+        let mut encoder = wire::Encoder::new(false);
+        let mut summary = ConvertSummary::default();
+        let file: Cow<'static, str> = Cow::Owned(file.to_owned());
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            summary.lines += 1;
+            let fields = fields(&line);
+            for rule in &self.rules {
+                if rule
+                    .filter
+                    .as_ref()
+                    .map_or(false, |f| !line.contains(f.as_str()))
+                {
+                    continue;
+                }
+                let Some(body) = body(rule, &line, &fields) else {
+                    continue;
+                };
+                let time = rule
+                    .time
+                    .as_ref()
+                    .and_then(|key| number(&fields, key))
+                    .and_then(|t| Duration::try_from_secs_f64(t).ok())
+                    .unwrap_or_default();
+                let message = Message::Record {
+                    surface: rule.surface.as_str().into(),
+                    meta: wire::Meta {
+                        target: "log".into(),
+                        module: Cow::Borrowed(""),
+                        file: file.clone(),
+                        line: i as u32 + 1,
+                        time: time.as_secs_f64(),
+                        wall: 0,
+                        tid: 0,
+                        thread: "".into(),
+                    },
+                    body: body.into(),
+                };
+                if let Some(msg) = encoder.encode(&message, 1) {
+                    recorder.write_message_at(time, &msg)?;
+                    summary.records += 1;
+                }
+            }
+        }
+        Ok(summary)
+    }
+}
+
+/// The encoded body of the visual of the rule, if the line has all required fields.
+fn body(rule: &MappingRule, line: &str, fields: &[(String, String)]) -> Option<String> {
+    let label = match &rule.label {
+        Some(key) => field(fields, key)?,
+        None => "",
+    };
+    let size = Num(rule.size);
+    let mut body = match &rule.visual {
+        Visual::Point { x, y } => {
+            let (x, y) = (number(fields, x)?, number(fields, y)?);
+            format!(
+                "\"lbl\":\"{}\",\"pos\":[{x},{y},0],\"style\":\"{:?}\",\"size\":{size}",
+                Escape(label),
+                rule.style
+            )
+        }
+        Visual::Line { from, to } => {
+            let (x1, y1) = (number(fields, &from[0])?, number(fields, &from[1])?);
+            let (x2, y2) = (number(fields, &to[0])?, number(fields, &to[1])?);
+            format!(
+                "\"lbl\":\"{}\",\"pos\":[{x1},{y1},0],\"pos2\":[{x2},{y2},0],\"style\":\"Simple\",\"size\":{size}",
+                Escape(label)
+            )
+        }
+        Visual::Message => {
+            let text = if rule.label.is_some() { label } else { line };
+            format!("\"msg\":\"{}\"", Escape(text.trim()))
+        }
+    };
+    crate::write_color(&mut body, "col", &rule.color);
+    Some(body)
+}
+
+fn field<'a>(fields: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    fields
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+}
+
+/// The finite number in the field.
+fn number(fields: &[(String, String)], key: &str) -> Option<f64> {
+    field(fields, key)?
+        .parse()
+        .ok()
+        .filter(|v: &f64| v.is_finite())
+}
+
+/// Split a line into its fields, see the [module documentation](self).
+fn fields(line: &str) -> Vec<(String, String)> {
+    let mut fields = vec![];
+    let trimmed = line.trim();
+    if trimmed.starts_with('{') {
+        if let Some(value) = json::parse(trimmed) {
+            flatten(&value, &mut String::new(), &mut fields);
+            return fields;
+        }
+    }
+    // split into tokens, while keeping quoted values together.
+    let mut tokens = vec![];
+    let mut token = String::new();
+    let mut quote = None;
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => token.push(c),
+            None if (c == '"' || c == '\'') && token.ends_with('=') => quote = Some(c),
+            None if c.is_whitespace() || c == ',' => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            None => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    for token in tokens {
+        if let Some((key, value)) = token.split_once('=') {
+            if !key.is_empty() {
+                fields.push((key.to_owned(), value.to_owned()));
+            }
+        }
+    }
+    fields
+}
+
+/// Add the leaves of a JSON value as fields with their dotted path.
+fn flatten(value: &Value, path: &mut String, fields: &mut Vec<(String, String)>) {
+    let mut child = |key: &str, value: &Value, path: &mut String| {
+        let len = path.len();
+        if !path.is_empty() {
+            path.push('.');
+        }
+        path.push_str(key);
+        flatten(value, path, fields);
+        path.truncate(len);
+    };
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                child(key, value, path);
+            }
+        }
+        Value::Array(array) => {
+            for (i, value) in array.iter().enumerate() {
+                child(&i.to_string(), value, path);
+            }
+        }
+        Value::String(s) => fields.push((path.clone(), s.clone())),
+        value => fields.push((path.clone(), value.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::RecordingReader;
+
+    /// Convert the log and read the recorded messages back with their time.
+    fn convert(log: &str, rules: Vec<MappingRule>) -> Vec<(Duration, Value)> {
+        let mut converter = LogConverter::new();
+        for rule in rules {
+            converter.rule(rule);
+        }
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+        let summary = converter.convert(log.as_bytes(), &mut recorder).unwrap();
+        assert_eq!(summary.lines, log.lines().count());
+        let recording = recorder.into_inner();
+        let mut reader = RecordingReader::new(&recording[..]);
+        let mut messages = vec![];
+        while let Some((time, message)) = reader.next_message().unwrap() {
+            messages.push((time, json::parse(&message).unwrap()));
+        }
+        assert_eq!(reader.skipped(), 0);
+        assert_eq!(summary.records, messages.len());
+        messages
+    }
+
+    fn get(message: &Value, key: &str) -> String {
+        message.get(key).map_or(String::new(), Value::to_string)
+    }
+
+    #[test]
+    fn key_value_pairs() {
+        let log = "12:00:01 INFO moved x=1.5 y=-2 t=0.25\n12:00:02 INFO moved x=2,y=3e2,t=1\n";
+        let messages = convert(log, vec![MappingRule::point("path", "x", "y").time("t")]);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].0, Duration::from_millis(250));
+        assert_eq!(get(&messages[0].1, "surf"), r#""path""#);
+        assert_eq!(get(&messages[0].1, "pos"), "[1.5,-2,0]");
+        assert_eq!(messages[1].0, Duration::from_secs(1));
+        assert_eq!(get(&messages[1].1, "pos"), "[2,300,0]");
+        let meta = messages[1].1.get("meta").unwrap();
+        assert_eq!(get(meta, "line"), "2");
+        assert_eq!(get(meta, "target"), r#""log""#);
+    }
+
+    #[test]
+    fn non_finite_sizes_stay_valid_json() {
+        let messages = convert(
+            "x=1 y=2",
+            vec![
+                MappingRule::point("path", "x", "y").size(f64::NAN),
+                MappingRule::line("path", ["x", "y"], ["y", "x"]).size(f64::INFINITY),
+            ],
+        );
+        assert_eq!(messages.len(), 2);
+        assert_eq!(get(&messages[0].1, "size"), "null");
+        assert_eq!(get(&messages[1].1, "size"), "null");
+    }
+
+    #[test]
+    fn quoted_values() {
+        let log = r#"hit name="left arm" kind='soft, fast' x=1 y=2"#;
+        let messages = convert(
+            log,
+            vec![
+                MappingRule::point("hits", "x", "y").label("name"),
+                MappingRule::message("kinds").label("kind"),
+            ],
+        );
+        assert_eq!(messages.len(), 2);
+        assert_eq!(get(&messages[0].1, "lbl"), r#""left arm""#);
+        assert_eq!(get(&messages[1].1, "msg"), r#""soft, fast""#);
+        assert_eq!(messages[0].0, Duration::ZERO);
+    }
+
+    #[test]
+    fn json_objects() {
+        let log = r#"{"event":"hit","pos":{"x":4,"y":1},"path":[[0,0],[2,3.5]],"t":0.5}"#;
+        let messages = convert(
+            log,
+            vec![
+                MappingRule::point("hits", "pos.x", "pos.y")
+                    .label("event")
+                    .time("t"),
+                MappingRule::line("paths", ["path.0.0", "path.0.1"], ["path.1.0", "path.1.1"]),
+            ],
+        );
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].0, Duration::from_millis(500));
+        assert_eq!(get(&messages[0].1, "pos"), "[4,1,0]");
+        assert_eq!(get(&messages[0].1, "lbl"), r#""hit""#);
+        assert_eq!(get(&messages[1].1, "pos"), "[0,0,0]");
+        assert_eq!(get(&messages[1].1, "pos2"), "[2,3.5,0]");
+    }
+
+    #[test]
+    fn plain_lines() {
+        let log = "  starting the \"robot\"  \nx=1 y=2\n";
+        let messages = convert(log, vec![MappingRule::message("log").when("robot")]);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(get(&messages[0].1, "msg"), r#""starting the \"robot\"""#);
+    }
+
+    #[test]
+    fn lines_without_the_fields_are_skipped() {
+        let log = "x=1\nx=1 y=nan\nx=1 y=inf\ny=2\n{\"x\":1,\"y\":\"two\"}\n{broken x=1 y=2\n";
+        let messages = convert(log, vec![MappingRule::point("path", "x", "y")]);
+        // only the broken JSON line falls back to the key value pairs.
+        assert_eq!(messages.len(), 1);
+        assert_eq!(get(&messages[0].1, "pos"), "[1,2,0]");
+    }
+}
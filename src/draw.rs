@@ -0,0 +1,244 @@
+//! Drawing functions, which take the points and vectors of math libraries, see [`VlogPoint`].
+
+use crate::{
+    json::{Num, Pos},
+    shape::{self, Location, PathSegment, Shape, ShapeStyle},
+    Origin, VLOGGER,
+};
+use std::borrow::Cow;
+use v_log::{Color, PointStyle};
+
+/// The target of the visuals drawn with the functions of this module.
+const TARGET: &str = "draw";
+
+/// A point or vector, which can be passed to the drawing functions like [`draw_point`] directly.
+///
+/// It is implemented for arrays and tuples of `f32` and `f64` and, behind the features of the same
+/// name, for the 2D and 3D points and vectors of `mint`, `nalgebra` and `glam`.
+/// 2D types have a z coordinate of 0. Surfaces are 2D, so the functions drawing shapes ignore z.
+///
+/// ```
+/// use web_vlog::VlogPoint;
+///
+/// struct Wheel {
+///     x: f32,
+///     y: f32,
+/// }
+///
+/// impl VlogPoint for Wheel {
+///     fn xyz(&self) -> [f64; 3] {
+///         [self.x.into(), self.y.into(), 0.0]
+///     }
+/// }
+///
+/// web_vlog::draw_point("robot", Wheel { x: 1.0, y: 2.0 }, 5.0, v_log::Color::Base);
+/// ```
+pub trait VlogPoint {
+    /// The coordinates `[x, y, z]`.
+    fn xyz(&self) -> [f64; 3];
+    /// The coordinates `[x, y]`.
+    fn xy(&self) -> [f64; 2] {
+        let [x, y, _] = self.xyz();
+        [x, y]
+    }
+}
+
+impl<P: VlogPoint + ?Sized> VlogPoint for &P {
+    fn xyz(&self) -> [f64; 3] {
+        (**self).xyz()
+    }
+}
+
+/// Implement [`VlogPoint`] for types with `x`, `y` and optionally `z` fields.
+#[cfg(any(feature = "mint", feature = "nalgebra", feature = "glam"))]
+macro_rules! impl_fields {
+    ($($t:ty),* => x, y) => {
+        $(impl VlogPoint for $t {
+            fn xyz(&self) -> [f64; 3] {
+                [f64::from(self.x), f64::from(self.y), 0.0]
+            }
+        })*
+    };
+    ($($t:ty),* => x, y, z) => {
+        $(impl VlogPoint for $t {
+            fn xyz(&self) -> [f64; 3] {
+                [f64::from(self.x), f64::from(self.y), f64::from(self.z)]
+            }
+        })*
+    };
+}
+
+/// Implement [`VlogPoint`] for arrays and tuples of the float type.
+macro_rules! impl_float {
+    ($($t:ty),*) => {
+        $(impl VlogPoint for [$t; 2] {
+            fn xyz(&self) -> [f64; 3] {
+                [f64::from(self[0]), f64::from(self[1]), 0.0]
+            }
+        }
+        impl VlogPoint for [$t; 3] {
+            fn xyz(&self) -> [f64; 3] {
+                self.map(f64::from)
+            }
+        }
+        impl VlogPoint for ($t, $t) {
+            fn xyz(&self) -> [f64; 3] {
+                [f64::from(self.0), f64::from(self.1), 0.0]
+            }
+        }
+        impl VlogPoint for ($t, $t, $t) {
+            fn xyz(&self) -> [f64; 3] {
+                [f64::from(self.0), f64::from(self.1), f64::from(self.2)]
+            }
+        })*
+    };
+}
+
+impl_float!(f32, f64);
+
+#[cfg(feature = "mint")]
+impl_fields!(
+    mint::Point2<f32>, mint::Point2<f64>, mint::Vector2<f32>, mint::Vector2<f64> => x, y
+);
+#[cfg(feature = "mint")]
+impl_fields!(
+    mint::Point3<f32>, mint::Point3<f64>, mint::Vector3<f32>, mint::Vector3<f64> => x, y, z
+);
+#[cfg(feature = "nalgebra")]
+impl_fields!(
+    nalgebra::Point2<f32>, nalgebra::Point2<f64>, nalgebra::Vector2<f32>, nalgebra::Vector2<f64> => x, y
+);
+#[cfg(feature = "nalgebra")]
+impl_fields!(
+    nalgebra::Point3<f32>, nalgebra::Point3<f64>, nalgebra::Vector3<f32>, nalgebra::Vector3<f64> => x, y, z
+);
+#[cfg(feature = "glam")]
+impl_fields!(glam::Vec2, glam::DVec2 => x, y);
+#[cfg(feature = "glam")]
+impl_fields!(glam::Vec3, glam::Vec3A, glam::DVec3 => x, y, z);
+
+/// The location of the caller of a drawing function.
+#[track_caller]
+fn location() -> Location<'static> {
+    let caller = std::panic::Location::caller();
+    Location {
+        target: TARGET,
+        module: "",
+        file: caller.file(),
+        line: caller.line(),
+    }
+}
+
+fn stroke(color: Color) -> ShapeStyle {
+    ShapeStyle {
+        stroke: color,
+        fill: None,
+        width: 0.0,
+    }
+}
+
+/// Draw a circular point like [`v_log::point!`] with the `size` in pixels.
+///
+/// The visuals of the drawing functions have the target `draw` and link to the line of the call.
+#[track_caller]
+pub fn draw_point(surface: &str, position: impl VlogPoint, size: f64, color: Color) {
+    let caller = std::panic::Location::caller();
+    let vlogger = VLOGGER.read().unwrap();
+    let Some(vlogger) = &*vlogger else {
+        return;
+    };
+    if !vlogger.accepts(TARGET, surface) || !vlogger.admit(TARGET, surface, &color, false) {
+        return;
+    }
+    let body = format!(
+        "\"lbl\":\"\",\"pos\":{},\"style\":\"{:?}\",\"size\":{}",
+        Pos(position.xyz()),
+        PointStyle::Circle,
+        Num(size)
+    );
+    let origin = Origin {
+        target: TARGET,
+        module: Cow::Borrowed(""),
+        file: Cow::Borrowed(caller.file()),
+        line: caller.line(),
+    };
+    vlogger.emit(origin, surface, &color, false, body);
+}
+
+/// Draw a line from `from` to `to`, which scales with the zoom like the lines of [`v_log`].
+#[track_caller]
+pub fn draw_line(surface: &str, from: impl VlogPoint, to: impl VlogPoint, color: Color) {
+    let shape = Shape::Path {
+        start: from.xy(),
+        segments: vec![PathSegment::Line(to.xy())],
+    };
+    shape::vlog(
+        &location(),
+        surface,
+        shape,
+        &stroke(color),
+        format_args!(""),
+    );
+}
+
+/// Draw an open polyline through the points.
+///
+/// ```
+/// let path = [(0.0, 0.0), (10.0, 5.0), (20.0, 0.0)];
+/// web_vlog::draw_polyline("planner", path, v_log::Color::Healthy);
+/// ```
+#[track_caller]
+pub fn draw_polyline<P: VlogPoint>(
+    surface: &str,
+    points: impl IntoIterator<Item = P>,
+    color: Color,
+) {
+    let mut points = points.into_iter().map(|p| p.xy());
+    let Some(start) = points.next() else {
+        return;
+    };
+    let shape = Shape::Path {
+        start,
+        segments: points.map(PathSegment::Line).collect(),
+    };
+    shape::vlog(
+        &location(),
+        surface,
+        shape,
+        &stroke(color),
+        format_args!(""),
+    );
+}
+
+/// Draw a closed polygon through the points with an optional fill.
+#[track_caller]
+pub fn draw_polygon<P: VlogPoint>(
+    surface: &str,
+    points: impl IntoIterator<Item = P>,
+    color: Color,
+    fill: Option<Color>,
+) {
+    let shape = Shape::Polygon(points.into_iter().map(|p| p.xy()).collect());
+    let style = ShapeStyle {
+        fill,
+        ..stroke(color)
+    };
+    shape::vlog(&location(), surface, shape, &style, format_args!(""));
+}
+
+/// Draw a circle around the center.
+#[track_caller]
+pub fn draw_circle(surface: &str, center: impl VlogPoint, radius: f64, color: Color) {
+    let shape = Shape::Ellipse {
+        center: center.xy(),
+        radii: [radius; 2],
+        rotation: 0.0,
+    };
+    shape::vlog(
+        &location(),
+        surface,
+        shape,
+        &stroke(color),
+        format_args!(""),
+    );
+}
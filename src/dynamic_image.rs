@@ -0,0 +1,71 @@
+//! Vlogging of the images of the `image` crate, see [`image`](crate::image()).
+
+use crate::{
+    shape::{self, ImageData, Location, Shape, ShapeStyle},
+    VLOGGER,
+};
+use image::{DynamicImage, ImageFormat};
+use std::io::Cursor;
+use v_log::Color;
+
+/// The target of the vlogged images.
+const TARGET: &str = "image";
+
+/// Vlog an image with its top left corner at `(x, y)`, where every pixel is `scale` units large.
+///
+/// The image is encoded as PNG, so e.g. the intermediate frames of a computer vision pipeline can be
+/// shown inline. The pixel `(i, j)` covers the units from `(x + i * scale, y + j * scale)`, so points
+/// and lines vlogged on the same surface in these units are drawn on top of the image.
+/// Images with a color type, which PNG doesn't support, are converted to 8 bit RGBA first.
+/// The target of the image is `image` and it links to the line of the call.
+///
+/// ```
+/// use image::{DynamicImage, Rgb, RgbImage};
+///
+/// let frame = RgbImage::from_fn(64, 48, |x, y| Rgb([(x * 4) as u8, (y * 5) as u8, 128]));
+/// web_vlog::image("camera", &DynamicImage::ImageRgb8(frame), 0.0, 0.0, 2.0);
+/// v_log::point!("camera", [40.0, 30.0], 5.0, Error, "feature");
+/// ```
+#[track_caller]
+pub fn image(surface: &str, image: &DynamicImage, x: f64, y: f64, scale: f64) {
+    // encoding is expensive, so it is skipped for images, which are filtered anyway.
+    let accepted = VLOGGER
+        .read()
+        .unwrap()
+        .as_ref()
+        .map_or(false, |vlogger| vlogger.accepts(TARGET, surface));
+    if !accepted {
+        return;
+    }
+    let (width, height) = (image.width(), image.height());
+    let mut png = Vec::new();
+    let data = match image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png) {
+        Ok(()) => ImageData::Png(png),
+        Err(_) => ImageData::Rgba {
+            width,
+            height,
+            pixels: image.to_rgba8().into_raw(),
+        },
+    };
+    let caller = std::panic::Location::caller();
+    shape::vlog(
+        &Location {
+            target: TARGET,
+            module: "",
+            file: caller.file(),
+            line: caller.line(),
+        },
+        surface,
+        Shape::Image {
+            min: [x, y],
+            size: [width as f64 * scale, height as f64 * scale],
+            data,
+        },
+        &ShapeStyle {
+            stroke: Color::Base,
+            fill: None,
+            width: 0.0,
+        },
+        format_args!(""),
+    );
+}
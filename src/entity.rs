@@ -0,0 +1,41 @@
+//! Stable identities of visuals, which are replaced instead of accumulated.
+
+use crate::{json::Escape, scoped::scoped_ref};
+use std::{cell::RefCell, fmt::Write as _, time::Duration};
+
+thread_local! {
+    static ENTITY: RefCell<Option<(String, Duration)>> = const { RefCell::new(None) };
+}
+
+/// Let all visuals vlogged by the current thread inside of `f` belong to the entity with the id.
+///
+/// A visual of an entity replaces the previous visual of the same entity on the surface,
+/// so moving agents can be vlogged without clearing the surface every frame.
+/// If `tween` is not zero, the viewer moves the new visual from the position of the previous one
+/// over this duration. If multiple visuals are vlogged for one entity, only the last one is kept.
+///
+/// ```
+/// use std::time::Duration;
+/// use v_log::point;
+///
+/// for step in 0..10 {
+///     web_vlog::with_entity("agent 3", Duration::from_millis(100), || {
+///         point!("crowd", [step as f64 * 10.0, 20.0], 5.0, Base);
+///     });
+/// }
+/// ```
+pub fn with_entity<R>(id: &str, tween: Duration, f: impl FnOnce() -> R) -> R {
+    scoped_ref(&ENTITY, (id.to_owned(), tween), f)
+}
+
+/// Append the entity of the current thread to the body of a visual.
+pub(crate) fn write_entity(body: &mut String) {
+    ENTITY.with(|e| {
+        if let Some((id, tween)) = &*e.borrow() {
+            write!(body, ",\"id\":\"{}\"", Escape(id)).unwrap();
+            if !tween.is_zero() {
+                write!(body, ",\"tw\":{}", tween.as_millis()).unwrap();
+            }
+        }
+    });
+}
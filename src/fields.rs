@@ -0,0 +1,120 @@
+//! Structured key-value fields attached to records.
+
+use crate::json::Escape;
+use std::{cell::RefCell, fmt::Write as _};
+
+/// The value of a structured field, see [`with_fields`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldValue {
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    /// Non-finite floats are sent as `null`.
+    Float(f64),
+    Str(String),
+}
+
+macro_rules! impl_from {
+    ($variant:ident($target:ty): $($t:ty),*) => {
+        $(impl From<$t> for FieldValue {
+            fn from(value: $t) -> Self {
+                Self::$variant(value as $target)
+            }
+        })*
+    };
+}
+impl_from!(Int(i64): i8, i16, i32, i64, isize);
+impl_from!(UInt(u64): u8, u16, u32, u64, usize);
+impl_from!(Float(f64): f32, f64);
+
+impl From<bool> for FieldValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+impl From<&str> for FieldValue {
+    fn from(value: &str) -> Self {
+        Self::Str(value.to_owned())
+    }
+}
+impl From<String> for FieldValue {
+    fn from(value: String) -> Self {
+        Self::Str(value)
+    }
+}
+
+thread_local! {
+    /// The fields of all active [`with_fields`] scopes of this thread, outermost first.
+    static FIELDS: RefCell<Vec<(String, FieldValue)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Attach structured fields to all records vlogged by the current thread inside of `f`.
+///
+/// The fields are shown in the tooltips of the visuals and messages in the viewer.
+/// Scopes can be nested, in which case the fields of all active scopes are attached.
+/// Fields of inner scopes take precedence over outer fields with the same key.
+///
+/// ```
+/// use v_log::message;
+///
+/// for iteration in 0..3 {
+///     let loss = 0.1 / (iteration + 1) as f64;
+///     web_vlog::with_fields(&[("iteration", iteration.into()), ("loss", loss.into())], || {
+///         message!("training", "finished iteration");
+///     });
+/// }
+/// ```
+pub fn with_fields<R>(fields: &[(&str, FieldValue)], f: impl FnOnce() -> R) -> R {
+    /// Removes the fields of the scope again, even if `f` panics.
+    struct Guard(usize);
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            FIELDS.with(|fields| fields.borrow_mut().truncate(self.0));
+        }
+    }
+    let _guard = FIELDS.with(|stack| {
+        let mut stack = stack.borrow_mut();
+        let guard = Guard(stack.len());
+        stack.extend(fields.iter().map(|(k, v)| ((*k).to_owned(), v.clone())));
+        guard
+    });
+    f()
+}
+
+/// Append the active fields of the current thread as `,"fields":{...}` to the record body.
+pub(crate) fn write_fields(body: &mut String) {
+    FIELDS.with(|stack| {
+        let stack = stack.borrow();
+        if stack.is_empty() {
+            return;
+        }
+        body.push_str(",\"fields\":{");
+        let mut first = true;
+        for (i, (key, value)) in stack.iter().enumerate() {
+            // skip fields, which are shadowed by an inner scope.
+            if stack[i + 1..].iter().any(|(k, _)| k == key) {
+                continue;
+            }
+            if !first {
+                body.push(',');
+            }
+            first = false;
+            write!(body, "\"{}\":", Escape(key)).unwrap();
+            write_value(body, value);
+        }
+        body.push('}');
+    });
+}
+
+/// Append a value as json.
+pub(crate) fn write_value(body: &mut String, value: &FieldValue) {
+    match value {
+        FieldValue::Bool(b) => write!(body, "{b}"),
+        FieldValue::Int(n) => write!(body, "{n}"),
+        FieldValue::UInt(n) => write!(body, "{n}"),
+        FieldValue::Float(x) if x.is_finite() => write!(body, "{x}"),
+        FieldValue::Float(_) => write!(body, "null"),
+        FieldValue::Str(s) => write!(body, "\"{}\"", Escape(s)),
+    }
+    .unwrap();
+}
@@ -0,0 +1,252 @@
+//! Matching of target filters, see [`Builder::add_target`](crate::Builder::add_target).
+//!
+//! The filters are kept sorted when they are added, so checking a record is a binary search
+//! instead of a scan over all filters.
+
+/// The target and surface whitelists and blacklists.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Targets {
+    allowed: Prefixes,
+    denied: Prefixes,
+    /// Surfaces are matched by their full name. Both lists are sorted.
+    allowed_surfaces: Vec<String>,
+    denied_surfaces: Vec<String>,
+}
+
+/// A set of target filters, which match all targets starting with them.
+#[derive(Clone, Debug, Default)]
+struct Prefixes {
+    /// The sorted prefixes, where no prefix starts with another one.
+    ///
+    /// The only prefix, which can match a target, is then the greatest one not after the target.
+    /// Any prefix between it and the target would have to start with it.
+    sorted: Vec<String>,
+    /// The glob patterns, which are matched one by one.
+    #[cfg(feature = "glob")]
+    globs: Vec<String>,
+}
+
+impl Prefixes {
+    fn is_empty(&self) -> bool {
+        #[cfg(feature = "glob")]
+        if !self.globs.is_empty() {
+            return false;
+        }
+        self.sorted.is_empty()
+    }
+    fn insert(&mut self, filter: &str) {
+        #[cfg(feature = "glob")]
+        if filter.contains(['*', '?']) {
+            if !self.globs.iter().any(|g| g == filter) {
+                self.globs.push(filter.to_owned());
+            }
+            return;
+        }
+        // a filter starting with an existing one doesn't match any additional targets.
+        if self.matches_prefix(filter) {
+            return;
+        }
+        // and existing filters starting with the new one become redundant.
+        self.sorted.retain(|p| !p.starts_with(filter));
+        let i = self.sorted.partition_point(|p| p.as_str() < filter);
+        self.sorted.insert(i, filter.to_owned());
+    }
+    fn matches_prefix(&self, target: &str) -> bool {
+        let i = self.sorted.partition_point(|p| p.as_str() <= target);
+        i > 0 && target.starts_with(self.sorted[i - 1].as_str())
+    }
+    fn matches(&self, target: &str) -> bool {
+        #[cfg(feature = "glob")]
+        if self
+            .globs
+            .iter()
+            .any(|g| glob(g.as_bytes(), target.as_bytes()))
+        {
+            return true;
+        }
+        self.matches_prefix(target)
+    }
+}
+
+/// Insert the name into the sorted list, if it isn't in there yet.
+fn insert_sorted(list: &mut Vec<String>, name: &str) {
+    if let Err(i) = list.binary_search_by(|s| s.as_str().cmp(name)) {
+        list.insert(i, name.to_owned());
+    }
+}
+
+fn contains_sorted(list: &[String], name: &str) -> bool {
+    list.binary_search_by(|s| s.as_str().cmp(name)).is_ok()
+}
+
+impl Targets {
+    /// Add the comma separated filters in the syntax of `RUST_VLOG`.
+    pub fn parse(&mut self, filters: &str) {
+        for filter in filters.split(',') {
+            self.push(filter);
+        }
+    }
+    /// Add a single filter in the syntax of `RUST_VLOG`:
+    /// - `target` allows the target, `-target` denies it.
+    /// - `surface=name` allows the surface, `-surface=name` denies it.
+    /// - `target=off` denies the target like in `env_logger`. Other values, e.g. log levels,
+    ///   allow the target, as visuals have no levels.
+    pub fn push(&mut self, filter: &str) {
+        let filter = filter.trim();
+        let (deny, filter) = match filter.strip_prefix('-') {
+            Some(filter) => (true, filter.trim_start()),
+            None => (false, filter),
+        };
+        let (name, value) = match filter.split_once('=') {
+            Some((name, value)) => (name.trim_end(), Some(value.trim_start())),
+            None => (filter, None),
+        };
+        match (name, value) {
+            ("", _) | ("surface", Some("")) => {}
+            ("surface", Some(surface)) if deny => self.deny_surface(surface),
+            ("surface", Some(surface)) => self.allow_surface(surface),
+            (target, Some(value)) if deny || value.eq_ignore_ascii_case("off") => self.deny(target),
+            (target, _) if deny => self.deny(target),
+            (target, _) => self.allow(target),
+        }
+    }
+    pub fn allow(&mut self, target: &str) {
+        self.allowed.insert(target);
+    }
+    pub fn deny(&mut self, target: &str) {
+        self.denied.insert(target);
+    }
+    pub fn allow_surface(&mut self, surface: &str) {
+        insert_sorted(&mut self.allowed_surfaces, surface);
+    }
+    pub fn deny_surface(&mut self, surface: &str) {
+        insert_sorted(&mut self.denied_surfaces, surface);
+    }
+    /// Check if the target passes the whitelist and is not in the blacklist.
+    ///
+    /// Plain filters match all targets starting with them. With the `glob` feature, filters containing
+    /// `*` or `?` are glob patterns, which match the target or any of its submodules.
+    pub fn enabled(&self, target: &str) -> bool {
+        (self.allowed.is_empty() || self.allowed.matches(target)) && !self.denied.matches(target)
+    }
+    /// Check if the surface passes the surface whitelist and is not in the surface blacklist.
+    pub fn surface_enabled(&self, surface: &str) -> bool {
+        (self.allowed_surfaces.is_empty() || contains_sorted(&self.allowed_surfaces, surface))
+            && !contains_sorted(&self.denied_surfaces, surface)
+    }
+}
+
+/// Match a glob pattern against the start of a target, which has to end at a module boundary.
+///
+/// `?` matches a single character and `*` any number of characters within a module path segment.
+/// `**` matches any number of characters across `::`.
+#[cfg(feature = "glob")]
+fn glob(pattern: &[u8], target: &[u8]) -> bool {
+    match pattern {
+        [] => target.is_empty() || target.starts_with(b"::"),
+        [b'*', b'*', rest @ ..] => (0..=target.len()).any(|i| glob(rest, &target[i..])),
+        [b'*', rest @ ..] => {
+            let segment = target
+                .iter()
+                .position(|&c| c == b':')
+                .unwrap_or(target.len());
+            (0..=segment).any(|i| glob(rest, &target[i..]))
+        }
+        [b'?', rest @ ..] => matches!(target, [c, tail @ ..] if *c != b':' && glob(rest, tail)),
+        [p, rest @ ..] => matches!(target, [c, tail @ ..] if c == p && glob(rest, tail)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(filters: &str) -> Targets {
+        let mut targets = Targets::default();
+        targets.parse(filters);
+        targets
+    }
+
+    #[test]
+    fn empty_filters_allow_everything() {
+        for filters in ["", " ", ",", " , ,", "-", "surface=", "-surface=", "=on"] {
+            let targets = parse(filters);
+            assert!(targets.enabled("my_crate::module"), "{filters:?}");
+            assert!(targets.surface_enabled("physics"), "{filters:?}");
+        }
+    }
+
+    #[test]
+    fn targets_are_prefixes() {
+        let targets = parse("my_crate, other::module");
+        assert!(targets.enabled("my_crate"));
+        assert!(targets.enabled("my_crate::noisy"));
+        assert!(targets.enabled("other::module::sub"));
+        assert!(!targets.enabled("other"));
+        assert!(!targets.enabled("third"));
+    }
+
+    #[test]
+    fn overlapping_prefixes() {
+        let targets = parse("my_crate::a::b,my_crate::a,my_crate::ab,my,zzz");
+        assert_eq!(targets.allowed.sorted, ["my", "zzz"]);
+        assert!(targets.enabled("my_crate::a::b::c"));
+        assert!(targets.enabled("zzz"));
+        assert!(!targets.enabled("m"));
+        assert!(!targets.enabled("other"));
+        let targets = parse("b::x,a,c,b");
+        assert_eq!(targets.allowed.sorted, ["a", "b", "c"]);
+        assert!(targets.enabled("b::y"));
+        assert!(targets.enabled("bb"));
+        assert!(!targets.enabled("d"));
+    }
+
+    #[test]
+    fn negation_denies_targets() {
+        let targets = parse("my_crate,-my_crate::noisy");
+        assert!(targets.enabled("my_crate::quiet"));
+        assert!(!targets.enabled("my_crate::noisy"));
+        assert!(!targets.enabled("my_crate::noisy::sub"));
+        assert!(!targets.enabled("other"));
+        // only deny filters allow all other targets.
+        let targets = parse("- my_crate::noisy");
+        assert!(targets.enabled("other"));
+        assert!(!targets.enabled("my_crate::noisy"));
+    }
+
+    #[test]
+    fn env_logger_values() {
+        let targets = parse("my_crate=debug,my_crate::noisy=off,other=OFF");
+        assert!(targets.enabled("my_crate::quiet"));
+        assert!(!targets.enabled("my_crate::noisy"));
+        assert!(!targets.enabled("other"));
+        assert!(!targets.enabled("third"));
+    }
+
+    #[test]
+    fn surface_selectors() {
+        let targets = parse("my_crate,surface=physics,surface = loading");
+        assert!(targets.surface_enabled("physics"));
+        assert!(targets.surface_enabled("loading"));
+        assert!(!targets.surface_enabled("physics2"));
+        // surface and target filters are independent.
+        assert!(targets.enabled("my_crate"));
+        assert!(!targets.enabled("other"));
+        let targets = parse("-surface=debug");
+        assert!(!targets.surface_enabled("debug"));
+        assert!(targets.surface_enabled("physics"));
+        assert!(targets.enabled("other"));
+    }
+
+    #[cfg(feature = "glob")]
+    #[test]
+    fn glob_patterns() {
+        let targets = parse("engine::*::collision,-engine::**::debug");
+        assert!(targets.enabled("engine::physics::collision"));
+        assert!(targets.enabled("engine::physics::collision::broad_phase"));
+        assert!(!targets.enabled("engine::a::b::collision"));
+        assert!(!targets.enabled("engine::physics::collisions"));
+        assert!(!targets.enabled("engine::physics::collision::debug"));
+        assert!(parse("eng?ne").enabled("engine"));
+    }
+}
@@ -0,0 +1,293 @@
+//! Forwarding of the messages to an upstream server, see [`Builder::forward_to`](crate::Builder::forward_to).
+//!
+//! Instead of serving the viewer, the vlogger connects out to the `/ingest` endpoint of the upstream
+//! server with a websocket and sends every message in the basic wire format as a text frame.
+//! The upstream connection counts as a connected client, so [`wait_for_connection`](crate::wait_for_connection)
+//! and [`Builder::lazy`](crate::Builder::lazy) work like with a local viewer. While the upstream server
+//! can't be reached, the messages wait in the queue and the connection is retried every [`RETRY`].
+
+use crate::{
+    channel::{Receiver, RecvTimeoutError, TryRecvError},
+    ingest, snapshot, stats, view,
+    wire::{self, Message},
+    Packet, Projection, CLIENTS, QUEUED, WAIT,
+};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The time between the attempts to connect to the upstream server.
+const RETRY: Duration = Duration::from_secs(1);
+/// The time to wait for the TCP connection and for the response to the websocket handshake.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The connection settings of the forwarding vlogger.
+pub(crate) struct Forwarder {
+    /// The `host:port` of the upstream server.
+    pub upstream: String,
+    /// The name of this process, which namespaces its surfaces on the upstream server.
+    pub process: String,
+    pub projections: HashMap<String, Projection>,
+    pub nodelay: bool,
+    pub send_buffer: Option<usize>,
+}
+
+/// Counts the upstream connection as connected client while it is alive.
+struct Connected;
+
+impl Connected {
+    fn new() -> Self {
+        let mut guard = WAIT.0.lock().unwrap();
+        *guard += 1;
+        CLIENTS.store(*guard, Ordering::Relaxed);
+        WAIT.1.notify_all();
+        Self
+    }
+}
+
+impl Drop for Connected {
+    fn drop(&mut self) {
+        let mut guard = WAIT.0.lock().unwrap();
+        *guard -= 1;
+        CLIENTS.store(*guard, Ordering::Relaxed);
+        WAIT.1.notify_all();
+    }
+}
+
+/// How a connection to the upstream server ended without an error.
+enum End {
+    /// The vlogger was flushed or shut down.
+    Flushed,
+    /// All senders are gone, so nothing is left to forward.
+    Disconnected,
+}
+
+impl Forwarder {
+    /// Forward the messages until the vlogger is shut down.
+    pub(crate) fn run(&self, rx: Receiver<Packet>, stop: &AtomicBool) {
+        // the messages taken from the queue, which couldn't be sent yet.
+        let mut held = VecDeque::new();
+        let mut warned = false;
+        while !stop.load(Ordering::SeqCst) {
+            let stream = match self.connect() {
+                Ok(stream) => stream,
+                Err(err) => {
+                    if !warned {
+                        chatter!(
+                            warn,
+                            "failed to connect to the upstream vlogging server {}: {err}, retrying",
+                            self.upstream
+                        );
+                        warned = true;
+                    }
+                    std::thread::sleep(RETRY);
+                    continue;
+                }
+            };
+            chatter!(
+                info,
+                "forwarding to the upstream vlogging server {}",
+                self.upstream
+            );
+            warned = false;
+            let connected = Connected::new();
+            let end = self.forward(stream, &rx, &mut held);
+            drop(connected);
+            match end {
+                Ok(End::Disconnected) => break,
+                Ok(End::Flushed) => {
+                    // don't reconnect right away, so a waiting flush sees the disconnect.
+                    match rx.recv_timeout(RETRY) {
+                        Ok(packet) => held.push_back(received(packet)),
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                Err(err) => {
+                    chatter!(
+                        warn,
+                        "lost the connection to the upstream vlogging server {}: {err}",
+                        self.upstream
+                    );
+                    std::thread::sleep(RETRY);
+                }
+            }
+        }
+    }
+    /// Connect to the ingest endpoint and complete the websocket handshake.
+    fn connect(&self) -> io::Result<TcpStream> {
+        let mut last_err = None;
+        let mut stream = None;
+        for addr in self.upstream.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+                Ok(s) => {
+                    stream = Some(s);
+                    break;
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+        let Some(mut stream) = stream else {
+            return Err(last_err.unwrap_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "no address for the upstream server",
+                )
+            }));
+        };
+        // an upstream server, which accepts the connection but doesn't answer, must not block forever.
+        stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+        crate::configure_stream(&stream, self.nodelay, self.send_buffer)?;
+        let key = BASE64_STANDARD.encode(mask().to_be_bytes().repeat(4));
+        let query = if self.process.is_empty() {
+            String::new()
+        } else {
+            format!("?process={}", ingest::percent_encode(&self.process))
+        };
+        write!(
+            stream,
+            "GET /ingest{query} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {key}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            self.upstream
+        )?;
+        let mut reader = BufReader::new(&stream);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if !line.starts_with("HTTP/1.1 101") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the server refused the websocket: {}", line.trim_end()),
+            ));
+        }
+        while line != "\r\n" {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        stream.set_read_timeout(None)?;
+        Ok(stream)
+    }
+    /// Send the messages from the queue until the connection fails or the vlogger is flushed.
+    ///
+    /// If the connection fails, the messages, which haven't been flushed yet, are put back into `held`
+    /// and sent again after reconnecting. So a message might reach the upstream server twice, but none is lost.
+    fn forward(
+        &self,
+        stream: TcpStream,
+        rx: &Receiver<Packet>,
+        held: &mut VecDeque<Message>,
+    ) -> io::Result<End> {
+        let mut unflushed = Vec::new();
+        let end = self.write_messages(BufWriter::new(stream), rx, held, &mut unflushed);
+        if end.is_err() {
+            for message in unflushed.into_iter().rev() {
+                held.push_front(message);
+            }
+        }
+        end
+    }
+    /// Write the messages to the connection. The messages written since the last flush are kept in `unflushed`.
+    fn write_messages(
+        &self,
+        mut writer: BufWriter<TcpStream>,
+        rx: &Receiver<Packet>,
+        held: &mut VecDeque<Message>,
+        unflushed: &mut Vec<Message>,
+    ) -> io::Result<End> {
+        for (surface, projection) in &self.projections {
+            write_masked(&mut writer, &wire::view(surface, *projection))?;
+        }
+        for setting in view::settings() {
+            write_masked(&mut writer, &setting)?;
+        }
+        // the upstream server interns the strings for its own clients.
+        let mut encoder = wire::Encoder::new(false);
+        loop {
+            let message = match held.pop_front() {
+                Some(message) => message,
+                None => match rx.try_recv() {
+                    Ok(packet) => received(packet),
+                    Err(TryRecvError::Empty) => {
+                        writer.flush()?;
+                        unflushed.clear();
+                        match rx.recv() {
+                            Ok(packet) => received(packet),
+                            Err(_) => return Ok(End::Disconnected),
+                        }
+                    }
+                    Err(TryRecvError::Disconnected) => return Ok(End::Disconnected),
+                },
+            };
+            if let Message::Flush = message {
+                unflushed.push(message);
+                writer.write_all(&[0x88, 0x80])?;
+                writer.write_all(&mask().to_be_bytes())?;
+                writer.flush()?;
+                unflushed.clear();
+                return Ok(End::Flushed);
+            }
+            let Some(msg) = encoder.encode(&message, 1) else {
+                continue;
+            };
+            unflushed.push(message);
+            write_masked(&mut writer, &msg)?;
+        }
+    }
+}
+
+/// The name of the executable and the id of this process, e.g. `robot-4711`.
+pub(crate) fn default_process_name() -> String {
+    let exe = std::env::current_exe().ok();
+    let name = exe
+        .as_deref()
+        .and_then(|exe| exe.file_stem())
+        .map_or("process".into(), |name| name.to_string_lossy());
+    format!("{name}-{}", std::process::id())
+}
+
+/// Take a message out of its packet and count it as received.
+fn received(packet: Packet) -> Message {
+    QUEUED.fetch_sub(1, Ordering::Relaxed);
+    stats::add_received();
+    #[cfg(feature = "self-profile")]
+    stats::add_queue_wait(packet.queued.elapsed());
+    if packet.retain {
+        snapshot::retain(&packet.message);
+    }
+    packet.message
+}
+
+/// A masking key for a frame. Websocket clients have to mask their frames, but the key only has to
+/// be unpredictable for browsers, so the time is good enough.
+fn mask() -> u32 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    nanos.wrapping_mul(0x9E37_79B9) | 1
+}
+
+/// Write a masked text frame without flushing.
+fn write_masked(writer: &mut impl Write, msg: &str) -> io::Result<()> {
+    stats::add_sent(msg.len());
+    let len = msg.len();
+    let mut frame = Vec::with_capacity(len + 14);
+    frame.push(0x81);
+    if len < 126 {
+        frame.push(0x80 | len as u8);
+    } else if len <= u16::MAX as usize {
+        frame.push(0x80 | 126);
+        frame.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        frame.push(0x80 | 127);
+        frame.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+    let mask = mask().to_be_bytes();
+    frame.extend_from_slice(&mask);
+    frame.extend(msg.bytes().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    writer.write_all(&frame)
+}
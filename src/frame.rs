@@ -0,0 +1,35 @@
+//! Frame markers, which let the viewer step through the history of the surfaces.
+
+use crate::{wire, VLOGGER};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The number of the current frame.
+static FRAME: AtomicU64 = AtomicU64::new(0);
+
+/// Mark the end of a frame, e.g. a tick of a simulation, and return the number of the next frame.
+///
+/// Once frames are marked, the viewer keeps the visuals of the last 1000 frames when
+/// a surface is cleared and shows a timeline to scrub backwards and forwards through them.
+/// The keys `,` and `.` step one frame back and forth. Text messages and plots are not part of the history.
+///
+/// ```
+/// use v_log::macros::*;
+///
+/// for tick in 0..3 {
+///     clear!("simulation");
+///     point!("simulation", [tick as f64, 0.0], 5.0, Base);
+///     web_vlog::frame();
+/// }
+/// ```
+pub fn frame() -> u64 {
+    let frame = FRAME.fetch_add(1, Ordering::Relaxed) + 1;
+    if let Some(vlogger) = &*VLOGGER.read().unwrap() {
+        vlogger.send(wire::Message::Setting(wire::frame(frame)));
+    }
+    frame
+}
+
+/// Check if a frame has been marked, so the viewer keeps the history of the surfaces.
+pub(crate) fn marked() -> bool {
+    FRAME.load(Ordering::Relaxed) > 0
+}
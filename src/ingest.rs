@@ -0,0 +1,341 @@
+//! Messages of the wire format posted by external producers to `/ingest`, see [`Builder::ingest`](crate::Builder::ingest).
+//!
+//! The body of the request is a single message or a JSON array of messages in the basic wire format,
+//! i.e. without the interned strings of the compact encoding. Records are objects with a `"surf"`
+//! and the fields of their visual, e.g. `{"surf":"map","pos":[1,2,0],"style":"Circle","size":5}`.
+//! Their `"meta"` is optional and its target defaults to [`TARGET`], so the target filters apply as usual.
+//! The time of the records is the time they were received. Samples of line charts have an `"xy"`
+//! and a `"series"` and a surface is cleared with `{"clear":1,"surf":name}`. Of the objects without
+//! a surface, only the settings of a surface, i.e. `{"view":..}`, `{"grid":..}` and `{"conf":..}`,
+//! are passed on to the viewer with their known fields. Everything else, e.g. the template of the
+//! editor links, could change the viewer beyond the surfaces and is dropped.
+//!
+//! Several producers drawing onto the same surfaces would overwrite each other. A producer, which
+//! names its process with the query `?process=name`, gets its surfaces prefixed with `name/`,
+//! so the surfaces of every process are grouped under their own prefix in the viewer.
+//! The surfaces referenced by the settings of the viewer, e.g. `{"grid":surface}`, are prefixed as well.
+
+use crate::{
+    json::{self, Escape, Value},
+    wire::{Message, Meta},
+};
+use std::{
+    borrow::Cow,
+    fmt::Write as _,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The target of the ingested records without a target in their meta.
+pub(crate) const TARGET: &str = "ingest";
+
+/// The maximal size of the body of a request in bytes.
+pub(crate) const MAX_BODY: usize = 16 << 20;
+
+/// The fields of a record, which are not part of the visual.
+const RESERVED: [&str; 4] = ["surf", "meta", "rep", "dict"];
+
+/// The settings of the viewer, which can be ingested, with their fields besides the surface.
+const SETTINGS: [(&str, &[&str]); 3] = [
+    ("view", &["proj"]),
+    ("grid", &["sp", "ext"]),
+    ("conf", &["yup", "rect", "fit"]),
+];
+
+/// Parse the body of a request into messages. A single invalid message rejects the whole body,
+/// while the objects, which aren't records, samples or settings of a surface, are dropped.
+///
+/// `time` is the monotonic time of the vlogger in seconds, which is used for all records.
+/// The surfaces are prefixed with the name of the `process`, if it has one.
+pub(crate) fn parse(body: &str, time: f64, process: Option<&str>) -> Result<Vec<Message>, String> {
+    let value = json::parse(body.trim()).ok_or("the body is not valid JSON")?;
+    let wall = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    let values = match value {
+        Value::Array(values) => values,
+        value => vec![value],
+    };
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut value)| {
+            if let Some(process) = process {
+                namespace(&mut value, process);
+            }
+            message(value, time, wall).map_err(|err| format!("message {i}: {err}"))
+        })
+        .filter_map(Result::transpose)
+        .collect()
+}
+
+/// Prefix the surfaces of the message with the name of the process.
+fn namespace(value: &mut Value, process: &str) {
+    let Value::Object(fields) = value else {
+        return;
+    };
+    for (key, value) in fields {
+        if key != "surf" && !SETTINGS.iter().any(|(setting, _)| key == setting) {
+            continue;
+        }
+        if let Value::String(surface) = value {
+            surface.insert(0, '/');
+            surface.insert_str(0, process);
+        }
+    }
+}
+
+/// The value of a parameter in the query of a url, e.g. `process` in `/ingest?process=robot`.
+pub(crate) fn query_param(query: &str, name: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        (key == name).then(|| percent_decode(value))
+    })
+}
+
+fn percent_decode(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        rest = tail;
+        match b {
+            b'+' => bytes.push(b' '),
+            b'%' => match rest
+                .get(..2)
+                .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())
+            {
+                Some(byte) => {
+                    bytes.push(byte);
+                    rest = &rest[2..];
+                }
+                None => bytes.push(b'%'),
+            },
+            b => bytes.push(b),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Encode a value for the query of a url.
+pub(crate) fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            write!(encoded, "%{b:02X}").unwrap();
+        }
+    }
+    encoded
+}
+
+fn message(value: Value, time: f64, wall: u128) -> Result<Option<Message>, String> {
+    let Value::Object(fields) = value else {
+        return Err("not a JSON object".to_owned());
+    };
+    let string = |key: &str| match fields.iter().find(|(k, _)| k == key) {
+        Some((_, Value::String(s))) => Ok(Some(s.clone())),
+        Some(_) => Err(format!("\"{key}\" is not a string")),
+        None => Ok(None),
+    };
+    if fields.iter().any(|(k, _)| k == "dict" || k == "hello") {
+        return Err("interned strings and the protocol handshake are not supported".to_owned());
+    }
+    let Some(surface) = string("surf")? else {
+        return Ok(setting(&fields));
+    };
+    if fields.iter().any(|(k, _)| k == "clear") {
+        return Ok(Some(Message::Clear(surface)));
+    }
+    if let Some(series) = string("series")? {
+        if !fields.iter().any(|(k, _)| k == "xy") {
+            return Err("a sample needs an \"xy\"".to_owned());
+        }
+        let body = body(&fields, &["series"]);
+        return Ok(Some(Message::Sample {
+            surface: surface.into(),
+            series,
+            body: body.into(),
+        }));
+    }
+    let meta = fields.iter().find(|(k, _)| k == "meta").map(|(_, v)| v);
+    let text = |key: &str| match meta.and_then(|m| m.get(key)) {
+        Some(Value::String(s)) => s.clone(),
+        _ => String::new(),
+    };
+    let number = |key: &str| match meta.and_then(|m| m.get(key)) {
+        Some(Value::Number(n)) if *n >= 0.0 => *n,
+        _ => 0.0,
+    };
+    let target = text("target");
+    let meta = Meta {
+        target: if target.is_empty() {
+            TARGET.into()
+        } else {
+            target.into()
+        },
+        module: Cow::Owned(text("module")),
+        file: Cow::Owned(text("file")),
+        line: number("line") as u32,
+        time,
+        wall,
+        tid: number("tid") as u64,
+        thread: Arc::from(text("thread")),
+    };
+    let body = body(&fields, &[]);
+    if body.is_empty() {
+        return Err("a record needs the fields of a visual".to_owned());
+    }
+    Ok(Some(Message::Record {
+        surface: surface.into(),
+        meta,
+        body: body.into(),
+    }))
+}
+
+/// The setting of a surface with only its known fields, or `None` for any other object.
+fn setting(fields: &[(String, Value)]) -> Option<Message> {
+    let (key, surface, known) =
+        SETTINGS
+            .iter()
+            .find_map(|(key, known)| match fields.iter().find(|(k, _)| k == key) {
+                Some((_, Value::String(surface))) => Some((key, surface, known)),
+                _ => None,
+            })?;
+    let mut json = format!("{{\"{key}\":\"{}\"", Escape(surface));
+    for (k, value) in fields {
+        if known.contains(&k.as_str()) {
+            write!(json, ",\"{k}\":{value}").unwrap();
+        }
+    }
+    json.push('}');
+    Some(Message::Setting(json))
+}
+
+/// The fields of the visual without the enclosing braces.
+fn body(fields: &[(String, Value)], skip: &[&str]) -> String {
+    let mut body = String::new();
+    for (key, value) in fields {
+        if RESERVED.contains(&key.as_str()) || skip.contains(&key.as_str()) {
+            continue;
+        }
+        if !body.is_empty() {
+            body.push(',');
+        }
+        write!(body, "\"{}\":{value}", Escape(key)).unwrap();
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The json of the settings among the messages of the body.
+    fn settings(body: &str) -> Vec<String> {
+        settings_of(&parse(body, 0.0, None).unwrap())
+    }
+
+    fn settings_of(messages: &[Message]) -> Vec<String> {
+        messages
+            .iter()
+            .filter_map(|message| match message {
+                Message::Setting(json) => Some(json.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn only_surface_settings_pass() {
+        assert_eq!(
+            settings(r#"{"grid":"map","sp":10,"ext":[0,0,1,1],"editor":"javascript:alert(1)"}"#),
+            [r#"{"grid":"map","sp":10,"ext":[0,0,1,1]}"#]
+        );
+        assert_eq!(
+            settings(
+                r#"[{"view":"3d","proj":"ortho","meta":{"file":"x"}},{"conf":"map","yup":true,"fit":true}]"#
+            ),
+            [
+                r#"{"view":"3d","proj":"ortho"}"#,
+                r#"{"conf":"map","yup":true,"fit":true}"#
+            ]
+        );
+        for body in [
+            r#"{"editor":"javascript:alert(1)"}"#,
+            r#"{"title":"<script>"}"#,
+            r#"{"frame":3}"#,
+            r#"{"insp":"x","val":1}"#,
+            r#"{"grid":1}"#,
+        ] {
+            assert!(parse(body, 0.0, None).unwrap().is_empty(), "{body}");
+        }
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("robot"), "robot");
+        assert_eq!(percent_decode("my%20robot"), "my robot");
+        assert_eq!(percent_decode("my+robot"), "my robot");
+        // a literal plus is encoded.
+        assert_eq!(percent_decode("a%2Bb"), "a+b");
+        assert_eq!(percent_decode("%c3%a4%E2%82%AC"), "ä€");
+        assert_eq!(percent_decode("ä€"), "ä€");
+        // invalid escapes are kept as they are.
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%+"), "% ");
+        // invalid UTF-8 is replaced.
+        assert_eq!(percent_decode("a%FFb"), "a\u{FFFD}b");
+        assert_eq!(percent_decode("%C3"), "\u{FFFD}");
+        for value in ["my robot/arm", "a+b&c=d", "ä€ %"] {
+            assert_eq!(percent_decode(&percent_encode(value)), value);
+        }
+    }
+
+    #[test]
+    fn query_params() {
+        let query = "process=my+robot&empty=&flag&x=%2F";
+        assert_eq!(query_param(query, "process").as_deref(), Some("my robot"));
+        assert_eq!(query_param(query, "empty").as_deref(), Some(""));
+        assert_eq!(query_param(query, "flag").as_deref(), Some(""));
+        assert_eq!(query_param(query, "x").as_deref(), Some("/"));
+        assert_eq!(query_param(query, "missing"), None);
+        assert_eq!(query_param("", "process"), None);
+    }
+
+    #[test]
+    fn surfaces_are_namespaced() {
+        let body = r#"[
+            {"surf":"map","pos":[1,2,0]},
+            {"surf":"plot","series":"a","xy":[1,2]},
+            {"clear":1,"surf":"map"},
+            {"view":"map","proj":"ortho"},
+            {"grid":"map","sp":1},
+            {"conf":"map","fit":true}
+        ]"#;
+        let messages = parse(body, 0.0, Some("robot")).unwrap();
+        assert_eq!(messages.len(), 6);
+        let surfaces: Vec<_> = messages.iter().filter_map(Message::surface).collect();
+        assert_eq!(surfaces, ["robot/map", "robot/plot", "robot/map"]);
+        assert!(matches!(&messages[1], Message::Sample { series, .. } if series == "a"));
+        assert_eq!(
+            settings_of(&messages),
+            [
+                r#"{"view":"robot/map","proj":"ortho"}"#,
+                r#"{"grid":"robot/map","sp":1}"#,
+                r#"{"conf":"robot/map","fit":true}"#,
+            ]
+        );
+        // the other fields are kept as they are.
+        let mut value = json::parse(r#"{"surf":"map","lbl":"map","grid":1}"#).unwrap();
+        namespace(&mut value, "robot");
+        assert_eq!(
+            value.to_string(),
+            r#"{"surf":"robot/map","lbl":"map","grid":1}"#
+        );
+        // without a process, nothing is prefixed.
+        let messages = parse(body, 0.0, None).unwrap();
+        assert_eq!(messages[0].surface(), Some("map"));
+    }
+}
@@ -0,0 +1,54 @@
+//! A live view of structured values in a side panel of the viewer, see [`inspect`].
+
+use crate::{json::Escape, Origin, VLOGGER};
+use serde::Serialize;
+use std::borrow::Cow;
+use v_log::Color;
+
+/// The target of the inspected values.
+const TARGET: &str = "inspect";
+
+/// Show the `value` as collapsible tree named `name` in the inspector panel of the viewer.
+///
+/// The value is serialized to JSON and replaces the previous value with the same name on the surface,
+/// so calling this repeatedly, e.g. once per tick, shows the live state of a variable. The expanded
+/// nodes of the tree stay expanded when the value is updated. The values are grouped by their surface
+/// in the panel and stay when the surface is cleared. Values, which fail to serialize, are logged as error.
+/// The target of the values is `inspect` and they link to the line of the call.
+///
+/// ```
+/// #[derive(serde::Serialize)]
+/// struct Joint {
+///     angle: f64,
+///     limits: [f64; 2],
+/// }
+///
+/// let arm = vec![Joint { angle: 0.5, limits: [-1.0, 1.0] }, Joint { angle: -0.2, limits: [-2.0, 2.0] }];
+/// web_vlog::inspect("robot", "arm", &arm);
+/// ```
+#[track_caller]
+pub fn inspect<T: Serialize + ?Sized>(surface: &str, name: &str, value: &T) {
+    let caller = std::panic::Location::caller();
+    let vlogger = VLOGGER.read().unwrap();
+    let Some(vlogger) = &*vlogger else {
+        return;
+    };
+    if !vlogger.accepts(TARGET, surface) || !vlogger.admit(TARGET, surface, &Color::Base, false) {
+        return;
+    }
+    let value = match serde_json::to_string(value) {
+        Ok(value) => value,
+        Err(err) => {
+            log::error!("failed to serialize the inspected value {name}: {err}");
+            return;
+        }
+    };
+    let body = format!("\"insp\":\"{}\",\"val\":{value}", Escape(name));
+    let origin = Origin {
+        target: TARGET,
+        module: Cow::Borrowed(""),
+        file: Cow::Borrowed(caller.file()),
+        line: caller.line(),
+    };
+    vlogger.emit(origin, surface, &Color::Base, false, body);
+}
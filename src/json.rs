@@ -0,0 +1,346 @@
+//! A minimal JSON parser for the messages produced by this crate and the escaping of JSON strings.
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+/// The number of decimal places of [`Num`], or `u32::MAX` for the full precision.
+static PRECISION: AtomicU32 = AtomicU32::new(u32::MAX);
+
+/// Set the decimal places of [`Num`], see [`Builder::float_precision`](crate::Builder::float_precision).
+pub(crate) fn set_precision(digits: Option<u32>) {
+    PRECISION.store(digits.unwrap_or(u32::MAX), Ordering::Relaxed);
+}
+
+/// Escapes a string for use inside of a JSON string literal when formatted.
+///
+/// Quotes, backslashes and control characters are escaped. All other characters,
+/// including non-ASCII characters, are written as is, which is valid in UTF-8 encoded JSON.
+pub(crate) struct Escape<'a>(pub &'a str);
+
+impl fmt::Display for Escape<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rest = self.0;
+        // write unescaped runs at once, as escaping is rarely needed.
+        while let Some(i) = rest.find(|c: char| c < ' ' || c == '"' || c == '\\') {
+            f.write_str(&rest[..i])?;
+            match rest.as_bytes()[i] {
+                b'"' => f.write_str("\\\""),
+                b'\\' => f.write_str("\\\\"),
+                b'\n' => f.write_str("\\n"),
+                b'\r' => f.write_str("\\r"),
+                b'\t' => f.write_str("\\t"),
+                b => write!(f, "\\u{b:04x}"),
+            }?;
+            rest = &rest[i + 1..];
+        }
+        f.write_str(rest)
+    }
+}
+
+/// Formats a coordinate as JSON number with the precision set by [`set_precision`].
+///
+/// NaN and the infinities have no JSON representation, so they are written as `null`.
+/// Otherwise the message couldn't be parsed by the client and the strings interned by it would be lost.
+pub(crate) struct Num(pub f64);
+
+impl fmt::Display for Num {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_num(f, self.0, PRECISION.load(Ordering::Relaxed))
+    }
+}
+
+/// Formats a point as JSON array of [`Num`]s.
+pub(crate) struct Pos<const N: usize>(pub [f64; N]);
+
+impl<const N: usize> fmt::Display for Pos<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = PRECISION.load(Ordering::Relaxed);
+        f.write_str("[")?;
+        for (i, &value) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write_num(f, value, digits)?;
+        }
+        f.write_str("]")
+    }
+}
+
+/// Write the number rounded to `digits` decimal places in the shortest form, which reads back
+/// as the rounded number, so there are no trailing zeros.
+fn write_num(f: &mut fmt::Formatter<'_>, value: f64, digits: u32) -> fmt::Result {
+    if !value.is_finite() {
+        return f.write_str("null");
+    }
+    if digits < 16 {
+        let scale = 10f64.powi(digits as i32);
+        let scaled = (value * scale).round();
+        // the division is exact enough, as long as the scaled number is an exact integer.
+        // Adding zero turns `-0` into `0`.
+        if scaled.abs() < (1u64 << 53) as f64 {
+            return write!(f, "{}", scaled / scale + 0.0);
+        }
+    }
+    write!(f, "{value}")
+}
+
+/// A parsed JSON value. Objects keep the order of their keys.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Get the value of a key, if this is an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Object(object) => object.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    /// Write the value as compact JSON.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => f.write_str("null"),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Number(n) => write_num(f, *n, u32::MAX),
+            Self::String(s) => write!(f, "\"{}\"", Escape(s)),
+            Self::Array(array) => {
+                f.write_str("[")?;
+                for (i, v) in array.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{v}")?;
+                }
+                f.write_str("]")
+            }
+            Self::Object(object) => {
+                f.write_str("{")?;
+                for (i, (k, v)) in object.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "\"{}\":{v}", Escape(k))?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+/// Parse a complete JSON document. Returns `None` if the input is not valid JSON.
+pub(crate) fn parse(input: &str) -> Option<Value> {
+    let mut parser = Parser {
+        bytes: input.as_bytes(),
+        pos: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    (parser.pos == parser.bytes.len()).then_some(value)
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+    fn next(&mut self) -> Option<u8> {
+        let b = self.peek()?;
+        self.pos += 1;
+        Some(b)
+    }
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+    fn expect(&mut self, literal: &str) -> Option<()> {
+        let end = self.pos + literal.len();
+        (self.bytes.get(self.pos..end)? == literal.as_bytes()).then(|| self.pos = end)
+    }
+    fn value(&mut self) -> Option<Value> {
+        self.skip_whitespace();
+        match self.peek()? {
+            b'n' => self.expect("null").map(|_| Value::Null),
+            b't' => self.expect("true").map(|_| Value::Bool(true)),
+            b'f' => self.expect("false").map(|_| Value::Bool(false)),
+            b'"' => self.string().map(Value::String),
+            b'[' => {
+                self.pos += 1;
+                let mut array = vec![];
+                self.skip_whitespace();
+                if self.peek()? == b']' {
+                    self.pos += 1;
+                    return Some(Value::Array(array));
+                }
+                loop {
+                    array.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.next()? {
+                        b',' => {}
+                        b']' => return Some(Value::Array(array)),
+                        _ => return None,
+                    }
+                }
+            }
+            b'{' => {
+                self.pos += 1;
+                let mut object = vec![];
+                self.skip_whitespace();
+                if self.peek()? == b'}' {
+                    self.pos += 1;
+                    return Some(Value::Object(object));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    if self.next()? != b':' {
+                        return None;
+                    }
+                    object.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.next()? {
+                        b',' => {}
+                        b'}' => return Some(Value::Object(object)),
+                        _ => return None,
+                    }
+                }
+            }
+            _ => self.number().map(Value::Number),
+        }
+    }
+    fn number(&mut self) -> Option<f64> {
+        let start = self.pos;
+        while matches!(
+            self.peek(),
+            Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+        ) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.pos])
+            .ok()?
+            .parse()
+            .ok()
+    }
+    fn hex4(&mut self) -> Option<u32> {
+        let hex = std::str::from_utf8(self.bytes.get(self.pos..self.pos + 4)?).ok()?;
+        self.pos += 4;
+        u32::from_str_radix(hex, 16).ok()
+    }
+    fn string(&mut self) -> Option<String> {
+        if self.next()? != b'"' {
+            return None;
+        }
+        let mut out = String::new();
+        loop {
+            // copy unescaped runs at once to keep multibyte characters intact.
+            let start = self.pos;
+            while !matches!(self.peek()?, b'"' | b'\\') {
+                self.pos += 1;
+            }
+            out.push_str(std::str::from_utf8(&self.bytes[start..self.pos]).ok()?);
+            if self.next()? == b'"' {
+                return Some(out);
+            }
+            match self.next()? {
+                b'"' => out.push('"'),
+                b'\\' => out.push('\\'),
+                b'/' => out.push('/'),
+                b'b' => out.push('\u{8}'),
+                b'f' => out.push('\u{c}'),
+                b'n' => out.push('\n'),
+                b'r' => out.push('\r'),
+                b't' => out.push('\t'),
+                b'u' => {
+                    let mut code = self.hex4()?;
+                    if (0xD800..0xDC00).contains(&code) {
+                        // surrogate pair
+                        self.expect("\\u")?;
+                        let low = self.hex4()?;
+                        if !(0xDC00..0xE000).contains(&low) {
+                            return None;
+                        }
+                        code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                    }
+                    out.push(char::from_u32(code)?);
+                }
+                _ => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(s: &str) {
+        let escaped = format!("\"{}\"", Escape(s));
+        assert_eq!(
+            parse(&escaped),
+            Some(Value::String(s.to_owned())),
+            "{escaped}"
+        );
+    }
+
+    #[test]
+    fn escape_tricky_strings() {
+        assert_eq!(Escape("a\"b\\c").to_string(), r#"a\"b\\c"#);
+        assert_eq!(Escape("\n\r\t\0\u{1b}").to_string(), r"\n\r\t\u0000\u001b");
+        assert_eq!(Escape("äöü 😀").to_string(), "äöü 😀");
+        for s in [
+            "",
+            "plain",
+            "it's",
+            "quote \" and backslash \\",
+            "trailing backslash \\",
+            "controls \u{1}\u{8}\u{c}\u{1f}\u{7f}",
+            "emoji 😀 and ümlauts",
+            "C:\\path\\to\\file.rs",
+            "\u{2028}line separator",
+        ] {
+            roundtrip(s);
+        }
+    }
+
+    #[test]
+    fn rounded_numbers() {
+        struct Rounded(f64, u32);
+        impl fmt::Display for Rounded {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write_num(f, self.0, self.1)
+            }
+        }
+        let num = |value, digits| Rounded(value, digits).to_string();
+        assert_eq!(num(400.00000000000006, u32::MAX), "400.00000000000006");
+        assert_eq!(num(400.00000000000006, 3), "400");
+        assert_eq!(num(0.1 + 0.2, 2), "0.3");
+        assert_eq!(num(1.23456, 2), "1.23");
+        assert_eq!(num(-1.23456, 4), "-1.2346");
+        assert_eq!(num(-0.0001, 2), "0");
+        assert_eq!(num(1e300, 2), 1e300.to_string());
+        assert_eq!(num(12345.678, 0), "12346");
+        for digits in [2, u32::MAX] {
+            assert_eq!(num(f64::NAN, digits), "null");
+            assert_eq!(num(f64::INFINITY, digits), "null");
+            assert_eq!(num(f64::NEG_INFINITY, digits), "null");
+        }
+        assert_eq!(Value::Number(f64::NAN).to_string(), "null");
+    }
+}
@@ -0,0 +1,52 @@
+//! Draw order of visuals on a surface.
+
+use crate::scoped::scoped;
+use std::{cell::Cell, collections::HashMap};
+
+thread_local! {
+    static LAYER: Cell<Option<i32>> = const { Cell::new(None) };
+}
+
+/// Draw all visuals vlogged by the current thread inside of `f` on the layer.
+///
+/// Visuals on higher layers are drawn on top of visuals on lower layers, independent of
+/// the order they arrive in. Within a layer, later visuals are drawn on top. The default layer is 0.
+/// This overrides the defaults set with [`Builder::target_layer`](crate::Builder::target_layer)
+/// and [`Builder::surface_layer`](crate::Builder::surface_layer).
+///
+/// ```
+/// use v_log::point;
+///
+/// // the background grid stays behind the data, even if it is drawn later.
+/// web_vlog::with_layer(-1, || {
+///     point!("map", [1.0, 2.0], 5.0, Base);
+/// });
+/// ```
+pub fn with_layer<R>(layer: i32, f: impl FnOnce() -> R) -> R {
+    scoped(&LAYER, layer, f)
+}
+
+/// The default layers of the targets and surfaces.
+#[derive(Clone, Default)]
+pub(crate) struct Layers {
+    /// Target prefixes with their layer, sorted by decreasing length, so the most specific prefix matches first.
+    pub targets: Vec<(String, i32)>,
+    pub surfaces: HashMap<String, i32>,
+}
+
+impl Layers {
+    /// The layer of a visual on the current thread. [`with_layer`] takes precedence over
+    /// the default of the target, which takes precedence over the default of the surface.
+    pub fn of(&self, target: &str, surface: &str) -> i32 {
+        LAYER
+            .with(Cell::get)
+            .or_else(|| {
+                self.targets
+                    .iter()
+                    .find(|(prefix, _)| target.starts_with(prefix.as_str()))
+                    .map(|(_, layer)| *layer)
+            })
+            .or_else(|| self.surfaces.get(surface).copied())
+            .unwrap_or(0)
+    }
+}
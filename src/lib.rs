@@ -4,11 +4,14 @@
 //! high datarates. This setup doesn't have the performance of a direct GPU renderer, but
 //! it has decent performance at very little compiletime and runtime cost for the vlogging
 //! process itself.
+//! To check the throughput on a machine, run [`self_test`] with a viewer connected.
+//! The benchmarks in `benches/` measure the cost of single records and of the websocket send loop.
 //!
 //! The webpage uses SVG to render the vlogging surfaces and provides clickable links
 //! to open the relevant lines in VSCode.
 //!
-//! This crate depends on `sha1` and `base64` due to the websocket handshake, which requires both.
+//! This crate depends on `sha1` and `base64` due to the websocket handshake, which requires both,
+//! and on `log` for its own diagnostics. Everything else is behind optional features.
 //! **Nothing is encrypted, as this is a debug utility, which should not be shipped in production code.**
 //!
 //! # Usage
@@ -47,6 +50,17 @@
 //! $ RUST_VLOG=custom_target_2 cargo run
 //! ```
 //! the output is "Second message" and "Third message". This is due to the filter being a prefix filter.
+//! Filters starting with `-` deny all targets which start with the rest of the filter, even if
+//! they are allowed by another filter. Running with `RUST_VLOG=custom_target_2,-custom_target_2::submodule`
+//! only produces the message "Second message". If there are only deny filters, all other targets are allowed.
+//! Filters of the form `surface=<name>` allow only the named surfaces instead of filtering by target,
+//! so `RUST_VLOG=surface=loading` only vlogs the surface "loading" and `RUST_VLOG=-surface=loading`
+//! vlogs all surfaces except "loading". Surface and target filters apply independently.
+//! For `env_logger` users, `target=off` denies the target like `-target`, while other values
+//! like `target=debug` allow it, as visuals have no levels. So `RUST_VLOG=my_crate,my_crate::noisy=off,surface=physics`
+//! only vlogs the surface "physics" of `my_crate` without `my_crate::noisy`.
+//! With the `glob` feature, filters containing `*` or `?` are glob patterns instead of prefixes,
+//! e.g. `RUST_VLOG=engine::*::collision`, see [`Builder::add_target`].
 //! Executing the executable directly with an environment variable, and executing using
 //! `cargo run` both work. This way it is also possible to use filtering in tests using `RUST_VLOG=... cargo test`.
 //! Tests in a library should only use a vlogger implementation as dev-dependency.
@@ -58,36 +72,371 @@
 //! // choosing "custom_target_1" as an allowed prefix for the vlogger.
 //! web_vlog::Builder::new().port(1234).add_target("custom_target_1").init().unwrap();
 //! ```
+//!
+//! Other processes, e.g. Python scripts or shell tools, can draw onto the same surfaces by posting
+//! messages of the wire format to the `/ingest` path of the server, once it is enabled with [`Builder::ingest`].
+//! The body is a single JSON message or an array of messages with the `Content-Type: application/json`
+//! and the ingested records go through the target filters with the target `ingest`.
+//! With `/ingest?process=name` the surfaces of the producer are prefixed with `name/`, so several processes
+//! don't draw over each other.
+//! ```cmd
+//! $ curl -H 'Content-Type: application/json' -d '{"surf":"map","pos":[1,2,0],"style":"Circle","size":5}' localhost:1234/ingest
+//! ```
 
 use base64::{prelude::BASE64_STANDARD, Engine};
+use channel::{channel, Receiver, RecvTimeoutError, Sender};
+use json::{Num, Pos};
 use sha1::Digest;
 use std::{
+    borrow::Cow,
+    cell::Cell,
+    collections::{HashMap, HashSet},
     fmt::{self, Write as _},
     io::{self, prelude::*, BufReader, BufWriter},
     net::*,
     sync::{
-        atomic::AtomicBool,
-        mpsc::{channel, Receiver, Sender},
-        Condvar, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, RwLock,
     },
-    time::Duration,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use v_log::{Color, Record, SetVLoggerError, VLog, Visual};
+use wire::Message;
+
+/// Log the own output of the vlogger, unless it is silenced with [`Builder::quiet`].
+macro_rules! chatter {
+    ($level:ident, $($arg:tt)+) => {
+        if !$crate::QUIET.load(std::sync::atomic::Ordering::Relaxed) {
+            log::$level!($($arg)+);
+        }
+    };
+}
+
+mod audit;
+pub mod bench;
+mod channel;
+pub mod convert;
+mod draw;
+#[cfg(feature = "image")]
+mod dynamic_image;
+mod entity;
+mod fields;
+mod filter;
+mod forward;
+mod frame;
+mod ingest;
+#[cfg(feature = "inspect")]
+mod inspect;
+mod json;
+mod layer;
+mod line;
+#[cfg(feature = "log-bridge")]
+mod log_bridge;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+mod multi;
+mod opacity;
+mod page;
+mod panic_hook;
+#[cfg(feature = "plotters")]
+mod plotters;
+mod priority;
+mod queue;
+#[cfg(feature = "png")]
+mod raster;
+mod rate;
+pub mod recording;
+mod retention;
+mod scoped;
+mod self_test;
+mod session;
+pub mod shape;
+mod snapshot;
+mod stats;
+mod surface;
+mod terminal;
+mod thread;
+#[cfg(feature = "tracing")]
+mod tracing_layer;
+mod ttl;
+mod validate;
+mod view;
+mod wire;
+pub use audit::{connections, AuthResult, Connection};
+pub use draw::{draw_circle, draw_line, draw_point, draw_polygon, draw_polyline, VlogPoint};
+#[cfg(feature = "image")]
+pub use dynamic_image::image;
+pub use entity::with_entity;
+pub use fields::{with_fields, FieldValue};
+pub use frame::frame;
+#[cfg(feature = "inspect")]
+pub use inspect::inspect;
+pub use layer::with_layer;
+pub use line::{with_arrowheads, with_dash, with_vertex_markers, Arrowheads};
+#[cfg(feature = "log-bridge")]
+pub use log_bridge::LogBridge;
+pub use multi::MultiVLogger;
+pub use opacity::with_opacity;
+pub use panic_hook::install_panic_hook;
+#[cfg(feature = "plotters")]
+pub use plotters::VLogBackend;
+pub use priority::{with_priority, Priority};
+pub use rate::{RateLimit, RateLimitKey};
+pub use retention::{retention, set_retention, RetentionPolicy};
+pub use self_test::{self_test, SelfTestReport};
+pub use stats::{stats, Stats};
+pub use surface::{Projection, Surface};
+pub use thread::ThreadPriority;
+#[cfg(feature = "tracing")]
+pub use tracing_layer::VLogLayer;
+pub use ttl::with_ttl;
+/// The color type of `v_log`, which is used by the macros of this crate.
+#[doc(hidden)]
+pub use v_log::Color as __Color;
+pub use validate::{validation_summary, Problem, ProblemKind, ValidationRules, ValidationSummary};
+pub use view::{configure_surface, surface_grid, SurfaceConfig};
 
-static WAIT: (Mutex<bool>, Condvar) = (Mutex::new(false), Condvar::new());
+/// The number of connected websocket clients.
+static WAIT: (Mutex<usize>, Condvar) = (Mutex::new(0), Condvar::new());
+/// The number of connected websocket clients for the lock-free check of [`Builder::lazy`].
+static CLIENTS: AtomicUsize = AtomicUsize::new(0);
 static INIT: AtomicBool = AtomicBool::new(false);
+/// A copy of the global vlogger for the visuals of the [`shape`] module.
+static VLOGGER: RwLock<Option<WebVLogger>> = RwLock::new(None);
+/// The number of messages in the queue to the server thread.
+static QUEUED: AtomicUsize = AtomicUsize::new(0);
+/// Silences the own log output of the vlogger except errors, see [`Builder::quiet`].
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// The thread id and name. Computed once per thread, as they never change.
+    static THREAD_META: (u64, Arc<str>) = {
+        let thread = std::thread::current();
+        // `ThreadId::as_u64` is unstable, so the number is taken from the debug output `ThreadId(N)`.
+        let id = format!("{:?}", thread.id());
+        let id = id.trim_start_matches("ThreadId(").trim_end_matches(')');
+        (id.parse().unwrap_or(0), thread.name().unwrap_or("").into())
+    };
+    /// Reusable buffer for the formatted text of the records of this thread.
+    static SCRATCH: std::cell::RefCell<String> = const { std::cell::RefCell::new(String::new()) };
+    /// The length of the last message body of this thread, see [`body_buffer`].
+    static BODY_LEN: Cell<usize> = const { Cell::new(0) };
+    /// The targets and surfaces of the records of this thread, see [`intern`].
+    static INTERNED: std::cell::RefCell<HashSet<Arc<str>>> = std::cell::RefCell::new(HashSet::new());
+}
+
+/// The most strings kept by [`intern`] per thread, so generated names don't accumulate.
+const MAX_INTERNED: usize = 1024;
+
+/// Share the string with the previous records of this thread, so the targets and surfaces
+/// of the records are only allocated once.
+fn intern(s: &str) -> Arc<str> {
+    INTERNED.with(|interned| {
+        let mut interned = interned.borrow_mut();
+        if let Some(shared) = interned.get(s) {
+            return shared.clone();
+        }
+        if interned.len() >= MAX_INTERNED {
+            interned.clear();
+        }
+        let shared: Arc<str> = Arc::from(s);
+        interned.insert(shared.clone());
+        shared
+    })
+}
+
+/// The largest capacity of the [`SCRATCH`] buffer, which is kept for the next record.
+const MAX_SCRATCH: usize = 1 << 16;
+
+/// Call `f` with the escaped text of the format arguments.
+///
+/// Unless the text is a static string, it is formatted into a buffer of the thread,
+/// so tight vlogging loops don't allocate for it.
+fn with_escaped<R>(args: &fmt::Arguments, f: impl FnOnce(json::Escape) -> R) -> R {
+    if let Some(text) = args.as_str() {
+        return f(json::Escape(text));
+    }
+    SCRATCH.with(|scratch| match scratch.try_borrow_mut() {
+        Ok(mut buf) => {
+            buf.clear();
+            buf.write_fmt(*args).unwrap();
+            let result = f(json::Escape(&buf));
+            if buf.capacity() > MAX_SCRATCH {
+                *buf = String::new();
+            }
+            result
+        }
+        // the arguments vlog themselves while they are formatted, so the buffer is in use.
+        Err(_) => f(json::Escape(&args.to_string())),
+    })
+}
+
+/// An empty buffer for a message body with the capacity of the previous body of the thread,
+/// so the body is usually allocated only once.
+fn body_buffer() -> String {
+    String::with_capacity(BODY_LEN.with(Cell::get))
+}
+
+/// Avoid the allocation for strings, which are known to be static.
+fn static_or_owned(s: Option<&'static str>, fallback: Option<&str>) -> Cow<'static, str> {
+    s.map_or_else(
+        || Cow::Owned(fallback.unwrap_or("").to_owned()),
+        Cow::Borrowed,
+    )
+}
 
 /// A builder for [`WebVLogger`].
 pub struct Builder {
     port: u16,
-    targets: Vec<String>,
+    targets: filter::Targets,
+    priority: ThreadPriority,
+    frame_budget: Option<FrameBudget>,
+    auto_snapshot: Option<(Duration, usize)>,
+    queue_limit: Option<usize>,
+    client_queue: usize,
+    flush_interval: Duration,
+    terminal_fallback: Option<Duration>,
+    rate_limit: Option<RateLimit>,
+    retention: Option<RetentionPolicy>,
+    ttls: HashMap<String, Duration>,
+    projections: HashMap<String, Projection>,
+    layers: layer::Layers,
+    grids: Vec<(String, f64, [[f64; 2]; 2])>,
+    configs: Vec<(String, SurfaceConfig)>,
+    record_to: Option<std::path::PathBuf>,
+    session: Option<std::path::PathBuf>,
+    quiet: bool,
+    lazy: bool,
+    float_precision: Option<u32>,
+    nodelay: bool,
+    send_buffer: Option<usize>,
+    ingest: bool,
+    /// The `host:port` of the server to forward to, see [`Builder::forward_to`].
+    upstream: Option<String>,
+    /// The name of this process on the upstream server, see [`Builder::process_name`].
+    process_name: Option<String>,
+    editor_link: Option<String>,
+    page: page::Page,
 }
 /// A Vlogger implementation, which hosts a webpage for the visualisation.
+///
+/// Usually it is set as the global vlogger with [`Builder::init`]. A handle created with
+/// [`Builder::build`] can also be driven directly. Clones share the same server.
+#[derive(Clone)]
 pub struct WebVLogger {
-    sender: Sender<String>,
-    targets: Vec<String>,
+    sender: Sender<Packet>,
+    /// The port of the server, 0 for the validating vlogger.
+    port: u16,
+    /// Stops the server thread, see [`WebVLogger::shutdown`].
+    stop: Arc<AtomicBool>,
+    /// Shared with all clones, so [`set_targets`] affects the global vlogger.
+    targets: Arc<RwLock<filter::Targets>>,
+    start: Instant,
+    snapshots: bool,
+    queue_limit: Option<usize>,
+    /// Shared with all clones, so the global vlogger and the shape macros use the same buckets.
+    rate: Option<Arc<rate::Limiter>>,
+    /// The default time to live of the visuals per surface.
+    ttls: HashMap<String, Duration>,
+    layers: layer::Layers,
+    /// The file of the persisted session, see [`Builder::persist_session`].
+    session: Option<Arc<std::path::PathBuf>>,
+    /// Discard everything while no client is connected, see [`Builder::lazy`].
+    lazy: bool,
+    /// Check the messages instead of sending them, see [`Builder::validate_init`].
+    validator: Option<Arc<validate::Validator>>,
 }
 
+/// A message on its way to the server thread.
+struct Packet {
+    message: Message,
+    /// Apply the message to the retained state, see [`Builder::auto_snapshot`].
+    retain: bool,
+    #[cfg(feature = "self-profile")]
+    queued: Instant,
+}
+
+impl Packet {
+    fn new(message: Message, retain: bool) -> Self {
+        Self {
+            message,
+            retain,
+            #[cfg(feature = "self-profile")]
+            queued: Instant::now(),
+        }
+    }
+}
+
+/// The configuration of the server threads.
+struct ServerConfig {
+    priority: ThreadPriority,
+    frame_budget: Option<FrameBudget>,
+    client_queue: usize,
+    flush_interval: Duration,
+    terminal_fallback: Option<Duration>,
+    nodelay: bool,
+    send_buffer: Option<usize>,
+    /// Accept messages on `/ingest`, see [`Builder::ingest`].
+    ingest: bool,
+    /// The surfaces in the 3D mode.
+    projections: HashMap<String, Projection>,
+    /// The template of the links to the source, see [`Builder::editor_link_template`].
+    editor_link: Option<String>,
+    page: page::Page,
+    /// The messages of the previous run, see [`Builder::persist_session`].
+    last_run: Vec<String>,
+    /// Passes the messages posted to `/ingest` on like the records of this process.
+    vlogger: Mutex<WebVLogger>,
+}
+
+/// The duration of a frame for the [`FrameBudget`].
+const FRAME_DURATION: Duration = Duration::from_micros(16_667);
+
+/// Per frame limits for the messages sent to the client, see [`Builder::frame_budget`].
+#[derive(Clone, Copy)]
+struct FrameBudget {
+    records: usize,
+    bytes: usize,
+}
+
+/// The usage of the [`FrameBudget`] in the current frame.
+struct FrameState {
+    start: Instant,
+    records: usize,
+    bytes: usize,
+    dropped: usize,
+}
+
+impl FrameState {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            records: 0,
+            bytes: 0,
+            dropped: 0,
+        }
+    }
+    /// Start a new frame and return the truncation notice for the ended frame if records were dropped.
+    fn next_frame(&mut self) -> Option<String> {
+        let dropped = self.dropped;
+        *self = Self::new();
+        (dropped > 0).then(|| format!("{{\"trunc\":{dropped}}}"))
+    }
+    /// Check if a message of `len` bytes still fits into the budget of the current frame.
+    fn admit(&mut self, budget: &FrameBudget, len: usize) -> bool {
+        if self.records >= budget.records || self.bytes + len > budget.bytes {
+            self.dropped += 1;
+            return false;
+        }
+        self.records += 1;
+        self.bytes += len;
+        true
+    }
+}
+
+/// The time the client has to acknowledge the protocol version.
+const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+
 /// The error type returned by [`init`].
 ///
 /// [`init`]: fn.init.html
@@ -120,13 +469,45 @@ impl From<io::Error> for InitError {
     }
 }
 
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Builder {
     /// Create a new [`Builder`] for [`WebVLogger`] with
     /// the default port `0`, which means the OS will choose the port.
     pub fn new() -> Self {
         Self {
             port: 0,
-            targets: vec![],
+            targets: filter::Targets::default(),
+            priority: ThreadPriority::Normal,
+            frame_budget: None,
+            auto_snapshot: None,
+            queue_limit: None,
+            client_queue: queue::DEFAULT_LIMIT,
+            flush_interval: Duration::ZERO,
+            terminal_fallback: None,
+            rate_limit: None,
+            retention: None,
+            ttls: HashMap::new(),
+            projections: HashMap::new(),
+            layers: layer::Layers::default(),
+            grids: vec![],
+            configs: vec![],
+            record_to: None,
+            session: None,
+            quiet: false,
+            lazy: false,
+            float_precision: None,
+            nodelay: true,
+            send_buffer: None,
+            ingest: false,
+            upstream: None,
+            process_name: None,
+            editor_link: None,
+            page: page::Page::default(),
         }
     }
     /// Set the port on which the server will be made available.
@@ -138,22 +519,351 @@ impl Builder {
     }
     /// Add a target to the target whitelist.
     /// If the whitelist is left empty, all targets are allowed.
+    ///
+    /// With the `glob` feature, targets containing `*` or `?` are glob patterns, where `*` matches
+    /// within a single module path segment and `**` across segments. Like plain prefixes, they also
+    /// allow all submodules of the matched targets, e.g. `engine::*::collision` allows
+    /// `engine::physics::collision::broad_phase`.
     pub fn add_target(&mut self, target: &str) -> &mut Self {
-        self.targets.push(target.to_owned());
+        self.targets.allow(target);
         self
     }
-    /// Read the targets from the
+    /// Add a target to the target blacklist. Targets starting with it are never vlogged,
+    /// even if they are in the whitelist, e.g. to silence one noisy submodule of an allowed crate.
+    pub fn deny_target(&mut self, target: &str) -> &mut Self {
+        self.targets.deny(target);
+        self
+    }
+    /// Add a surface to the surface whitelist.
+    /// If the whitelist is left empty, all surfaces are allowed.
+    ///
+    /// Unlike targets, surfaces are matched by their full name. Clearing a surface is never filtered.
+    pub fn allow_surface(&mut self, surface: &str) -> &mut Self {
+        self.targets.allow_surface(surface);
+        self
+    }
+    /// Add a surface to the surface blacklist. Visuals on it are never vlogged,
+    /// even if their target is allowed.
+    pub fn deny_surface(&mut self, surface: &str) -> &mut Self {
+        self.targets.deny_surface(surface);
+        self
+    }
+    /// Set the scheduling priority of the server threads.
+    ///
+    /// This is best-effort and silently ignored on platforms where it is not supported.
+    /// Lowering the priority keeps the vlogger from competing with real-time threads.
+    pub fn thread_priority(&mut self, priority: ThreadPriority) -> &mut Self {
+        self.priority = priority;
+        self
+    }
+    /// Limit the number of records and bytes sent to the client per frame (1/60 of a second).
+    ///
+    /// When a frame exceeds either limit, the remaining records of that frame are dropped
+    /// and a single truncation notice is shown in the message table instead.
+    /// Clearing a surface is never dropped. By default there is no limit.
+    pub fn frame_budget(&mut self, records: usize, bytes: usize) -> &mut Self {
+        self.frame_budget = Some(FrameBudget { records, bytes });
+        self
+    }
+    /// Periodically snapshot the retained state of all surfaces into an in-memory ring,
+    /// which keeps the last `keep` snapshots.
+    ///
+    /// The retained state of a surface are all records since it was last cleared,
+    /// limited by the [`RetentionPolicy`], see [`Builder::retention`]. The records are retained and
+    /// the snapshots are taken by the server thread, so the snapshots are also taken while nothing is vlogged.
+    /// On panic, the snapshots are written to `web-vlog-snapshots` in the temporary directory
+    /// and they can be written on demand with [`dump_snapshots`]. This provides post-mortem
+    /// visuals even if no client was connected.
+    pub fn auto_snapshot(&mut self, interval: Duration, keep: usize) -> &mut Self {
+        self.auto_snapshot = Some((interval, keep));
+        self
+    }
+    /// Drop records by their [`Priority`] when the client can't keep up.
+    ///
+    /// When more than `limit` messages are waiting to be sent, records with low priority are dropped.
+    /// When more than twice the limit are waiting, records with normal priority are dropped as well.
+    /// Records with high priority and clearing a surface are always delivered.
+    /// The number of dropped records is counted in [`Stats::dropped`]. By default nothing is dropped.
+    ///
+    /// Like all records dropped by backpressure, the viewer marks the affected surfaces with the number of dropped records.
+    pub fn queue_limit(&mut self, limit: usize) -> &mut Self {
+        self.queue_limit = Some(limit);
+        self
+    }
+    /// Set the number of messages, which can wait to be sent to a single client.
+    ///
+    /// Every connected client has its own queue, so a slow client doesn't delay the others.
+    /// When the queue of a client is full, records for this client are dropped and the client
+    /// is notified how many were dropped. A client, which has twice the limit waiting
+    /// and doesn't make progress for a few seconds, is disconnected. The dropped records are counted in [`Stats::client_dropped`].
+    /// The default is 4096.
+    pub fn client_queue(&mut self, limit: usize) -> &mut Self {
+        self.client_queue = limit;
+        self
+    }
+    /// Set the latency budget for writing to the clients.
+    ///
+    /// The messages are written to the socket in batches, which are flushed at most once per `interval`,
+    /// so fast animations cause fewer syscalls. Messages are delayed by up to `interval`.
+    /// By default every batch is flushed as soon as no more messages are waiting.
+    pub fn flush_interval(&mut self, interval: Duration) -> &mut Self {
+        self.flush_interval = interval;
+        self
+    }
+    /// Render to the terminal, if no client connects within the `timeout`.
+    ///
+    /// Instead of queuing the messages until a viewer connects, e.g. when running over SSH without a browser,
+    /// the text messages are written to stderr and the points, lines, outlines of shapes and labels of every
+    /// changed surface are drawn as a coarse grid of characters about once per second. The terminal counts as
+    /// a connected client, so [`wait_for_connection`] returns once it takes over. When a viewer connects,
+    /// the terminal stops rendering and only takes over again once no viewer was connected for the `timeout`.
+    /// The colors are ANSI escape codes, unless the `NO_COLOR` environment variable is set.
+    /// By default the messages wait for a viewer. The forwarding mode of [`Builder::forward_to`] doesn't use it.
+    pub fn terminal_fallback(&mut self, timeout: Duration) -> &mut Self {
+        self.terminal_fallback = Some(timeout);
+        self
+    }
+    /// Limit the records per second of every target or surface with a [`RateLimit`].
+    /// Clearing a surface is never limited. By default there is no limit.
+    pub fn rate_limit(&mut self, limit: RateLimit) -> &mut Self {
+        self.rate_limit = Some(limit);
+        self
+    }
+    /// Write every message, including clearing surfaces, to the file at `path`, while still streaming them to the browser.
+    ///
+    /// The messages are written from a separate thread as soon as they are vlogged, even if no
+    /// browser is connected, so the file can be examined after a crash.
+    /// The file uses the line-delimited JSON format of the [`recording`] module.
+    /// The file is created or truncated when the server is started.
+    /// With the `gzip` feature, the file is compressed if the path ends with `.gz`.
+    pub fn record_to(&mut self, path: impl AsRef<std::path::Path>) -> &mut Self {
+        self.record_to = Some(path.as_ref().to_owned());
+        self
+    }
+    /// Keep the state of the surfaces across process restarts in the file at `path`.
+    ///
+    /// When the server is shut down with [`shutdown`] or [`WebVLogger::shutdown`], the retained state
+    /// of all surfaces is written to the file. On the next start, the saved state is shown
+    /// on the surfaces `last run/<surface>`, so the last run can be compared side by side with the current one.
+    /// The state is retained like for [`auto_snapshot`](Builder::auto_snapshot), so the
+    /// [`RetentionPolicy`] applies to it as well. The file uses the format of the [`recording`] module.
+    ///
+    /// ```no_run
+    /// web_vlog::Builder::new().persist_session("target/vlog-session.jsonl").init().unwrap();
+    /// // ... vlog the current run
+    /// web_vlog::shutdown();
+    /// ```
+    pub fn persist_session(&mut self, path: impl AsRef<std::path::Path>) -> &mut Self {
+        self.session = Some(path.as_ref().to_owned());
+        self
+    }
+    /// Silence the log output of the server about the connections, except for errors.
+    ///
+    /// The environment variable `WEB_VLOG_QUIET` overrides this setting. It silences the output,
+    /// unless it is empty, `0` or `false`.
+    pub fn quiet(&mut self, quiet: bool) -> &mut Self {
+        self.quiet = quiet;
+        self
+    }
+    /// Only vlog while a client is connected.
+    ///
+    /// Without a client, records and clearing surfaces are discarded before any formatting,
+    /// so vlogging costs almost nothing while nobody is watching. Snapshots, sessions and
+    /// recordings then only contain, what was vlogged while a client was connected.
+    /// The validating vlogger ignores this setting.
+    pub fn lazy(&mut self, lazy: bool) -> &mut Self {
+        self.lazy = lazy;
+        self
+    }
+    /// Round the coordinates and sizes of the visuals to `digits` decimal places on the wire.
+    ///
+    /// The numbers are written in their shortest form, e.g. `400.00000000000006` becomes `400`
+    /// with 3 digits, which shrinks the messages of surfaces with many points.
+    /// By default the numbers are sent with their full precision.
+    pub fn float_precision(&mut self, digits: u32) -> &mut Self {
+        self.float_precision = Some(digits);
+        self
+    }
+    /// Set `TCP_NODELAY` on the connections, so small messages are sent immediately
+    /// instead of being delayed by Nagle's algorithm. This is enabled by default.
+    pub fn nodelay(&mut self, nodelay: bool) -> &mut Self {
+        self.nodelay = nodelay;
+        self
+    }
+    /// Set the size of the send buffer of the sockets in bytes. A larger buffer lets a client
+    /// fall behind further before its queue fills up. By default the size of the OS is kept.
+    ///
+    /// This requires the `send-buffer` feature, which adds a dependency on `socket2`.
+    #[cfg(feature = "send-buffer")]
+    pub fn send_buffer(&mut self, size: usize) -> &mut Self {
+        self.send_buffer = Some(size);
+        self
+    }
+    /// Accept the messages of other processes on the `/ingest` path of the server, see the [crate documentation](crate).
+    ///
+    /// This is needed for the vloggers [forwarding](Builder::forward_to) to this server.
+    /// Requests from web pages of other origins are refused, so a website visited in the browser
+    /// can't draw onto the surfaces. It is disabled by default.
+    pub fn ingest(&mut self, ingest: bool) -> &mut Self {
+        self.ingest = ingest;
+        self
+    }
+    /// Forward everything to the server of another vlogger at `upstream`, e.g. `"192.168.1.5:8080"`,
+    /// instead of serving the viewer from this process.
+    ///
+    /// No port is opened. The vlogger connects out to the `/ingest` endpoint of the upstream server,
+    /// which has to [accept](Builder::ingest) it, with a websocket and reconnects when the connection is lost. This is useful when the debugged
+    /// process runs on a device, which can't accept inbound connections. The upstream connection counts
+    /// as connected client, e.g. for [`wait_for_connection`]. The records arrive with the target filters
+    /// of the upstream server applied as well, where they are timestamped on arrival.
+    pub fn forward_to(&mut self, upstream: &str) -> &mut Self {
+        self.upstream = Some(upstream.to_owned());
+        self
+    }
+    /// Set the name of this process, when [forwarding](Builder::forward_to) to an upstream server.
+    ///
+    /// The upstream server prefixes the surfaces of this process with `name/`, so several processes
+    /// vlogging to the same server don't overwrite each other's surfaces. The default name is
+    /// the name of the executable and the process id, e.g. `robot-4711`. An empty name keeps the surfaces as they are,
+    /// e.g. when only a single process forwards to the server.
+    pub fn process_name(&mut self, name: &str) -> &mut Self {
+        self.process_name = Some(name.to_owned());
+        self
+    }
+    /// Set the [`RetentionPolicy`] for the retained records of the [`auto_snapshot`](Builder::auto_snapshot)s.
+    ///
+    /// The policy can be adjusted at runtime with [`set_retention`].
+    pub fn retention(&mut self, policy: RetentionPolicy) -> &mut Self {
+        self.retention = Some(policy);
+        self
+    }
+    /// Let the visuals on a surface disappear from the viewer after `ttl`, without clearing the surface.
+    ///
+    /// This is useful for streaming overlays. It can be overridden per record with [`with_ttl`].
+    pub fn surface_ttl(&mut self, surface: &str, ttl: Duration) -> &mut Self {
+        self.ttls.insert(surface.to_owned(), ttl);
+        self
+    }
+    /// Set the default layer of the visuals of all targets starting with `target`.
+    /// If multiple prefixes match, the longest one is used. See [`with_layer`] for the draw order.
+    pub fn target_layer(&mut self, target: &str, layer: i32) -> &mut Self {
+        let targets = &mut self.layers.targets;
+        targets.retain(|(t, _)| t != target);
+        targets.push((target.to_owned(), layer));
+        targets.sort_by_key(|(t, _)| std::cmp::Reverse(t.len()));
+        self
+    }
+    /// Set the default layer of the visuals on a surface. See [`with_layer`] for the draw order.
+    pub fn surface_layer(&mut self, surface: &str, layer: i32) -> &mut Self {
+        self.layers.surfaces.insert(surface.to_owned(), layer);
+        self
+    }
+    /// Configure how the viewer shows a surface from the start, see [`configure_surface`].
+    pub fn configure_surface(&mut self, surface: &str, config: SurfaceConfig) -> &mut Self {
+        self.configs.retain(|(s, _)| s != surface);
+        self.configs.push((surface.to_owned(), config));
+        self
+    }
+    /// Draw a background grid on a surface from the start, see [`surface_grid`].
+    pub fn surface_grid(
+        &mut self,
+        surface: &str,
+        spacing: f64,
+        extent: [[f64; 2]; 2],
+    ) -> &mut Self {
+        self.grids.push((surface.to_owned(), spacing, extent));
+        self
+    }
+    /// Show the surface in the 3D mode, where the z coordinate of the `v_log` visuals is projected
+    /// with the `projection` in the viewer. The camera is rotated by dragging with the Alt key pressed
+    /// and the visuals are drawn back to front. Other visuals, e.g. [`polygon!`], stay in the xy plane.
+    pub fn surface_3d(&mut self, surface: &str, projection: Projection) -> &mut Self {
+        self.projections.insert(surface.to_owned(), projection);
+        self
+    }
+    /// Set the template of the links from the visuals in the viewer to their source code.
+    ///
+    /// The placeholders `{file}` and `{line}` are replaced with the location of the vlogging call.
+    /// The default opens VSCode with `vscode://file/{file}:{line}:0`.
+    ///
+    /// ```
+    /// web_vlog::Builder::new()
+    ///     .editor_link_template("idea://open?file={file}&line={line}")
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    pub fn editor_link_template(&mut self, template: &str) -> &mut Self {
+        self.editor_link = Some(template.to_owned());
+        self
+    }
+    /// Set the title of the viewer page, e.g. to tell the tabs of multiple debugging sessions apart.
+    /// Pages, which are already open, get the new title when they reconnect.
+    pub fn title(&mut self, title: &str) -> &mut Self {
+        self.page.title = Some(title.to_owned());
+        self
+    }
+    /// Add custom CSS to the viewer page, which is applied after the default styles.
+    ///
+    /// The colors of the visuals are the CSS variables `--base`, `--error`, `--warn`, `--info`,
+    /// `--healthy`, `--x`, `--y`, `--z` and `--mis`, and `--back` is the background color.
+    ///
+    /// ```
+    /// web_vlog::Builder::new()
+    ///     .custom_css(":root{--back:#FFF;--base:#000} body{font-family:monospace}")
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    pub fn custom_css(&mut self, css: &str) -> &mut Self {
+        self.page.css.push_str(css);
+        self.page.css.push('\n');
+        self
+    }
+    /// Add custom JavaScript to the viewer page, e.g. for bespoke visualizations.
+    ///
+    /// The scripts run after the viewer script in the head of the page and can register hooks on
+    /// the global `webVlog` object:
+    /// - `webVlog.onMessage(f)` calls `f(message)` with every decoded message from the websocket
+    ///   before it is shown. Returning `false` from `f` skips the default handling of the message.
+    /// - `webVlog.onSurfaceCreated(f)` calls `f(name, svg)` with the svg element of every new surface.
+    ///
+    /// The messages are the json objects of the wire format with the interned strings already resolved,
+    /// e.g. `{"surf":"map","pos":[1,2,0],"meta":{...},...}` for a point.
+    ///
+    /// ```
+    /// web_vlog::Builder::new()
+    ///     .inject_script("webVlog.onSurfaceCreated((name, svg) => console.log('new surface', name))")
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    pub fn inject_script(&mut self, script: &str) -> &mut Self {
+        self.page.scripts.push_str(script);
+        self.page.scripts.push_str(";\n");
+        self
+    }
+    /// Read the targets from the environment variable `RUST_VLOG`, see the [crate] documentation for the syntax.
+    /// Filters starting with `-` are added to the blacklist with [`deny_target`](Builder::deny_target).
     pub fn targets_from_env(&mut self) -> &mut Self {
-        if let Ok(var) = std::env::var("RUST_VLOG") {
-            for target in var.split(",") {
-                let target = target.trim();
-                if !target.is_empty() {
-                    self.add_target(target);
-                }
-            }
+        self.env_var("RUST_VLOG")
+    }
+    /// Read the filters from the environment variable `name` instead of `RUST_VLOG`,
+    /// e.g. for libraries embedding the vlogger, see [`targets_from_env`](Builder::targets_from_env).
+    /// Nothing is added if the variable is not set.
+    pub fn env_var(&mut self, name: &str) -> &mut Self {
+        if let Ok(var) = std::env::var(name) {
+            self.parse_filters(&var);
         }
         self
     }
+    /// Add comma separated filters in the syntax of `RUST_VLOG`, e.g. from a config file,
+    /// see the [crate] documentation for the syntax.
+    ///
+    /// ```
+    /// web_vlog::Builder::new().parse_filters("my_crate,-my_crate::noisy,surface=physics");
+    /// ```
+    pub fn parse_filters(&mut self, filters: &str) -> &mut Self {
+        self.targets.parse(filters);
+        self
+    }
     /// Initialize the [`WebVLogger`] and set it as the global vlogger for [`v_log`].
     ///
     /// Returns the actual port, which the server runs on.
@@ -164,111 +874,508 @@ impl Builder {
     /// If the global vlogger has already been set an [`InitError::SetVLoggerError`] is returned.
     /// If the server could not be started on the chosen port, the [`std::io::Error`] is returned inside [`InitError::TcpError`].
     pub fn init(&self) -> Result<u16, InitError> {
+        self.build()?.set_global()
+    }
+    /// Start the server and return the [`WebVLogger`] without setting it as the global vlogger.
+    ///
+    /// The handle can be driven directly with [`WebVLogger::vlog_record`] and [`WebVLogger::clear`],
+    /// e.g. in tests, or set as the global vlogger later with [`WebVLogger::set_global`].
+    /// The shape macros of this crate, the retention policy, the snapshots and the surface settings
+    /// like [`surface_grid`] are global and apply to all servers.
+    ///
+    /// ```
+    /// let vlogger = web_vlog::Builder::new().build().unwrap();
+    /// println!("open http://localhost:{}", vlogger.port());
+    /// vlogger.clear("surface");
+    /// vlogger.shutdown();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the server could not be started on the chosen port, the [`std::io::Error`] is returned inside [`InitError::TcpError`].
+    pub fn build(&self) -> Result<WebVLogger, InitError> {
+        Ok(self.start(true)?.0)
+    }
+    /// Start the server or the forwarder and return the vlogger with the clients of its server.
+    ///
+    /// The global settings of the process are only changed if `global` is set.
+    fn start(&self, global: bool) -> Result<(WebVLogger, Option<Arc<queue::Clients>>), InitError> {
         let port = self.port;
+        // open the port on localhost first, so nothing is set up if this fails.
+        let listener = match self.upstream {
+            Some(_) => None,
+            None => Some(TcpListener::bind(("localhost", port))?),
+        };
         let (sender, rx) = channel();
-        let mut vlogger = WebVLogger {
-            sender,
-            targets: self.targets.clone(),
+        let rx = match &self.record_to {
+            Some(path) => recording::relay(path, rx)?,
+            None => rx,
+        };
+        let mut vlogger = self.vlogger(sender);
+        if let Some(listener) = &listener {
+            vlogger.port = listener.local_addr()?.port();
+        }
+        if global {
+            self.set_globals();
+        } else {
+            vlogger.snapshots = false;
+            if self.quiet {
+                // the output about the connections is shared by all servers.
+                QUIET.store(true, Ordering::Relaxed);
+            }
+        }
+        let stop = vlogger.stop.clone();
+        let Some(listener) = listener else {
+            let forwarder = forward::Forwarder {
+                upstream: self.upstream.clone().unwrap_or_default(),
+                process: self
+                    .process_name
+                    .clone()
+                    .unwrap_or_else(forward::default_process_name),
+                projections: self.projections.clone(),
+                nodelay: self.nodelay,
+                send_buffer: self.send_buffer,
+            };
+            let priority = self.priority;
+            std::thread::Builder::new()
+                .name("web-vlog-forward".to_owned())
+                .spawn(move || {
+                    thread::set_current_priority(priority);
+                    forwarder.run(rx, &stop);
+                })?;
+            return Ok((vlogger, None));
         };
-        vlogger.targets.sort();
-        vlogger.targets.dedup();
-        // first try to set the vlogger.
-        v_log::set_boxed_vlogger(Box::new(vlogger))?;
-        INIT.store(true, std::sync::atomic::Ordering::SeqCst);
-        // then try to open the port on localhost
-        // If this fails, the `rx` will be dropped.
-        // The vlogger will therefore stop.
-        let listener = TcpListener::bind(("localhost", port))?;
         let addr = listener.local_addr()?;
-        log::info!("web-vlog server started on {addr}");
+        let last_run = match &self.session {
+            Some(path) => session::load(path).unwrap_or_else(|err| {
+                log::error!("failed to load the vlog session {}: {err}", path.display());
+                vec![]
+            }),
+            None => vec![],
+        };
+        chatter!(info, "web-vlog server started on {addr}");
         // If the vlogger is successfully set, start the webserver.
-        std::thread::spawn(move || {
-            server_loop(listener, rx);
+        let config = Arc::new(ServerConfig {
+            priority: self.priority,
+            frame_budget: self.frame_budget,
+            client_queue: self.client_queue,
+            flush_interval: self.flush_interval,
+            terminal_fallback: self.terminal_fallback,
+            nodelay: self.nodelay,
+            send_buffer: self.send_buffer,
+            ingest: self.ingest,
+            projections: self.projections.clone(),
+            editor_link: self.editor_link.clone(),
+            page: self.page.clone(),
+            last_run,
+            vlogger: Mutex::new(vlogger.clone()),
         });
+        // The messages are copied into the queue of every connected client.
+        let clients = Arc::new(queue::Clients::new(config.client_queue));
+        let server = clients.clone();
+        std::thread::Builder::new()
+            .name("web-vlog-server".to_owned())
+            .spawn(move || {
+                thread::set_current_priority(config.priority);
+                server_loop(listener, rx, server, config, &stop);
+            })?;
         if port != 0 {
             assert_eq!(port, addr.port());
         }
-        Ok(addr.port())
+        Ok((vlogger, Some(clients)))
+    }
+    /// Apply the settings, which are global for the process.
+    fn set_globals(&self) {
+        let quiet = match std::env::var("WEB_VLOG_QUIET") {
+            Ok(var) => !matches!(var.trim(), "" | "0" | "false"),
+            Err(_) => self.quiet,
+        };
+        QUIET.store(quiet, Ordering::Relaxed);
+        json::set_precision(self.float_precision);
+        INIT.store(true, std::sync::atomic::Ordering::SeqCst);
+        if let Some(policy) = self.retention {
+            set_retention(policy);
+        }
+        for (surface, spacing, extent) in &self.grids {
+            surface_grid(surface, *spacing, *extent);
+        }
+        for (surface, config) in &self.configs {
+            configure_surface(surface, *config);
+        }
+        if let Some((interval, keep)) = self.auto_snapshot {
+            snapshot::enable(interval, keep);
+            snapshot::install_panic_hook();
+        } else if self.session.is_some() {
+            // only retain the state for the session, without taking snapshots.
+            snapshot::enable(Duration::MAX, 1);
+        }
+    }
+    /// Start the server and show the recording at `path` instead of live data, see [`replay`].
+    ///
+    /// The recording is played with its original timing divided by `speed`, so `2.0` plays twice as fast.
+    /// With an infinite speed all messages are sent at once. Returns the port of the server.
+    ///
+    /// The settings, which are global for the process, like the float precision, the retention
+    /// and the surface settings, are not applied, so a live vlogger next to the replay isn't affected.
+    /// Only [`quiet`](Builder::quiet) applies, as the log output is shared by all servers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the recording can't be opened or the server could not be started.
+    pub fn replay(&self, path: impl AsRef<std::path::Path>, speed: f64) -> io::Result<u16> {
+        let reader = recording::RecordingReader::open(path)?;
+        let (vlogger, clients) = match self.start(false) {
+            Ok(started) => started,
+            Err(InitError::TcpError(err)) => return Err(err),
+            Err(InitError::SetVLoggerError(_)) => {
+                unreachable!("starting doesn't set the global vlogger")
+            }
+        };
+        let port = vlogger.port();
+        std::thread::Builder::new()
+            .name("web-vlog-replay".to_owned())
+            .spawn(move || recording::play(reader, &vlogger, clients.as_deref(), speed))?;
+        Ok(port)
+    }
+    /// Initialize a validating vlogger and set it as the global vlogger for [`v_log`].
+    ///
+    /// Instead of starting a server, every record is run through the serialization and
+    /// checked against the rules. The problems can be queried with [`validation_summary`].
+    /// This way CI can check the instrumentation without opening sockets or browsers.
+    /// Every message is checked when it is sent, including the ones of the shapes,
+    /// the `draw_*` functions and the bridges, so nothing is queued.
+    /// The queue limit, the rate limit and the snapshots are not used for validation.
+    ///
+    /// # Errors
+    ///
+    /// If the global vlogger has already been set an [`InitError::SetVLoggerError`] is returned.
+    pub fn validate_init(&self, rules: ValidationRules) -> Result<(), InitError> {
+        let (sender, _) = channel();
+        let mut inner = self.vlogger(sender);
+        inner.snapshots = false;
+        inner.queue_limit = None;
+        inner.rate = None;
+        inner.lazy = false;
+        inner.validator = Some(Arc::new(validate::Validator::new(rules.clone())));
+        json::set_precision(self.float_precision);
+        v_log::set_boxed_vlogger(Box::new(validate::ValidatingVLogger {
+            inner: inner.clone(),
+            rules,
+        }))?;
+        *VLOGGER.write().unwrap() = Some(inner);
+        Ok(())
+    }
+    fn vlogger(&self, sender: Sender<Packet>) -> WebVLogger {
+        WebVLogger {
+            sender,
+            port: 0,
+            stop: Arc::new(AtomicBool::new(false)),
+            targets: Arc::new(RwLock::new(self.targets.clone())),
+            start: Instant::now(),
+            snapshots: self.auto_snapshot.is_some() || self.session.is_some(),
+            queue_limit: self.queue_limit,
+            rate: self
+                .rate_limit
+                .map(|limit| Arc::new(rate::Limiter::new(limit))),
+            ttls: self.ttls.clone(),
+            layers: self.layers.clone(),
+            session: self.session.clone().map(Arc::new),
+            lazy: self.lazy,
+            validator: None,
+        }
     }
 }
 
-impl VLog for WebVLogger {
-    fn enabled(&self, metadata: &v_log::Metadata) -> bool {
-        self.targets.is_empty()
-            || self
-                .targets
-                .iter()
-                .any(|target| metadata.target().starts_with(target))
+impl WebVLogger {
+    /// Set this vlogger as the global vlogger for [`v_log`] and return the port of its server.
+    ///
+    /// # Errors
+    ///
+    /// If the global vlogger has already been set an [`InitError::SetVLoggerError`] is returned
+    /// and the server is shut down.
+    pub fn set_global(self) -> Result<u16, InitError> {
+        let port = self.port;
+        if let Err(err) = v_log::set_boxed_vlogger(Box::new(self.clone())) {
+            self.shutdown();
+            return Err(err.into());
+        }
+        *VLOGGER.write().unwrap() = Some(self);
+        Ok(port)
     }
-    fn vlog(&self, record: &Record) {
-        if !self.enabled(record.metadata()) {
+    /// The port, which the server runs on. It is 0 if there is no server, e.g. when [forwarding](Builder::forward_to).
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+    /// Vlog a record directly with this vlogger instead of the global vlogger.
+    pub fn vlog_record(&self, record: &Record) {
+        VLog::vlog(self, record);
+    }
+    /// Clear a surface directly with this vlogger instead of the global vlogger.
+    pub fn clear(&self, surface: &str) {
+        VLog::clear(self, surface);
+    }
+    /// Close the connection to the client and stop the server.
+    /// Records vlogged afterwards are discarded.
+    ///
+    /// With [`Builder::persist_session`], the retained state is written to the session file first.
+    pub fn shutdown(&self) {
+        if self.stop.swap(true, Ordering::SeqCst) {
             return;
         }
-        // convert the record into a message to be send to the frontend.
-        let surface = record.surface().escape_default();
-        let size = record.size();
-        let color_meta = |start| {
-            let mut msg = format!(
-                "{start},\"surf\":\"{surface}\",\"meta\":{{\"target\":\"{}\",\"file\":\"{}\",\"line\":{}}},\"col\":\"",
-                record.target().escape_default(),
-                record
-                    .file()
-                    .unwrap_or("")
-                    .trim_start_matches('.')
-                    .escape_default(),
-                record.line().unwrap_or(0),
-            );
-            match *record.color() {
-                Color::Base => msg.push_str("var(--base)\"}"),
-                Color::Healthy => msg.push_str("var(--healthy)\"}"),
-                Color::Error => msg.push_str("var(--error)\"}"),
-                Color::Warn => msg.push_str("var(--warn)\"}"),
-                Color::Info => msg.push_str("var(--info)\"}"),
-                Color::X => msg.push_str("var(--x)\"}"),
-                Color::Y => msg.push_str("var(--y)\"}"),
-                Color::Z => msg.push_str("var(--z)\"}"),
-                Color::Missing => msg.push_str("var(--mis)\"}"),
-                Color::Hex(hexcode) => write!(&mut msg, "#{hexcode:08X}\"}}").unwrap(),
-                _ => msg.push_str("#000\"}"), // unknown -> black, as Missing is already pink
+        if let Some(path) = &self.session {
+            if let Err(err) = session::save(path) {
+                log::error!("failed to save the vlog session {}: {err}", path.display());
             }
-            msg
-        };
-        let mut tmp = String::new();
-        let label = record.args().as_str().map_or_else(
-            || {
-                tmp = record.args().to_string();
-                tmp.escape_default()
-            },
-            |s| s.escape_default(),
-        );
-        let msg = match record.visual() {
-            Visual::Message => {
-                color_meta(format_args!("{{\"msg\":\"{label}\""))
+        }
+        // let the connection thread close the websocket.
+        self.send(Message::Flush);
+        // wake up the server thread, which is waiting for new connections.
+        if self.port != 0 {
+            let _ = TcpStream::connect(("localhost", self.port));
+        }
+    }
+    /// Queue a message for the server thread. Returns false if the server thread has stopped.
+    fn send(&self, message: Message) -> bool {
+        if let Some(validator) = &self.validator {
+            validator.check(&message);
+            return true;
+        }
+        // count before sending, so the server thread never sees a negative count.
+        QUEUED.fetch_add(1, Ordering::Relaxed);
+        if self.snapshots {
+            snapshot::mark();
+        }
+        let sent = self
+            .sender
+            .send(Packet::new(message, self.snapshots))
+            .is_ok();
+        if !sent {
+            QUEUED.fetch_sub(1, Ordering::Relaxed);
+            if self.snapshots {
+                snapshot::discard();
+            }
+        }
+        sent
+    }
+    /// Check if the target passes the target filters.
+    fn target_enabled(&self, target: &str) -> bool {
+        self.watched() && self.targets.read().unwrap().enabled(target)
+    }
+    /// Check if both the target and the surface pass the filters.
+    fn accepts(&self, target: &str, surface: &str) -> bool {
+        if !self.watched() {
+            return false;
+        }
+        let targets = self.targets.read().unwrap();
+        targets.enabled(target) && targets.surface_enabled(surface)
+    }
+    /// Check if a client is connected or the vlogger isn't [lazy](Builder::lazy).
+    fn watched(&self) -> bool {
+        !self.lazy || CLIENTS.load(Ordering::Relaxed) > 0
+    }
+    /// Check if a visual with the color is sent under the current load, see [`Builder::queue_limit`]
+    /// and [`Builder::rate_limit`].
+    fn admit(&self, target: &str, surface: &str, color: &Color, message: bool) -> bool {
+        if let Some(limit) = self.queue_limit {
+            if !Priority::of(color, message).admit(QUEUED.load(Ordering::Relaxed), limit) {
+                stats::add_dropped(surface);
+                return false;
             }
-            Visual::Label { x, y, z, alignment } => {
-                color_meta(format_args!("{{\"lbl\":\"{label}\",\"pos\":[{x},{y},{z}],\"align\":{},\"size\":{size}", *alignment as u8))
+        }
+        self.rate
+            .as_ref()
+            .map_or(true, |rate| rate.admit(target, surface))
+    }
+    /// Complete the visual specific json fields in `body` and send them as record to the server thread.
+    fn emit(&self, origin: Origin, surface: &str, color: &Color, message: bool, mut body: String) {
+        write_color(&mut body, "col", color);
+        if !message {
+            if let Some(ttl) = ttl::current().or_else(|| self.ttls.get(surface).copied()) {
+                write!(&mut body, ",\"ttl\":{}", ttl.as_millis()).unwrap();
             }
-            Visual::Point { x, y, z, style } => {
-                color_meta(format_args!("{{\"lbl\":\"{label}\",\"pos\":[{x},{y},{z}],\"style\":\"{style:?}\",\"size\":{size}"))
+            if let Some(opacity) = opacity::current() {
+                write!(&mut body, ",\"op\":{opacity}").unwrap();
             }
-            Visual::Line { x1, y1, z1, x2, y2, z2, style } => {
-                color_meta(format_args!("{{\"lbl\":\"{label}\",\"pos\":[{x1},{y1},{z1}],\"pos2\":[{x2},{y2},{z2}],\"style\":\"{style:?}\",\"size\":{size}"))
+            entity::write_entity(&mut body);
+            let layer = self.layers.of(origin.target, surface);
+            if layer != 0 {
+                write!(&mut body, ",\"layer\":{layer}").unwrap();
             }
+        }
+        fields::write_fields(&mut body);
+        BODY_LEN.with(|len| len.set(body.len()));
+        let meta = THREAD_META.with(|(tid, thread)| wire::Meta {
+            target: intern(origin.target),
+            module: origin.module,
+            file: origin.file,
+            line: origin.line,
+            time: self.start.elapsed().as_secs_f64(),
+            wall: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis()),
+            tid: *tid,
+            thread: thread.clone(),
+        });
+        let msg = Message::Record {
+            surface: intern(surface),
+            meta,
+            body: body.into(),
         };
+        #[cfg(feature = "self-profile")]
+        stats::add_allocated(msg.allocated());
         // If the receiver is dropped, the messages will still be constructed, but no longer sent.
         // This case doesn't have to be optimized with an early return, as it's the error state.
-        let _ = self.sender.send(msg);
+        self.send(msg);
+    }
+    /// Send the messages posted to `/ingest`, which pass the filters. Returns the number of sent messages.
+    fn ingest(&self, messages: Vec<Message>) -> usize {
+        let mut sent = 0;
+        for message in messages {
+            let passes = match &message {
+                Message::Record { surface, meta, .. } => {
+                    self.accepts(&meta.target, surface)
+                        && self.admit(&meta.target, surface, &Color::Base, false)
+                }
+                Message::Sample { surface, .. } => {
+                    self.accepts(ingest::TARGET, surface)
+                        && self.admit(ingest::TARGET, surface, &Color::Base, false)
+                }
+                Message::Clear(_) | Message::Setting(_) => self.watched(),
+                Message::Flush => false,
+            };
+            if !passes {
+                continue;
+            }
+            if !self.send(message) {
+                break;
+            }
+            sent += 1;
+        }
+        sent
+    }
+    /// Vlog a text message of another logging framework, if it passes the filters.
+    #[cfg(any(feature = "log-bridge", feature = "tracing"))]
+    fn forward(&self, origin: Origin, surface: &str, color: &Color, args: fmt::Arguments) {
+        if !self.accepts(origin.target, surface) || !self.admit(origin.target, surface, color, true)
+        {
+            return;
+        }
+        let mut body = body_buffer();
+        with_escaped(&args, |msg| write!(body, "\"msg\":\"{msg}\"")).unwrap();
+        self.emit(origin, surface, color, true, body);
+    }
+}
+
+/// The origin of a visual in the source code.
+struct Origin<'a> {
+    target: &'a str,
+    module: Cow<'static, str>,
+    file: Cow<'static, str>,
+    line: u32,
+}
+
+/// Write a color as json field `key` with a leading comma.
+fn write_color(out: &mut String, key: &str, color: &Color) {
+    write!(out, ",\"{key}\":").unwrap();
+    write_css(out, color);
+}
+
+/// Write a color as json string with the css color.
+fn write_css(out: &mut String, color: &Color) {
+    let css = match *color {
+        Color::Base => "var(--base)",
+        Color::Healthy => "var(--healthy)",
+        Color::Error => "var(--error)",
+        Color::Warn => "var(--warn)",
+        Color::Info => "var(--info)",
+        Color::X => "var(--x)",
+        Color::Y => "var(--y)",
+        Color::Z => "var(--z)",
+        Color::Missing => "var(--mis)",
+        Color::Hex(hexcode) => {
+            write!(out, "\"#{hexcode:08X}\"").unwrap();
+            return;
+        }
+        unknown => {
+            // colors added to `v_log` later get a stable color derived from their name,
+            // so different ones can still be told apart.
+            let hash = format!("{unknown:?}").bytes().fold(0x811c9dc5u32, |h, b| {
+                (h ^ b as u32).wrapping_mul(0x01000193)
+            });
+            // keep every channel bright enough for the dark background.
+            let rgb = (hash & 0x7F7F7F) | 0x808080;
+            write!(out, "\"#{rgb:06X}\"").unwrap();
+            return;
+        }
+    };
+    write!(out, "\"{css}\"").unwrap();
+}
+
+impl VLog for WebVLogger {
+    fn enabled(&self, metadata: &v_log::Metadata) -> bool {
+        self.target_enabled(metadata.target())
+    }
+    fn vlog(&self, record: &Record) {
+        let message = matches!(record.visual(), Visual::Message);
+        let (target, surface) = (record.target(), record.surface());
+        if !self.accepts(target, surface) || !self.admit(target, surface, record.color(), message) {
+            return;
+        }
+        #[cfg(feature = "self-profile")]
+        let _timer = stats::VlogTimer::start();
+        // convert the record into a message to be send to the frontend.
+        let size = Num(record.size());
+        let mut body = body_buffer();
+        with_escaped(record.args(), |label| match record.visual() {
+            Visual::Message => write!(body, "\"msg\":\"{label}\""),
+            Visual::Label { x, y, z, alignment } => write!(
+                body,
+                "\"lbl\":\"{label}\",\"pos\":{},\"align\":{},\"size\":{size}",
+                Pos([*x, *y, *z]),
+                *alignment as u8
+            ),
+            Visual::Point { x, y, z, style } => write!(
+                body,
+                "\"lbl\":\"{label}\",\"pos\":{},\"style\":\"{style:?}\",\"size\":{size}",
+                Pos([*x, *y, *z])
+            ),
+            Visual::Line {
+                x1,
+                y1,
+                z1,
+                x2,
+                y2,
+                z2,
+                style,
+            } => write!(
+                body,
+                "\"lbl\":\"{label}\",\"pos\":{},\"pos2\":{},\"style\":\"{style:?}\",\"size\":{size}",
+                Pos([*x1, *y1, *z1]),
+                Pos([*x2, *y2, *z2])
+            ),
+        })
+        .unwrap();
+        if let Visual::Line { .. } = record.visual() {
+            line::write_stroke(&mut body);
+        }
+        let origin = Origin {
+            target: record.target(),
+            module: static_or_owned(record.module_path_static(), record.module_path()),
+            file: static_or_owned(record.file_static(), record.file()),
+            line: record.line().unwrap_or(0),
+        };
+        self.emit(origin, record.surface(), record.color(), message, body);
     }
     fn clear(&self, surface: &str) {
-        let _ = self.sender.send(format!(
-            "{{\"clear\":1,\"surf\":\"{}\"}}",
-            surface.escape_default()
-        ));
+        if !self.watched() {
+            return;
+        }
+        self.send(Message::Clear(surface.to_owned()));
     }
     fn flush(&self) {
         let lock = WAIT.0.lock().unwrap();
-        if let Ok(_) = self.sender.send(String::new()) {
-            let _lock = WAIT.1.wait_while(lock, |v| *v).unwrap();
+        if self.send(Message::Flush) {
+            let _lock = WAIT.1.wait_while(lock, |v| *v > 0).unwrap();
         }
     }
 }
@@ -298,134 +1405,871 @@ pub fn init() -> u16 {
     Builder::new().targets_from_env().init().unwrap()
 }
 
+/// Replace the target filters of the running vlogger, e.g. to tighten or loosen
+/// the vlogging of a long-running service without restarting it.
+///
+/// The filters use the syntax of `RUST_VLOG`, see the [crate] documentation.
+/// An empty list allows all targets. Does nothing if no vlogger has been initialized.
+///
+/// ```
+/// web_vlog::validate_init();
+/// // only vlog the physics, except for the noisy broad phase.
+/// web_vlog::set_targets(&["physics", "-physics::broad_phase"]);
+/// ```
+pub fn set_targets(targets: &[&str]) {
+    if let Some(vlogger) = &*VLOGGER.read().unwrap() {
+        let mut filters = filter::Targets::default();
+        for target in targets {
+            filters.parse(target);
+        }
+        *vlogger.targets.write().unwrap() = filters;
+    }
+}
+
+/// Serve the recording at `path` on `port` instead of live data, e.g. to replay a session captured
+/// on CI with [`Builder::record_to`] locally.
+///
+/// The replay starts with the original timing when the first browser connects. Returns the port
+/// of the server. The global vlogger is not set, so this can be used next to a live vlogger.
+/// For other speeds use [`Builder::replay`].
+///
+/// ```no_run
+/// let port = web_vlog::replay("session.vlog", 0).unwrap();
+/// println!("open http://localhost:{port}");
+/// // the server and the replay run on their own threads.
+/// std::thread::park();
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if the recording can't be opened or the server could not be started.
+pub fn replay(path: impl AsRef<std::path::Path>, port: u16) -> io::Result<u16> {
+    Builder::new().port(port).replay(path, 1.0)
+}
+
+/// Write the current retained state of all surfaces as a standalone HTML file with the viewer inlined,
+/// e.g. to attach a frozen visualization to a bug report.
+///
+/// The retained state is only kept with [`Builder::auto_snapshot`], otherwise only the settings like
+/// [`surface_grid`] are included. The customizations of the page by the [`Builder`] are not included.
+/// The same file is served by the running vlogger at `/export/html`, including the customizations.
+pub fn snapshot_html(path: impl AsRef<std::path::Path>) -> io::Result<()> {
+    let mut file = BufWriter::new(std::fs::File::create(path)?);
+    page::Page::default().write_static(&mut file, Some(&static_messages()))?;
+    file.flush()
+}
+
+/// The messages of the standalone HTML export, see [`snapshot_html`].
+fn static_messages() -> Vec<String> {
+    let mut messages = view::settings();
+    messages.extend(snapshot::retained_messages());
+    messages
+}
+
+/// Render a surface into a PNG image with the given `[width, height]` in pixels, without a browser.
+///
+/// This is meant for CI jobs, which keep images of the vlog surfaces as artifacts.
+/// Like [`snapshot_html`], this uses the retained state, so [`Builder::auto_snapshot`] has to be enabled.
+/// The view set with [`SurfaceConfig::view`] is used if there is one, otherwise the view fits all visuals.
+/// Only points, lines, labels and the geometric [shapes](shape) are drawn, plots, tables, images
+/// and the other composite visuals are left out. Returns an error with [`io::ErrorKind::NotFound`]
+/// if nothing is retained on the surface.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use v_log::point;
+///
+/// web_vlog::Builder::new()
+///     .auto_snapshot(Duration::from_secs(60), 1)
+///     .init()
+///     .unwrap();
+/// point!("path", [1.0, 2.0], 5.0, Base);
+/// web_vlog::export_png("path", "path.png", [800, 600]).unwrap();
+/// ```
+#[cfg(feature = "png")]
+pub fn export_png(
+    surface: &str,
+    path: impl AsRef<std::path::Path>,
+    resolution: [u32; 2],
+) -> io::Result<()> {
+    raster::export_png(surface, path.as_ref(), resolution)
+}
+
+/// Save the current state of all surfaces under the name, so it can be shown again with [`restore`].
+///
+/// This uses the retained state, so [`Builder::auto_snapshot`] or [`Builder::persist_session`] has to be enabled.
+/// A previous checkpoint with the same name is replaced. Returns the number of saved records.
+///
+/// ```no_run
+/// # fn optimize() {}
+/// web_vlog::checkpoint("before");
+/// optimize();
+/// // switch back and forth between the states before and after the optimization.
+/// web_vlog::checkpoint("after");
+/// web_vlog::restore("before");
+/// ```
+pub fn checkpoint(name: &str) -> usize {
+    snapshot::checkpoint(name)
+}
+
+/// Reset the surfaces saved with [`checkpoint`] to their state at the checkpoint.
+///
+/// The surfaces are cleared and the saved records are sent to the viewer again, where they
+/// replace the current visuals. Surfaces, which were empty at the checkpoint, are not changed.
+/// Returns false if there is no checkpoint with the name.
+pub fn restore(name: &str) -> bool {
+    let Some(messages) = snapshot::restore(name) else {
+        return false;
+    };
+    if let Some(vlogger) = &*VLOGGER.read().unwrap() {
+        for message in messages {
+            // the messages are already encoded, so they are sent as they are.
+            vlogger.send(Message::Setting(message));
+        }
+    }
+    true
+}
+
+/// Write the surface snapshots taken by [`Builder::auto_snapshot`] into the directory.
+///
+/// The current state is snapshotted as well. Every snapshot is written as
+/// `snapshot-<unix millis>.vlog` in the [recording format](recording), so it can be shown
+/// again with [`replay`] or `web-vlog-view`. Returns the paths of the written files,
+/// which is empty if snapshots are not enabled.
+pub fn dump_snapshots(dir: impl AsRef<std::path::Path>) -> io::Result<Vec<std::path::PathBuf>> {
+    snapshot::dump(dir.as_ref(), true)
+}
+
+/// Initialise a validating vlogger with the default [`ValidationRules`].
+/// The target whitelist gets loaded from the environment variable
+/// `RUST_VLOG`. If it is not set, all targets are whitelisted.
+///
+/// See [`Builder::validate_init`] for details.
+///
+/// # Panics
+///
+/// This function will panic if the vlogger has already been set.
+pub fn validate_init() {
+    Builder::new()
+        .targets_from_env()
+        .validate_init(ValidationRules::new())
+        .unwrap()
+}
+
+/// Shut down the global vlogger, see [`WebVLogger::shutdown`].
+/// Does nothing if no vlogger has been initialized by this crate.
+pub fn shutdown() {
+    if let Some(vlogger) = &*VLOGGER.read().unwrap() {
+        vlogger.shutdown();
+    }
+}
+
 /// Wait for a client to connect to the vlogging server.
 /// This blocks indefinitely if no server has been started.
 pub fn wait_for_connection() {
     if INIT.load(std::sync::atomic::Ordering::SeqCst) {
         let lock = WAIT.0.lock().unwrap();
-        let _lock = WAIT.1.wait_while(lock, |v| !*v).unwrap();
+        let _lock = WAIT.1.wait_while(lock, |v| *v == 0).unwrap();
     }
 }
-/// Wait for the client to disconnect from the vlogging server.
+/// Wait for all clients to disconnect from the vlogging server.
 /// This can be used to ensure all messages have been received.
 pub fn wait_for_disconnect() {
     let lock = WAIT.0.lock().unwrap();
-    let _lock = WAIT.1.wait_while(lock, |v| *v).unwrap();
+    let _lock = WAIT.1.wait_while(lock, |v| *v > 0).unwrap();
 }
-/// Wait for the client to disconnect from the vlogging server.
+/// Wait for all clients to disconnect from the vlogging server.
 ///
 /// Returns true on success and false if it timed out.
 pub fn wait_for_disconnect_timeout(dur: Duration) -> bool {
     let lock = WAIT.0.lock().unwrap();
-    let lock = WAIT.1.wait_timeout_while(lock, dur, |v| *v).unwrap();
+    let lock = WAIT.1.wait_timeout_while(lock, dur, |v| *v > 0).unwrap();
     !lock.1.timed_out()
 }
 
-fn server_loop(listener: TcpListener, rx: Receiver<String>) {
+fn server_loop(
+    listener: TcpListener,
+    rx: Receiver<Packet>,
+    clients: Arc<queue::Clients>,
+    config: Arc<ServerConfig>,
+    stop: &Arc<AtomicBool>,
+) {
+    let dispatcher = clients.clone();
+    let spawned = std::thread::Builder::new()
+        .name("web-vlog-dispatch".to_owned())
+        .spawn(move || dispatcher.dispatch(rx));
+    if let Err(err) = spawned {
+        log::error!("failed to spawn the dispatch thread: {err:?}");
+        return;
+    }
+    if let Some(timeout) = config.terminal_fallback {
+        let clients = clients.clone();
+        let stop = stop.clone();
+        let spawned = std::thread::Builder::new()
+            .name("web-vlog-terminal".to_owned())
+            .spawn(move || terminal::run(&clients, timeout, &stop));
+        if let Err(err) = spawned {
+            log::error!("failed to spawn the terminal thread: {err:?}");
+        }
+    }
     // It's ok to panic in this thread to notify the user that something went wrong.
-    while let Ok((mut stream, addr)) = listener.accept() {
-        log::info!("vlogger connection from {addr}");
-        if let Err(err) = handle_connection(&stream, &rx) {
-            if let Err(err) = stream
-                .write_all(format!("HTTP/1.1 500 INTERNAL SERVER ERROR\r\n\r\n{err}").as_bytes())
-            {
-                log::error!("an error occurred: {err:?}");
-            }
+    while let Ok((stream, addr)) = listener.accept() {
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+        chatter!(info, "vlogger connection from {addr}");
+        if let Err(err) = configure_stream(&stream, config.nodelay, config.send_buffer) {
+            chatter!(warn, "failed to set the socket options: {err}");
+        }
+        let clients = clients.clone();
+        let config = config.clone();
+        let spawned = std::thread::Builder::new()
+            .name("web-vlog-client".to_owned())
+            .spawn(move || {
+                thread::set_current_priority(config.priority);
+                connection_thread(stream, &clients, &config);
+            });
+        if let Err(err) = spawned {
+            log::error!("failed to spawn connection thread: {err:?}");
+        }
+    }
+    clients.stop();
+}
+
+/// Apply the socket options of the [`Builder`].
+#[cfg_attr(not(feature = "send-buffer"), allow(unused_variables))]
+fn configure_stream(
+    stream: &TcpStream,
+    nodelay: bool,
+    send_buffer: Option<usize>,
+) -> io::Result<()> {
+    stream.set_nodelay(nodelay)?;
+    #[cfg(feature = "send-buffer")]
+    if let Some(size) = send_buffer {
+        socket2::SockRef::from(stream).set_send_buffer_size(size)?;
+    }
+    Ok(())
+}
+
+fn connection_thread(mut stream: TcpStream, clients: &Arc<queue::Clients>, config: &ServerConfig) {
+    let start = Instant::now();
+    let mut connection = audit::Connection::new(stream.peer_addr().ok());
+    let received = Cell::new(0);
+    let sent = Cell::new(0);
+    if let Err(err) = handle_connection(&stream, clients, config, &mut connection, &received, &sent)
+    {
+        connection.status = 500;
+        if let Err(err) =
+            stream.write_all(format!("HTTP/1.1 500 INTERNAL SERVER ERROR\r\n\r\n{err}").as_bytes())
+        {
+            log::error!("an error occurred: {err:?}");
         }
     }
+    connection.duration = start.elapsed();
+    connection.bytes_received = received.get();
+    connection.bytes_sent = sent.get();
+    audit::push(connection);
 }
 
-fn handle_connection(stream: &TcpStream, rx: &Receiver<String>) -> std::io::Result<()> {
-    let mut buf_reader = BufReader::new(stream);
-    let mut buf_writer = BufWriter::new(stream);
+fn handle_connection(
+    stream: &TcpStream,
+    clients: &Arc<queue::Clients>,
+    config: &ServerConfig,
+    connection: &mut audit::Connection,
+    received: &Cell<u64>,
+    sent: &Cell<u64>,
+) -> std::io::Result<()> {
+    let mut buf_reader = BufReader::new(audit::Counted {
+        inner: stream,
+        bytes: received,
+    });
+    let mut buf_writer = BufWriter::new(audit::Counted {
+        inner: stream,
+        bytes: sent,
+    });
     // only use the first line
     let mut buf = String::new();
     let mut http_request = String::new();
     let mut key_back = String::new();
+    let mut protocols = String::new();
+    let mut content_length = None;
+    let mut content_type = String::new();
+    let mut host = String::new();
+    let mut origin = None;
     while let Ok(bytes) = buf_reader.read_line(&mut buf) {
         let l = buf.trim_end();
-        log::debug!("{l}");
+        chatter!(debug, "{l}");
         if bytes == 0 || l.is_empty() {
             break;
         }
         if http_request.is_empty() {
             http_request.push_str(l);
-        }
-        // see https://datatracker.ietf.org/doc/html/rfc6455
-        else if let Some(key) = l.strip_prefix("Sec-WebSocket-Key: ") {
-            let key = key.to_owned() + "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
-            let digest = sha1::Sha1::digest(key);
-            key_back = BASE64_STANDARD.encode(digest);
+        } else if let Some((name, value)) = l.split_once(':') {
+            // header names are case insensitive.
+            let value = value.trim();
+            match name.to_ascii_lowercase().as_str() {
+                // see https://datatracker.ietf.org/doc/html/rfc6455
+                "sec-websocket-key" => {
+                    let key = value.to_owned() + "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+                    let digest = sha1::Sha1::digest(key);
+                    key_back = BASE64_STANDARD.encode(digest);
+                }
+                "sec-websocket-protocol" => protocols = value.to_owned(),
+                "user-agent" => connection.user_agent = value.to_owned(),
+                "content-length" => content_length = value.parse::<usize>().ok(),
+                "content-type" => content_type = value.to_owned(),
+                "host" => host = value.to_owned(),
+                "origin" => origin = Some(value.to_owned()),
+                _ => {}
+            }
         }
         buf.clear();
     }
     let (get, rest) = http_request.split_once(' ').unwrap_or(("", ""));
     let (path, http) = rest.split_once(' ').unwrap_or(("", ""));
+    connection.path = path.to_owned();
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+    // the name of a producer posting to `/ingest`, which namespaces its surfaces.
+    let process = ingest::query_param(query, "process").filter(|name| !name.is_empty());
+    if path == "/ingest" {
+        // browsers send the origin of the page with every cross-origin request, while other
+        // producers like curl or a forwarding vlogger send none.
+        let refusal = if !config.ingest {
+            Some((404, "ingesting is not enabled on this server"))
+        } else if origin.map_or(false, |origin| origin != format!("http://{host}")) {
+            Some((403, "pages of other origins can't ingest messages"))
+        } else if get == "POST" && !is_json(&content_type) {
+            Some((415, "the Content-Type has to be application/json"))
+        } else {
+            None
+        };
+        if let Some((status, error)) = refusal {
+            connection.status = status;
+            buf_writer.write_all(
+                format!(
+                    "HTTP/1.1 {status} {}\r\nContent-Type: application/json\r\n\r\n{{\"error\":\"{error}\"}}",
+                    reason(status)
+                )
+                .as_bytes(),
+            )?;
+            buf_writer.flush()?;
+            return Ok(());
+        }
+    }
     if get == "GET" && http == "HTTP/1.1" {
-        if !key_back.is_empty() {
-            log::debug!("vlogging client connected");
-            {
-                let mut guard = WAIT.0.lock().unwrap();
-                *guard = true;
-                WAIT.1.notify_all();
-            }
+        if !key_back.is_empty() && path == "/ingest" {
+            // a forwarding vlogger, which streams its messages, see `Builder::forward_to`.
+            connection.websocket = true;
+            connection.status = 101;
             buf_writer.write_all(format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {key_back}\r\n\r\n").as_bytes())?;
             buf_writer.flush()?;
+            chatter!(
+                info,
+                "forwarding vlogger {} connected",
+                process.as_deref().unwrap_or("")
+            );
+            // the payload of a fragmented message, which is reassembled from its continuation frames.
+            let mut payload = Vec::new();
+            loop {
+                let (head, fragment) = read_frame(&mut buf_reader, ingest::MAX_BODY)?;
+                match head & 0x0F {
+                    0x0 | 0x1 => {
+                        if payload.len() + fragment.len() > ingest::MAX_BODY {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                "websocket message from the client is too large",
+                            ));
+                        }
+                        payload.extend_from_slice(&fragment);
+                        // wait for the continuation frames of a fragmented message.
+                        if head & 0x80 == 0 {
+                            continue;
+                        }
+                    }
+                    0x8 => break,
+                    0x9 => {
+                        write_frame(&mut buf_writer, 0x8A, &fragment)?;
+                        buf_writer.flush()?;
+                        continue;
+                    }
+                    _ => continue,
+                }
+                let body = String::from_utf8_lossy(&std::mem::take(&mut payload)).into_owned();
+                // parse before locking the vlogger, so large messages don't block the other producers.
+                let time = config.vlogger.lock().unwrap().start.elapsed().as_secs_f64();
+                match ingest::parse(&body, time, process.as_deref()) {
+                    Ok(messages) => {
+                        config.vlogger.lock().unwrap().ingest(messages);
+                    }
+                    Err(err) => chatter!(warn, "invalid message from a forwarding vlogger: {err}"),
+                }
+            }
+            chatter!(
+                info,
+                "forwarding vlogger {} disconnected",
+                process.as_deref().unwrap_or("")
+            );
+        } else if !key_back.is_empty() {
+            let encoding = Encoding::negotiate(&protocols);
+            connection.websocket = true;
+            connection.status = 101;
+            buf_writer.write_all(format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {key_back}\r\n").as_bytes())?;
+            if let Some(protocol) = encoding.protocol() {
+                buf_writer
+                    .write_all(format!("Sec-WebSocket-Protocol: {protocol}\r\n").as_bytes())?;
+            }
+            buf_writer.write_all(b"\r\n")?;
+            // negotiate the version of the wire format.
+            encoding.write_frame(&mut buf_writer, &wire::hello())?;
+            buf_writer.flush()?;
+            stream.set_read_timeout(Some(ACK_TIMEOUT))?;
+            // the viewer only sends small control messages.
+            let ack = read_frame(&mut buf_reader, u16::MAX as usize)
+                .ok()
+                .and_then(|(_, payload)| wire::parse_ack(&payload));
+            stream.set_read_timeout(None)?;
+            let compact = match ack {
+                Some(wire::PROTOCOL_VERSION) => true,
+                Some(version) => {
+                    log::error!("vlogging client uses the unsupported protocol version {version}");
+                    let error = wire::version_error(version);
+                    encoding.write_frame(&mut buf_writer, &error)?;
+                    buf_writer.write_all(&[0x88, 0x80])?;
+                    buf_writer.flush()?;
+                    return Ok(());
+                }
+                None => {
+                    // Pages cached from older versions don't acknowledge the version,
+                    // but they understand messages without the compact encoding.
+                    chatter!(warn, "vlogging client did not acknowledge the protocol version, falling back to the basic protocol");
+                    false
+                }
+            };
+            for (surface, projection) in &config.projections {
+                encoding.write_frame(&mut buf_writer, &wire::view(surface, *projection))?;
+            }
+            if let Some(title) = &config.page.title {
+                encoding.write_frame(&mut buf_writer, &wire::title(title))?;
+            }
+            if let Some(template) = &config.editor_link {
+                encoding.write_frame(&mut buf_writer, &wire::editor(template))?;
+            }
+            for setting in view::settings() {
+                encoding.write_frame(&mut buf_writer, &setting)?;
+            }
+            for message in &config.last_run {
+                encoding.write_frame(&mut buf_writer, message)?;
+            }
+            chatter!(debug, "vlogging client connected");
+            // the client is removed again when this is dropped.
+            let client = clients.register();
             stream.set_nonblocking(true)?;
-            let close = |buf_writer: &mut BufWriter<&TcpStream>| {
-                // ignore IO errors here, as the condvar needs to be notified.
+            let close = |buf_writer: &mut BufWriter<audit::Counted<&TcpStream>>| {
+                // ignore IO errors here, as the client is removed anyway.
                 let _ = stream.set_nonblocking(false);
                 let _ = buf_writer.write_all(&[0x88, 0x80]);
                 let _ = buf_writer.flush();
-                log::info!("vlogger connection closed");
-                let mut guard = WAIT.0.lock().unwrap();
-                *guard = false;
-                WAIT.1.notify_all();
+                chatter!(info, "vlogger connection closed");
                 Ok(())
             };
             let mut byte_buf = [0u8; 64];
-            while let Ok(msg) = rx.recv() {
-                if msg.is_empty() {
-                    // this is a message to this thread, that the main thread has ended.
-                    // drop the connection to notify it that all messages have been written.
-                    return close(&mut buf_writer);
+            let mut socket = WebSocket {
+                writer: &mut buf_writer,
+                encoding,
+                encoder: wire::Encoder::new(compact),
+                frame: FrameState::new(),
+                config,
+                flushed: Instant::now(),
+                unflushed: false,
+                drops: HashMap::new(),
+                reported: Instant::now(),
+            };
+            // The last record is held back to merge it with identical records following it.
+            let mut pending: Option<(Arc<Message>, usize)> = None;
+            loop {
+                let message = match client.try_pop() {
+                    Some(message) => message,
+                    None => {
+                        // nothing to merge with right now, so send the record before waiting.
+                        if let Some((message, repeat)) = pending.take() {
+                            socket.send(&message, repeat)?;
+                        }
+                        if socket.unflushed {
+                            // wait for more messages to write them with the same flush.
+                            let budget = config
+                                .flush_interval
+                                .saturating_sub(socket.flushed.elapsed());
+                            match client.pop_timeout(budget) {
+                                Ok(message) => message,
+                                Err(RecvTimeoutError::Timeout) => {
+                                    socket.flush()?;
+                                    continue;
+                                }
+                                Err(RecvTimeoutError::Disconnected) => return close(socket.writer),
+                            }
+                        } else {
+                            match client.pop() {
+                                Some(message) => message,
+                                None => return close(socket.writer),
+                            }
+                        }
+                    }
+                };
+                // tell the client about the records dropped, because it couldn't keep up.
+                let dropped = client.take_dropped();
+                if !dropped.is_empty() {
+                    let count: u64 = dropped.values().sum();
+                    socket.unflushed = true;
+                    socket
+                        .encoding
+                        .write_frame(socket.writer, &format!("{{\"slow\":{count}}}"))?;
+                    for (surface, count) in dropped {
+                        *socket.drops.entry(surface).or_default() += count;
+                    }
                 }
                 // first check if a socket close is received
                 while let Ok(bytes) = buf_reader.read(&mut byte_buf) {
                     // don't parse it properly. Only ever expect close events to happen.
                     // if bytes = 0, the connection has ended already without the closing message.
-                    if bytes == 0 || byte_buf[..bytes].iter().any(|b| *b == 0x88) {
+                    if bytes == 0 || byte_buf[..bytes].contains(&0x88) {
                         // close the connection correctly so the server can listen for a new connection.
-                        return close(&mut buf_writer);
+                        return close(socket.writer);
                     }
                 }
-                // send message
-                if msg.len() < 126 {
-                    buf_writer.write_all(&[0x81, msg.len() as u8])?;
-                    buf_writer.write_all(msg.as_bytes())?;
-                } else if msg.len() <= u16::MAX as usize {
-                    buf_writer.write_all(&[0x81, 126])?;
-                    buf_writer.write_all(&(msg.len() as u16).to_be_bytes())?;
-                    buf_writer.write_all(msg.as_bytes())?;
-                } else {
-                    buf_writer.write_all(&[0x81, 127])?;
-                    buf_writer.write_all(&(msg.len() as u64).to_be_bytes())?;
-                    buf_writer.write_all(msg.as_bytes())?;
+                if let Message::Record { .. } = *message {
+                    match &mut pending {
+                        Some((last, repeat)) if last.same_record(&message) => {
+                            // keep the newest record, so the timestamp is up to date.
+                            *last = message;
+                            *repeat += 1;
+                        }
+                        _ => {
+                            if let Some((last, repeat)) = pending.replace((message, 1)) {
+                                socket.send(&last, repeat)?;
+                            }
+                        }
+                    }
+                    continue;
+                }
+                if let Some((last, repeat)) = pending.take() {
+                    socket.send(&last, repeat)?;
                 }
-                buf_writer.flush()?;
+                if let Message::Flush = *message {
+                    // this is a message to this thread, that the main thread has ended.
+                    // drop the connection to notify it that all messages have been written.
+                    socket.end_frame()?;
+                    return close(socket.writer);
+                }
+                socket.send(&message, 1)?;
+                socket.flush_due()?;
             }
         } else if path == "/" {
+            connection.status = 200;
             buf_writer.write_all("HTTP/1.1 200 OK\r\n\r\n".as_bytes())?;
-            buf_writer.write_all(include_bytes!("site.html"))?;
+            config.page.write(&mut buf_writer)?;
+        } else if path == "/export/html" {
+            connection.status = 200;
+            buf_writer.write_all(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Disposition: attachment; filename=\"vlog.html\"\r\n\r\n".as_bytes(),
+            )?;
+            config
+                .page
+                .write_static(&mut buf_writer, Some(&static_messages()))?;
+        } else if path == "/connections" {
+            connection.status = 200;
+            buf_writer.write_all(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n".as_bytes(),
+            )?;
+            buf_writer.write_all(audit::to_json().as_bytes())?;
+        } else if path == "/stats" {
+            connection.status = 200;
+            buf_writer.write_all(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n".as_bytes(),
+            )?;
+            buf_writer.write_all(stats::to_json().as_bytes())?;
+        } else if path == "/metrics" {
+            connection.status = 200;
+            buf_writer.write_all(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\r\n".as_bytes(),
+            )?;
+            buf_writer.write_all(stats::to_prometheus().as_bytes())?;
         } else {
+            connection.status = 404;
             buf_writer.write_all(
                 "HTTP/1.1 404 NOT FOUND\r\n\r\n<html><body>Path not found</body></html>".as_bytes(),
             )?;
         }
+    } else if get == "POST" && http == "HTTP/1.1" && path == "/ingest" {
+        let (status, response) = match content_length {
+            None => (
+                411,
+                "{\"error\":\"the Content-Length is required\"}".to_owned(),
+            ),
+            Some(len) if len > ingest::MAX_BODY => {
+                (413, "{\"error\":\"the body is too large\"}".to_owned())
+            }
+            Some(len) => {
+                let mut body = Vec::with_capacity(len);
+                (&mut buf_reader).take(len as u64).read_to_end(&mut body)?;
+                // parse before locking the vlogger, so large bodies don't block the other producers.
+                let time = config.vlogger.lock().unwrap().start.elapsed().as_secs_f64();
+                let messages = String::from_utf8(body)
+                    .map_err(|_| "the body is not valid UTF-8".to_owned())
+                    .and_then(|body| ingest::parse(&body, time, process.as_deref()));
+                match messages {
+                    Ok(messages) => {
+                        let received = messages.len();
+                        let sent = config.vlogger.lock().unwrap().ingest(messages);
+                        (200, format!("{{\"received\":{received},\"sent\":{sent}}}"))
+                    }
+                    Err(err) => (400, format!("{{\"error\":\"{}\"}}", json::Escape(&err))),
+                }
+            }
+        };
+        connection.status = status;
+        buf_writer.write_all(
+            format!(
+                "HTTP/1.1 {status} {}\r\nContent-Type: application/json\r\n\r\n{response}",
+                reason(status)
+            )
+            .as_bytes(),
+        )?;
     } else {
+        connection.status = 400;
         buf_writer.write_all("HTTP/1.1 400 BAD REQUEST\r\n\r\n".as_bytes())?;
     }
     stream.set_nonblocking(false)?;
     buf_writer.flush()?;
     Ok(())
 }
+
+/// The reason phrase of the status codes of the responses to `/ingest`.
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "BAD REQUEST",
+        403 => "FORBIDDEN",
+        404 => "NOT FOUND",
+        411 => "LENGTH REQUIRED",
+        413 => "PAYLOAD TOO LARGE",
+        415 => "UNSUPPORTED MEDIA TYPE",
+        // the reason phrase is optional.
+        _ => "",
+    }
+}
+
+/// Whether the `Content-Type` of a request is JSON, e.g. `application/json; charset=utf-8`.
+fn is_json(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("");
+    mime.trim().eq_ignore_ascii_case("application/json")
+}
+
+/// The sending half of a websocket connection to a client.
+struct WebSocket<'a, W: Write> {
+    writer: &'a mut W,
+    encoding: Encoding,
+    encoder: wire::Encoder,
+    frame: FrameState,
+    config: &'a ServerConfig,
+    /// The time of the last flush, see [`Builder::flush_interval`].
+    flushed: Instant,
+    /// True if frames were written since the last flush.
+    unflushed: bool,
+    /// The records dropped for this client per surface since the last report.
+    drops: HashMap<String, u64>,
+    /// The time of the last report of the dropped records.
+    reported: Instant,
+}
+
+impl<W: Write> WebSocket<'_, W> {
+    /// Encode and write a message, which is repeated `repeat` times, without flushing.
+    fn send(&mut self, message: &Message, repeat: usize) -> std::io::Result<()> {
+        if self.frame.start.elapsed() >= FRAME_DURATION {
+            self.end_frame()?;
+        }
+        if !self.drops.is_empty() && self.reported.elapsed() >= queue::DROPS_INTERVAL {
+            self.reported = Instant::now();
+            let notice = wire::drops(&std::mem::take(&mut self.drops));
+            self.unflushed = true;
+            self.encoding.write_frame(self.writer, &notice)?;
+        }
+        let Some((opcode, payload)) = self.encode(message, repeat) else {
+            return Ok(());
+        };
+        if let Some(budget) = &self.config.frame_budget {
+            // only records and samples are dropped. Clearing a surface must never be dropped,
+            // or the frontend would show stale visuals.
+            if let Message::Record { surface, .. } | Message::Sample { surface, .. } = message {
+                if !self.frame.admit(budget, payload.len()) {
+                    self.encoder.revert();
+                    *self.drops.entry(surface.to_string()).or_default() += 1;
+                    return Ok(());
+                }
+            }
+        }
+        self.unflushed = true;
+        write_frame(self.writer, opcode, &payload)
+    }
+    /// Encode a message in the encoding of the client. Returns the opcode and the payload of its frame.
+    fn encode(&mut self, message: &Message, repeat: usize) -> Option<(u8, Vec<u8>)> {
+        #[cfg(feature = "msgpack")]
+        if let Encoding::MsgPack = self.encoding {
+            if let Message::Record { body, .. } | Message::Sample { body, .. } = message {
+                // the body is transcoded once for all clients, so only the rest is transcoded here.
+                if let Some(fields) = body.packed() {
+                    let envelope = self.encoder.encode_envelope(message, repeat)?;
+                    if let Some(bytes) = msgpack::with_fields(&fields, &envelope) {
+                        return Some((0x82, bytes));
+                    }
+                    self.encoder.revert();
+                }
+            }
+        }
+        let msg = self.encoder.encode(message, repeat)?;
+        #[cfg(feature = "msgpack")]
+        if let Encoding::MsgPack = self.encoding {
+            if let Some(bytes) = msgpack::from_json(&msg) {
+                return Some((0x82, bytes));
+            }
+            log::error!("failed to transcode message to msgpack: {msg}");
+        }
+        Some((0x81, msg.into_bytes()))
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.unflushed = false;
+        self.flushed = Instant::now();
+        self.writer.flush()
+    }
+    /// Flush if the flush interval has passed since the last flush.
+    fn flush_due(&mut self) -> std::io::Result<()> {
+        if self.unflushed && self.flushed.elapsed() >= self.config.flush_interval {
+            self.flush()?;
+        }
+        Ok(())
+    }
+    /// End the current frame and send the truncation notice if records were dropped.
+    fn end_frame(&mut self) -> std::io::Result<()> {
+        if let Some(notice) = self.frame.next_frame() {
+            self.unflushed = true;
+            self.encoding.write_frame(self.writer, &notice)?;
+        }
+        Ok(())
+    }
+}
+
+/// The encoding of the messages on the websocket, negotiated using the websocket subprotocol.
+#[derive(Clone, Copy)]
+enum Encoding {
+    /// JSON in text frames. Used if the client doesn't request a subprotocol.
+    Json,
+    /// JSON in text frames, requested as subprotocol `vlog.json`.
+    JsonProtocol,
+    /// MessagePack in binary frames, requested as subprotocol `vlog.msgpack`.
+    #[cfg(feature = "msgpack")]
+    MsgPack,
+}
+
+impl Encoding {
+    /// Choose the encoding from the comma separated subprotocols offered by the client.
+    fn negotiate(protocols: &str) -> Self {
+        let mut encoding = Self::Json;
+        for protocol in protocols.split(',').map(str::trim) {
+            match protocol {
+                #[cfg(feature = "msgpack")]
+                "vlog.msgpack" => return Self::MsgPack,
+                "vlog.json" => encoding = Self::JsonProtocol,
+                _ => {}
+            }
+        }
+        encoding
+    }
+    /// The subprotocol to put into the handshake response.
+    fn protocol(self) -> Option<&'static str> {
+        match self {
+            Self::Json => None,
+            Self::JsonProtocol => Some("vlog.json"),
+            #[cfg(feature = "msgpack")]
+            Self::MsgPack => Some("vlog.msgpack"),
+        }
+    }
+    /// Write a JSON message in this encoding as a websocket frame without flushing.
+    fn write_frame(self, buf_writer: &mut impl Write, msg: &str) -> std::io::Result<()> {
+        #[cfg(feature = "msgpack")]
+        if let Self::MsgPack = self {
+            if let Some(bytes) = msgpack::from_json(msg) {
+                return write_frame(buf_writer, 0x82, &bytes);
+            }
+            log::error!("failed to transcode message to msgpack: {msg}");
+        }
+        write_frame(buf_writer, 0x81, msg.as_bytes())
+    }
+}
+
+/// Read a single websocket frame of at most `limit` bytes from the client.
+/// Returns the first byte of the frame with the FIN bit and the opcode, and the unmasked payload.
+fn read_frame(reader: &mut impl Read, limit: usize) -> std::io::Result<(u8, Vec<u8>)> {
+    let mut head = [0u8; 2];
+    reader.read_exact(&mut head)?;
+    let len = match head[1] & 0x7F {
+        126 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as u64
+        }
+        127 => {
+            let mut len = [0u8; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => len as u64,
+    };
+    if len > limit as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "websocket frame from the client is too large",
+        ));
+    }
+    let mut mask = [0u8; 4];
+    if head[1] & 0x80 != 0 {
+        reader.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, b) in payload.iter_mut().enumerate() {
+        *b ^= mask[i % 4];
+    }
+    Ok((head[0], payload))
+}
+
+/// Write a websocket frame with the given first byte (FIN + opcode) without flushing.
+fn write_frame(buf_writer: &mut impl Write, head: u8, msg: &[u8]) -> std::io::Result<()> {
+    stats::add_sent(msg.len());
+    let mut header = [head, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let header = if msg.len() < 126 {
+        header[1] = msg.len() as u8;
+        &header[..2]
+    } else if msg.len() <= u16::MAX as usize {
+        header[1] = 126;
+        header[2..4].copy_from_slice(&(msg.len() as u16).to_be_bytes());
+        &header[..4]
+    } else {
+        header[1] = 127;
+        header[2..].copy_from_slice(&(msg.len() as u64).to_be_bytes());
+        &header[..]
+    };
+    // write the header and the payload in a single call. A `BufWriter` copies them into its buffer,
+    // or passes payloads larger than the buffer to the socket in one vectored write without copying.
+    let written = loop {
+        match buf_writer.write_vectored(&[io::IoSlice::new(header), io::IoSlice::new(msg)]) {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            result => break result?,
+        }
+    };
+    // finish partial writes.
+    if written < header.len() {
+        buf_writer.write_all(&header[written..])?;
+        buf_writer.write_all(msg)
+    } else {
+        buf_writer.write_all(&msg[written - header.len()..])
+    }
+}
@@ -0,0 +1,159 @@
+//! Stroke options for lines beyond the styles of `v_log`.
+
+use crate::scoped::{scoped, scoped_ref};
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Write as _,
+};
+use v_log::PointStyle;
+
+thread_local! {
+    static ARROWHEADS: Cell<Option<Arrowheads>> = const { Cell::new(None) };
+    static DASH: RefCell<Option<Vec<f64>>> = const { RefCell::new(None) };
+    static VERTEX_MARKERS: RefCell<Option<(PointStyle, f64)>> = const { RefCell::new(None) };
+}
+
+/// Arrowheads at the ends of lines, see [`with_arrowheads`].
+///
+/// ```
+/// use web_vlog::Arrowheads;
+///
+/// // open arrowheads at both ends, e.g. for an undirected dependency.
+/// let both = Arrowheads::new().start(true).size(12.0).open(true);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Arrowheads {
+    start: bool,
+    end: bool,
+    size: f64,
+    open: bool,
+}
+
+impl Default for Arrowheads {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Arrowheads {
+    /// Create a filled arrowhead at the end of the line with a size of 8 pixels.
+    pub const fn new() -> Self {
+        Self {
+            start: false,
+            end: true,
+            size: 8.0,
+            open: false,
+        }
+    }
+    /// Draw an arrowhead at the start of the line, which points away from the line.
+    pub const fn start(mut self, start: bool) -> Self {
+        self.start = start;
+        self
+    }
+    /// Draw an arrowhead at the end of the line.
+    pub const fn end(mut self, end: bool) -> Self {
+        self.end = end;
+        self
+    }
+    /// Set the length and width of the arrowheads in pixels.
+    pub const fn size(mut self, size: f64) -> Self {
+        self.size = size;
+        self
+    }
+    /// Draw the arrowheads as open chevrons instead of filled triangles.
+    pub const fn open(mut self, open: bool) -> Self {
+        self.open = open;
+        self
+    }
+}
+
+/// Draw arrowheads on all lines vlogged by the current thread inside of `f`.
+///
+/// This applies to the lines of `v_log` as well as [`bezier!`](crate::bezier!) and [`spline!`](crate::spline!) paths.
+/// It is independent of [`LineStyle::Arrow`](v_log::LineStyle::Arrow), which should not be combined with it.
+///
+/// ```
+/// use v_log::polyline;
+/// use web_vlog::Arrowheads;
+///
+/// web_vlog::with_arrowheads(Arrowheads::new().open(true), || {
+///     polyline!("state machine", ([0.0, 0.0], [50.0, 20.0]), 1.0, Base, "-");
+/// });
+/// ```
+pub fn with_arrowheads<R>(arrowheads: Arrowheads, f: impl FnOnce() -> R) -> R {
+    scoped(&ARROWHEADS, arrowheads, f)
+}
+
+/// Draw all lines vlogged by the current thread inside of `f` with the dash pattern.
+///
+/// The pattern alternates between the lengths of dashes and gaps in pixels, e.g. `[6.0, 2.0, 1.0, 2.0]`
+/// for dash-dot lines, so different kinds of lines can be told apart without colors.
+/// This applies to the same lines as [`with_arrowheads`] and overrides [`LineStyle::Dashed`](v_log::LineStyle::Dashed).
+/// An empty pattern draws solid lines.
+///
+/// ```
+/// use v_log::polyline;
+///
+/// web_vlog::with_dash(&[6.0, 2.0, 1.0, 2.0], || {
+///     polyline!("signals", ([0.0, 0.0], [100.0, 0.0]), 1.0, Base, "-");
+/// });
+/// ```
+pub fn with_dash<R>(pattern: &[f64], f: impl FnOnce() -> R) -> R {
+    // non-finite and negative lengths can't be drawn, so they are dropped here.
+    let pattern = pattern
+        .iter()
+        .map(|v| if v.is_finite() { v.max(0.0) } else { 0.0 })
+        .collect();
+    scoped_ref(&DASH, pattern, f)
+}
+
+/// Draw a point with the style and size at every vertex of all lines vlogged by the current thread inside of `f`,
+/// so sampled paths show the curve and the sample locations in one record.
+///
+/// This applies to the same lines as [`with_arrowheads`]. The markers are drawn in the color of the line
+/// and the size has the same meaning as for the points of `v_log`.
+///
+/// ```
+/// use v_log::{polyline, PointStyle};
+///
+/// let samples = [[0.0, 0.0], [10.0, 4.0], [20.0, 5.0], [30.0, 9.0]];
+/// web_vlog::with_vertex_markers(PointStyle::Circle, 4.0, || {
+///     polyline!("trajectory", samples, 1.0, Base, "-");
+/// });
+/// ```
+pub fn with_vertex_markers<R>(style: PointStyle, size: f64, f: impl FnOnce() -> R) -> R {
+    scoped_ref(&VERTEX_MARKERS, (style, size), f)
+}
+
+/// Append the stroke options of the current thread to the body of a line.
+pub(crate) fn write_stroke(body: &mut String) {
+    DASH.with(|d| {
+        if let Some(pattern) = &*d.borrow() {
+            body.push_str(",\"dash\":[");
+            for (i, v) in pattern.iter().enumerate() {
+                if i > 0 {
+                    body.push(',');
+                }
+                write!(body, "{v}").unwrap();
+            }
+            body.push(']');
+        }
+    });
+    VERTEX_MARKERS.with(|m| {
+        if let Some((style, size)) = &*m.borrow() {
+            if size.is_finite() {
+                write!(body, ",\"vm\":[\"{style:?}\",{size}]").unwrap();
+            }
+        }
+    });
+    if let Some(a) = ARROWHEADS.with(Cell::get) {
+        if (a.start || a.end) && a.size.is_finite() {
+            write!(
+                body,
+                ",\"arr\":[{},{},{},{}]",
+                a.start as u8, a.end as u8, a.size, !a.open as u8
+            )
+            .unwrap();
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! Forwarding of the records of the `log` crate into the message table of a surface.
+
+use crate::{static_or_owned, Origin, VLOGGER};
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use v_log::Color;
+
+/// A [`log::Log`] implementation, which forwards the log records as text messages to the vlogger.
+///
+/// The level is shown as the color of the message, `Error`, `Warn` and `Info` map to the
+/// [`Color`]s of the same name and `Debug` and `Trace` to [`Color::Base`].
+/// The records pass the target and surface filters of the vlogger like any other record.
+/// The records of `web_vlog` itself are not forwarded.
+///
+/// ```
+/// web_vlog::init();
+/// web_vlog::LogBridge::new("log").level(log::LevelFilter::Info).init().unwrap();
+/// log::info!("shown in the message table of the surface \"log\"");
+/// ```
+#[derive(Clone, Debug)]
+pub struct LogBridge {
+    surface: String,
+    level: LevelFilter,
+}
+
+impl LogBridge {
+    /// Create a bridge, which forwards all records to the surface.
+    pub fn new(surface: &str) -> Self {
+        Self {
+            surface: surface.to_owned(),
+            level: LevelFilter::Trace,
+        }
+    }
+    /// Only forward records up to the level.
+    pub fn level(mut self, level: LevelFilter) -> Self {
+        self.level = level;
+        self
+    }
+    /// Set the bridge as the global logger of the `log` crate and set its maximal level.
+    ///
+    /// # Errors
+    ///
+    /// If the global logger has already been set, an error is returned.
+    pub fn init(self) -> Result<(), SetLoggerError> {
+        let level = self.level;
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(level);
+        Ok(())
+    }
+}
+
+impl Log for LogBridge {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level && !metadata.target().starts_with("web_vlog")
+    }
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let vlogger = VLOGGER.read().unwrap();
+        let Some(vlogger) = &*vlogger else {
+            return;
+        };
+        let color = match record.level() {
+            Level::Error => Color::Error,
+            Level::Warn => Color::Warn,
+            Level::Info => Color::Info,
+            Level::Debug | Level::Trace => Color::Base,
+        };
+        let origin = Origin {
+            target: record.target(),
+            module: static_or_owned(record.module_path_static(), record.module_path()),
+            file: static_or_owned(record.file_static(), record.file()),
+            line: record.line().unwrap_or(0),
+        };
+        vlogger.forward(origin, &self.surface, &color, *record.args());
+    }
+    fn flush(&self) {}
+}
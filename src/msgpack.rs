@@ -0,0 +1,242 @@
+//! MessagePack encoding of the wire messages, see <https://msgpack.org>.
+//!
+//! The messages are serialized as JSON by the vlogging threads and transcoded on the
+//! server threads, which keeps the cost of the encoding off the vlogging threads.
+//! The fields of the visuals are transcoded only once per message and shared by all clients,
+//! see [`Body::packed`](crate::wire::Body::packed). Only the few fields, which differ per client,
+//! like the interned strings, are transcoded for every client.
+
+use crate::json::Value;
+
+/// The fields of a JSON object transcoded to the entries of a MessagePack map.
+pub(crate) struct Fields {
+    len: usize,
+    bytes: Vec<u8>,
+}
+
+impl Fields {
+    /// Transcode the fields of a JSON object without the enclosing braces.
+    /// Returns `None` if they are not valid JSON.
+    pub fn from_json(fields: &str) -> Option<Self> {
+        let Value::Object(object) = crate::json::parse(&format!("{{{fields}}}"))? else {
+            return None;
+        };
+        let mut bytes = Vec::with_capacity(fields.len() / 2);
+        for (key, value) in &object {
+            encode_str(key, &mut bytes);
+            encode(value, &mut bytes);
+        }
+        Some(Self {
+            len: object.len(),
+            bytes,
+        })
+    }
+}
+
+/// Transcode a JSON message to MessagePack. Returns `None` if the message is not valid JSON.
+pub(crate) fn from_json(msg: &str) -> Option<Vec<u8>> {
+    let value = crate::json::parse(msg)?;
+    let mut out = Vec::with_capacity(msg.len() / 2);
+    encode(&value, &mut out);
+    Some(out)
+}
+
+/// Transcode a JSON object and add the already transcoded `fields` to it.
+/// Returns `None` if the object is not valid JSON.
+pub(crate) fn with_fields(fields: &Fields, object: &str) -> Option<Vec<u8>> {
+    let Value::Object(object) = crate::json::parse(object)? else {
+        return None;
+    };
+    let mut out = Vec::with_capacity(fields.bytes.len() + object.len() * 16 + 5);
+    encode_len(fields.len + object.len(), 0x80, 0xde, &mut out);
+    out.extend_from_slice(&fields.bytes);
+    for (key, value) in &object {
+        encode_str(key, &mut out);
+        encode(value, &mut out);
+    }
+    Some(out)
+}
+
+fn encode(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(false) => out.push(0xc2),
+        Value::Bool(true) => out.push(0xc3),
+        Value::Number(n) => encode_number(*n, out),
+        Value::String(s) => encode_str(s, out),
+        Value::Array(array) => {
+            encode_len(array.len(), 0x90, 0xdc, out);
+            for value in array {
+                encode(value, out);
+            }
+        }
+        Value::Object(object) => {
+            encode_len(object.len(), 0x80, 0xde, out);
+            for (key, value) in object {
+                encode_str(key, out);
+                encode(value, out);
+            }
+        }
+    }
+}
+
+fn encode_str(s: &str, out: &mut Vec<u8>) {
+    let len = s.len();
+    if len < 32 {
+        out.push(0xa0 | len as u8);
+    } else if len <= u8::MAX as usize {
+        out.extend_from_slice(&[0xd9, len as u8]);
+    } else if len <= u16::MAX as usize {
+        out.push(0xda);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(0xdb);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// Encode the length of an array or map, using the fix variant `fix` if possible
+/// or the 16/32 bit variants starting at `tag16`.
+fn encode_len(len: usize, fix: u8, tag16: u8, out: &mut Vec<u8>) {
+    if len < 16 {
+        out.push(fix | len as u8);
+    } else if len <= u16::MAX as usize {
+        out.push(tag16);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(tag16 + 1);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+}
+
+fn encode_number(n: f64, out: &mut Vec<u8>) {
+    if n.fract() == 0.0 && n.abs() < (1u64 << 53) as f64 {
+        let i = n as i64;
+        if (0..128).contains(&i) {
+            out.push(i as u8);
+        } else if (-32..0).contains(&i) {
+            out.push(i as i8 as u8);
+        } else if i8::try_from(i).is_ok() {
+            out.extend_from_slice(&[0xd0, i as i8 as u8]);
+        } else if let Ok(i) = i16::try_from(i) {
+            out.push(0xd1);
+            out.extend_from_slice(&i.to_be_bytes());
+        } else if let Ok(i) = i32::try_from(i) {
+            out.push(0xd2);
+            out.extend_from_slice(&i.to_be_bytes());
+        } else {
+            out.push(0xd3);
+            out.extend_from_slice(&i.to_be_bytes());
+        }
+    } else if (n as f32) as f64 == n {
+        out.push(0xca);
+        out.extend_from_slice(&(n as f32).to_be_bytes());
+    } else {
+        out.push(0xcb);
+        out.extend_from_slice(&n.to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::{Encoder, Message, Meta};
+    use std::{borrow::Cow, sync::Arc};
+
+    fn packed(json: &str) -> Vec<u8> {
+        from_json(json).unwrap()
+    }
+
+    #[test]
+    fn integers_and_floats() {
+        assert_eq!(packed("0"), [0x00]);
+        assert_eq!(packed("127"), [0x7f]);
+        assert_eq!(packed("128"), [0xd1, 0x00, 0x80]);
+        assert_eq!(packed("-1"), [0xff]);
+        assert_eq!(packed("-32"), [0xe0]);
+        assert_eq!(packed("-33"), [0xd0, 0xdf]);
+        assert_eq!(packed("-129"), [0xd1, 0xff, 0x7f]);
+        assert_eq!(packed("70000"), [0xd2, 0x00, 0x01, 0x11, 0x70]);
+        assert_eq!(
+            packed("5000000000"),
+            [0xd3, 0, 0, 0, 1, 0x2a, 0x05, 0xf2, 0]
+        );
+        assert_eq!(packed("2.0"), [0x02]);
+        assert_eq!(packed("-0"), [0x00]);
+        assert_eq!(packed("1.5"), [0xca, 0x3f, 0xc0, 0, 0]);
+        assert_eq!(packed("0.1")[0], 0xcb);
+        assert_eq!(packed("0.1")[1..], 0.1f64.to_be_bytes());
+        // integers beyond the exact range of f64 are sent as floats.
+        assert_eq!(packed("1e300")[0], 0xcb);
+        assert_eq!(packed("[null,true,false]"), [0x93, 0xc0, 0xc3, 0xc2]);
+    }
+
+    #[test]
+    fn string_lengths() {
+        for (len, header) in [
+            (0, &[0xa0][..]),
+            (31, &[0xbf]),
+            (32, &[0xd9, 32]),
+            (255, &[0xd9, 255]),
+            (256, &[0xda, 1, 0]),
+            (65535, &[0xda, 255, 255]),
+            (65536, &[0xdb, 0, 1, 0, 0]),
+        ] {
+            let s = "x".repeat(len);
+            let bytes = packed(&format!("\"{s}\""));
+            assert_eq!(&bytes[..header.len()], header, "{len}");
+            assert_eq!(&bytes[header.len()..], s.as_bytes(), "{len}");
+        }
+        // the length is in bytes, not characters.
+        assert_eq!(packed("\"äö\""), [0xa4, 0xc3, 0xa4, 0xc3, 0xb6]);
+    }
+
+    #[test]
+    fn nested_arrays_and_maps() {
+        assert_eq!(packed("[[1,[2]],[]]"), [0x92, 0x92, 0x01, 0x91, 0x02, 0x90]);
+        assert_eq!(
+            packed(r#"{"a":{"b":[1]}}"#),
+            [0x81, 0xa1, b'a', 0x81, 0xa1, b'b', 0x91, 0x01]
+        );
+        let long: Vec<_> = (0..16).map(|i| i.to_string()).collect();
+        let bytes = packed(&format!("[{}]", long.join(",")));
+        assert_eq!(bytes[..3], [0xdc, 0, 16]);
+        assert_eq!(bytes[3..], (0..16).collect::<Vec<u8>>());
+        let long: Vec<_> = (0..70000).map(|_| "0").collect();
+        assert_eq!(
+            packed(&format!("[{}]", long.join(",")))[..5],
+            [0xdd, 0, 1, 0x11, 0x70]
+        );
+    }
+
+    #[test]
+    fn shared_fields_match_the_whole_message() {
+        let message = Message::Record {
+            surface: "map".into(),
+            meta: Meta {
+                target: "my_crate".into(),
+                module: Cow::Borrowed("my_crate"),
+                file: Cow::Borrowed("src/main.rs"),
+                line: 3,
+                time: 0.25,
+                wall: 1000,
+                tid: 1,
+                thread: Arc::from("main"),
+            },
+            body: r#""lbl":"a","pos":[1.5,-2,0],"size":3"#.to_owned().into(),
+        };
+        let Message::Record { body, .. } = &message else {
+            unreachable!()
+        };
+        let fields = body.packed().unwrap();
+        for repeat in [1, 2] {
+            let (mut whole, mut parts) = (Encoder::new(true), Encoder::new(true));
+            for _ in 0..2 {
+                let expected = from_json(&whole.encode(&message, repeat).unwrap()).unwrap();
+                let envelope = parts.encode_envelope(&message, repeat).unwrap();
+                assert_eq!(with_fields(&fields, &envelope).unwrap(), expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Fanning out records to multiple vlogger implementations.
+
+use crate::{InitError, WebVLogger, VLOGGER};
+use v_log::{Metadata, Record, VLog};
+
+/// A vlogger, which sends every record to a [`WebVLogger`] and to other [`VLog`] implementations,
+/// e.g. a file recorder next to the browser view.
+///
+/// Every record is sent to all vloggers, which are enabled for it. The shape macros of this
+/// crate, e.g. [`polygon!`](crate::polygon!), only reach the [`WebVLogger`].
+///
+/// ```
+/// use v_log::{Metadata, Record, VLog};
+///
+/// /// Prints the text messages to stdout.
+/// struct Stdout;
+///
+/// impl VLog for Stdout {
+///     fn enabled(&self, _: &Metadata) -> bool {
+///         true
+///     }
+///     fn vlog(&self, record: &Record) {
+///         println!("{}: {}", record.surface(), record.args());
+///     }
+///     fn clear(&self, _: &str) {}
+///     fn flush(&self) {}
+/// }
+///
+/// let web = web_vlog::Builder::new().build().unwrap();
+/// let port = web_vlog::MultiVLogger::new(web).also(Box::new(Stdout)).set_global().unwrap();
+/// ```
+pub struct MultiVLogger {
+    web: WebVLogger,
+    others: Vec<Box<dyn VLog>>,
+}
+
+impl MultiVLogger {
+    /// Create a vlogger, which only sends the records to the [`WebVLogger`].
+    pub fn new(web: WebVLogger) -> Self {
+        Self {
+            web,
+            others: vec![],
+        }
+    }
+    /// Additionally send the records to `vlogger`.
+    pub fn also(mut self, vlogger: Box<dyn VLog>) -> Self {
+        self.others.push(vlogger);
+        self
+    }
+    /// Set this vlogger as the global vlogger for [`v_log`] and return the port of the server
+    /// of the [`WebVLogger`].
+    ///
+    /// # Errors
+    ///
+    /// If the global vlogger has already been set an [`InitError::SetVLoggerError`] is returned
+    /// and the server is shut down.
+    pub fn set_global(self) -> Result<u16, InitError> {
+        let web = self.web.clone();
+        if let Err(err) = v_log::set_boxed_vlogger(Box::new(self)) {
+            web.shutdown();
+            return Err(err.into());
+        }
+        let port = web.port();
+        *VLOGGER.write().unwrap() = Some(web);
+        Ok(port)
+    }
+}
+
+impl VLog for MultiVLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.web.enabled(metadata) || self.others.iter().any(|v| v.enabled(metadata))
+    }
+    fn vlog(&self, record: &Record) {
+        // the vloggers check if they are enabled themselves.
+        self.web.vlog(record);
+        for vlogger in &self.others {
+            if vlogger.enabled(record.metadata()) {
+                vlogger.vlog(record);
+            }
+        }
+    }
+    fn clear(&self, surface: &str) {
+        VLog::clear(&self.web, surface);
+        for vlogger in &self.others {
+            vlogger.clear(surface);
+        }
+    }
+    fn flush(&self) {
+        self.web.flush();
+        for vlogger in &self.others {
+            vlogger.flush();
+        }
+    }
+}
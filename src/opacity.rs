@@ -0,0 +1,36 @@
+//! Translucency of visuals.
+
+use crate::scoped::scoped;
+use std::cell::Cell;
+
+thread_local! {
+    static OPACITY: Cell<Option<f64>> = const { Cell::new(None) };
+}
+
+/// Draw all visuals vlogged by the current thread inside of `f` with the opacity,
+/// where 0 is invisible and 1 is opaque, so overlapping layers remain readable.
+///
+/// The opacity applies to the whole visual, including its fill and label, and is combined with
+/// the alpha channel of [`Color::Hex`](v_log::Color::Hex). Text messages in the message table are not affected.
+///
+/// ```
+/// use v_log::point;
+///
+/// web_vlog::with_opacity(0.3, || {
+///     point!("grid", [1.0, 2.0], 5.0, Base);
+/// });
+/// ```
+pub fn with_opacity<R>(opacity: f64, f: impl FnOnce() -> R) -> R {
+    // NaN is treated as opaque, so it can't break the message.
+    let opacity = if opacity.is_nan() {
+        1.0
+    } else {
+        opacity.clamp(0.0, 1.0)
+    };
+    scoped(&OPACITY, opacity, f)
+}
+
+/// The opacity set with [`with_opacity`] on the current thread.
+pub(crate) fn current() -> Option<f64> {
+    OPACITY.with(Cell::get)
+}
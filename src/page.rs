@@ -0,0 +1,121 @@
+//! The served viewer page with the customizations of the [`Builder`](crate::Builder).
+
+use crate::json::Escape;
+#[cfg(feature = "dev-frontend")]
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+/// The embedded viewer page.
+const SITE: &str = include_str!("site.html");
+/// The default title in [`SITE`], which is replaced by a custom title.
+const TITLE: &str = "<title>WebVLogger</title>";
+/// The end of the head in [`SITE`], before which the custom styles are inserted.
+const HEAD_END: &str = "</head>";
+
+/// The viewer page from the disk, so changes to it show up on reload without recompiling.
+///
+/// The page is read from the path in the environment variable `WEB_VLOG_SITE` or else from `src/site.html`
+/// of this crate. If it can't be read or lacks the title or the end of the head, the embedded page is used.
+#[cfg(feature = "dev-frontend")]
+fn site() -> Cow<'static, str> {
+    let path = std::env::var_os("WEB_VLOG_SITE").map_or_else(
+        || concat!(env!("CARGO_MANIFEST_DIR"), "/src/site.html").into(),
+        std::path::PathBuf::from,
+    );
+    match std::fs::read_to_string(&path) {
+        Ok(site) if site.contains(TITLE) && site.contains(HEAD_END) => Cow::Owned(site),
+        Ok(_) => {
+            log::error!(
+                "the viewer page {} lacks {TITLE} or {HEAD_END}, serving the embedded page",
+                path.display()
+            );
+            Cow::Borrowed(SITE)
+        }
+        Err(err) => {
+            log::error!(
+                "failed to read the viewer page {}: {err}, serving the embedded page",
+                path.display()
+            );
+            Cow::Borrowed(SITE)
+        }
+    }
+}
+
+/// The embedded viewer page.
+#[cfg(not(feature = "dev-frontend"))]
+fn site() -> &'static str {
+    SITE
+}
+
+/// The customizations of the viewer page.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Page {
+    /// The title of the page, see [`Builder::title`](crate::Builder::title).
+    pub title: Option<String>,
+    /// The custom styles, see [`Builder::custom_css`](crate::Builder::custom_css).
+    pub css: String,
+    /// The custom scripts, see [`Builder::inject_script`](crate::Builder::inject_script).
+    pub scripts: String,
+}
+
+impl Page {
+    /// Write the page with the customizations.
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        self.write_static(out, None)
+    }
+    /// Write the page with the customizations. If `messages` are given, the page shows them
+    /// instead of connecting to the server, so it can be opened as a standalone file.
+    pub fn write_static(
+        &self,
+        out: &mut impl Write,
+        messages: Option<&[String]>,
+    ) -> io::Result<()> {
+        let site = site();
+        let (head, rest) = site.split_once(TITLE).unwrap();
+        out.write_all(head.as_bytes())?;
+        match &self.title {
+            Some(title) => write!(out, "<title>{}</title>", HtmlEscape(title))?,
+            None => out.write_all(TITLE.as_bytes())?,
+        }
+        let (head, body) = rest.split_once(HEAD_END).unwrap();
+        out.write_all(head.as_bytes())?;
+        if !self.css.is_empty() {
+            // the styles come last to override the defaults and must not end the style element.
+            write!(out, "<style>{}</style>", self.css.replace("</", "<\\/"))?;
+        }
+        if !self.scripts.is_empty() {
+            // the scripts come after the viewer script, so the hooks are available.
+            let scripts = self.scripts.replace("</script", "<\\/script");
+            write!(out, "<script>{scripts}</script>")?;
+        }
+        if let Some(messages) = messages {
+            out.write_all(b"<script>VS=[")?;
+            for (i, msg) in messages.iter().enumerate() {
+                let msg = Escape(msg).to_string().replace("</", "<\\/");
+                write!(out, "{}\"{msg}\"", if i > 0 { "," } else { "" })?;
+            }
+            out.write_all(b"]</script>")?;
+        }
+        out.write_all(HEAD_END.as_bytes())?;
+        out.write_all(body.as_bytes())
+    }
+}
+
+/// Escapes the characters with a special meaning in html text.
+pub(crate) struct HtmlEscape<'a>(pub &'a str);
+
+impl std::fmt::Display for HtmlEscape<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut rest = self.0;
+        while let Some(i) = rest.find(['&', '<', '>']) {
+            f.write_str(&rest[..i])?;
+            f.write_str(match rest.as_bytes()[i] {
+                b'&' => "&amp;",
+                b'<' => "&lt;",
+                _ => "&gt;",
+            })?;
+            rest = &rest[i + 1..];
+        }
+        f.write_str(rest)
+    }
+}
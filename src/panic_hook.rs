@@ -0,0 +1,99 @@
+//! Reporting of panics to the viewer, see [`install_panic_hook`].
+
+use crate::{json::Escape, wire::Message, Origin, WebVLogger, CLIENTS, QUEUED, VLOGGER, WAIT};
+use std::{
+    backtrace::Backtrace,
+    borrow::Cow,
+    fmt::Write as _,
+    sync::atomic::Ordering,
+    thread,
+    time::{Duration, Instant},
+};
+use v_log::Color;
+
+/// The surface the panics are vlogged on.
+const ERRORS: &str = "errors";
+
+/// The longest time the hook waits for the panic to be sent to the clients.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Install a panic hook, which vlogs every panic with its message, location and backtrace
+/// as an error message on the surface `errors` before calling the previously installed hook.
+///
+/// When the panic ends the process, i.e. on the main thread or with `panic = "abort"`, the hook
+/// waits until the message was sent and closes the connections, like [`v_log::VLog::flush`].
+/// Otherwise it only waits for the message to leave the queue, so the clients stay connected.
+/// The message links to the location of the panic and bypasses the target filters.
+///
+/// ```
+/// web_vlog::validate_init();
+/// web_vlog::install_panic_hook();
+/// let result = std::thread::spawn(|| panic!("the robot fell over")).join();
+/// assert!(result.is_err());
+/// ```
+pub fn install_panic_hook() {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // the vlogger might be locked by the panicking thread, so it is skipped instead of deadlocking.
+        if let Ok(vlogger) = VLOGGER.try_read() {
+            if let Some(vlogger) = &*vlogger {
+                let payload = info.payload();
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("Box<dyn Any>");
+                let thread = thread::current();
+                let mut text =
+                    format!("thread '{}' panicked", thread.name().unwrap_or("<unnamed>"));
+                if let Some(location) = info.location() {
+                    write!(text, " at {location}").unwrap();
+                }
+                write!(
+                    text,
+                    ":\n{message}\nstack backtrace:\n{}",
+                    Backtrace::force_capture()
+                )
+                .unwrap();
+                let origin = Origin {
+                    target: "panic",
+                    module: Cow::Borrowed(""),
+                    file: info
+                        .location()
+                        .map_or(Cow::Borrowed(""), |l| Cow::Owned(l.file().to_owned())),
+                    line: info.location().map_or(0, |l| l.line()),
+                };
+                let body = format!("\"msg\":\"{}\"", Escape(&text));
+                if vlogger.watched() {
+                    vlogger.emit(origin, ERRORS, &Color::Error, true, body);
+                    let ends = cfg!(panic = "abort") || thread.name() == Some("main");
+                    deliver(vlogger, ends);
+                }
+            }
+        }
+        hook(info);
+    }));
+}
+
+/// Wait until the queued messages are sent, but at most for the [`DELIVERY_TIMEOUT`].
+/// If `close` is true, the connections are closed afterwards.
+fn deliver(vlogger: &WebVLogger, close: bool) {
+    // without a client, the messages wait in the queue for the next one.
+    if CLIENTS.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    if close {
+        if let Ok(lock) = WAIT.0.try_lock() {
+            if vlogger.send(Message::Flush) {
+                let _lock = WAIT
+                    .1
+                    .wait_timeout_while(lock, DELIVERY_TIMEOUT, |v| *v > 0);
+            }
+        }
+        return;
+    }
+    let start = Instant::now();
+    while QUEUED.load(Ordering::Relaxed) > 0 && start.elapsed() < DELIVERY_TIMEOUT {
+        thread::sleep(Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,295 @@
+//! A `plotters` backend drawing onto a surface, see [`VLogBackend`].
+
+use crate::{
+    json::{Escape, Pos},
+    shape::{self, ImageData, Location, PathSegment, Shape, ShapeStyle},
+    Origin, VLOGGER,
+};
+use plotters_backend::{
+    text_anchor::{HPos, VPos},
+    BackendColor, BackendCoord, BackendStyle, BackendTextStyle, DrawingBackend, DrawingErrorKind,
+};
+use std::{borrow::Cow, convert::Infallible};
+use v_log::Color;
+
+/// A [`plotters_backend::DrawingBackend`], which draws the charts of `plotters` onto a surface.
+///
+/// The primitives of `plotters` become the visuals of web-vlog, e.g. lines become paths, circles
+/// ellipses and texts labels, so the charts stay sharp when zooming in the viewer. The coordinates
+/// are the pixels of the backend with the y axis pointing down, like in the default view of a surface.
+/// The visuals pass the target and surface filters like any other record. Their target defaults to
+/// `plotters` and they link to the line where the backend was created.
+///
+/// The first drawing after [`present`](DrawingBackend::present) clears the surface, so a chart, which
+/// is redrawn and presented in a loop, is shown live. Rotated texts, e.g. the descriptions of y axes,
+/// are drawn horizontally.
+///
+/// ```
+/// use plotters::prelude::*;
+///
+/// web_vlog::init();
+/// let root = web_vlog::VLogBackend::new("chart", (640, 480)).into_drawing_area();
+/// let mut chart = ChartBuilder::on(&root)
+///     .margin(10)
+///     .x_label_area_size(30)
+///     .y_label_area_size(40)
+///     .build_cartesian_2d(0.0..10.0, -1.0..1.0)
+///     .unwrap();
+/// chart.configure_mesh().draw().unwrap();
+/// chart
+///     .draw_series(LineSeries::new((0..100).map(|i| (i as f64 * 0.1, (i as f64 * 0.1).sin())), &RED))
+///     .unwrap();
+/// root.present().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct VLogBackend {
+    surface: String,
+    target: String,
+    size: (u32, u32),
+    file: &'static str,
+    line: u32,
+    /// Whether the surface was cleared since the last `present`.
+    prepared: bool,
+}
+
+impl VLogBackend {
+    /// Create a backend, which draws a chart of `size` `(width, height)` pixels onto the surface.
+    #[track_caller]
+    pub fn new(surface: &str, size: (u32, u32)) -> Self {
+        let caller = std::panic::Location::caller();
+        Self {
+            surface: surface.to_owned(),
+            target: "plotters".to_owned(),
+            size,
+            file: caller.file(),
+            line: caller.line(),
+            prepared: false,
+        }
+    }
+    /// Set the target of the visuals for the target filters.
+    pub fn target(mut self, target: &str) -> Self {
+        self.target = target.to_owned();
+        self
+    }
+    fn vlog(&self, shape: Shape, color: BackendColor, width: u32, fill: bool) {
+        if color.alpha == 0.0 {
+            return;
+        }
+        let color = to_color(color);
+        shape::vlog(
+            &Location {
+                target: &self.target,
+                module: "",
+                file: self.file,
+                line: self.line,
+            },
+            &self.surface,
+            shape,
+            &ShapeStyle {
+                stroke: color,
+                fill: fill.then_some(color),
+                width: width as f64,
+            },
+            format_args!(""),
+        );
+    }
+}
+
+/// Convert a color of `plotters` into a hex color with its opacity.
+fn to_color(color: BackendColor) -> Color {
+    let (r, g, b) = color.rgb;
+    let a = (color.alpha.clamp(0.0, 1.0) * 255.0).round() as u32;
+    Color::Hex((r as u32) << 24 | (g as u32) << 16 | (b as u32) << 8 | a)
+}
+
+fn point((x, y): BackendCoord) -> [f64; 2] {
+    [x as f64, y as f64]
+}
+
+impl DrawingBackend for VLogBackend {
+    type ErrorType = Infallible;
+
+    fn get_size(&self) -> (u32, u32) {
+        self.size
+    }
+    fn ensure_prepared(&mut self) -> Result<(), DrawingErrorKind<Infallible>> {
+        if !self.prepared {
+            v_log::clear!(self.surface.as_str());
+            self.prepared = true;
+        }
+        Ok(())
+    }
+    fn present(&mut self) -> Result<(), DrawingErrorKind<Infallible>> {
+        self.prepared = false;
+        Ok(())
+    }
+    fn draw_pixel(
+        &mut self,
+        point: BackendCoord,
+        color: BackendColor,
+    ) -> Result<(), DrawingErrorKind<Infallible>> {
+        let [x, y] = self::point(point);
+        let shape = Shape::Rect {
+            center: [x + 0.5, y + 0.5],
+            size: [1.0, 1.0],
+            rotation: 0.0,
+        };
+        self.vlog(shape, color, 0, true);
+        Ok(())
+    }
+    fn draw_line<S: BackendStyle>(
+        &mut self,
+        from: BackendCoord,
+        to: BackendCoord,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<Infallible>> {
+        let shape = Shape::Path {
+            start: point(from),
+            segments: vec![PathSegment::Line(point(to))],
+        };
+        self.vlog(shape, style.color(), style.stroke_width(), false);
+        Ok(())
+    }
+    fn draw_rect<S: BackendStyle>(
+        &mut self,
+        upper_left: BackendCoord,
+        bottom_right: BackendCoord,
+        style: &S,
+        fill: bool,
+    ) -> Result<(), DrawingErrorKind<Infallible>> {
+        let ([x0, y0], [x1, y1]) = (point(upper_left), point(bottom_right));
+        let shape = Shape::Rect {
+            center: [(x0 + x1) / 2.0, (y0 + y1) / 2.0],
+            size: [x1 - x0, y1 - y0],
+            rotation: 0.0,
+        };
+        self.vlog(shape, style.color(), style.stroke_width(), fill);
+        Ok(())
+    }
+    fn draw_path<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(
+        &mut self,
+        path: I,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<Infallible>> {
+        let mut path = path.into_iter().map(point);
+        let Some(start) = path.next() else {
+            return Ok(());
+        };
+        let shape = Shape::Path {
+            start,
+            segments: path.map(PathSegment::Line).collect(),
+        };
+        self.vlog(shape, style.color(), style.stroke_width(), false);
+        Ok(())
+    }
+    fn draw_circle<S: BackendStyle>(
+        &mut self,
+        center: BackendCoord,
+        radius: u32,
+        style: &S,
+        fill: bool,
+    ) -> Result<(), DrawingErrorKind<Infallible>> {
+        let shape = Shape::Ellipse {
+            center: point(center),
+            radii: [radius as f64; 2],
+            rotation: 0.0,
+        };
+        self.vlog(shape, style.color(), style.stroke_width(), fill);
+        Ok(())
+    }
+    fn fill_polygon<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(
+        &mut self,
+        vert: I,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<Infallible>> {
+        let points: Vec<_> = vert.into_iter().map(point).collect();
+        if points.len() > 2 {
+            self.vlog(Shape::Polygon(points), style.color(), 0, true);
+        }
+        Ok(())
+    }
+    fn draw_text<TStyle: BackendTextStyle>(
+        &mut self,
+        text: &str,
+        style: &TStyle,
+        pos: BackendCoord,
+    ) -> Result<(), DrawingErrorKind<Infallible>> {
+        let color = style.color();
+        if color.alpha == 0.0 {
+            return Ok(());
+        }
+        let vlogger = VLOGGER.read().unwrap();
+        let Some(vlogger) = &*vlogger else {
+            return Ok(());
+        };
+        let color = to_color(color);
+        if !vlogger.accepts(&self.target, &self.surface)
+            || !vlogger.admit(&self.target, &self.surface, &color, false)
+        {
+            return Ok(());
+        }
+        // the viewer centers the labels vertically, so the other anchors are shifted by half the height.
+        let size = style.size();
+        let [x, mut y] = point(pos);
+        let anchor = style.anchor();
+        match anchor.v_pos {
+            VPos::Top => y += size / 2.0,
+            VPos::Center => {}
+            VPos::Bottom => y -= size / 2.0,
+        }
+        let align = match anchor.h_pos {
+            HPos::Left => 0,
+            HPos::Center => 1,
+            HPos::Right => 2,
+        };
+        let body = format!(
+            "\"lbl\":\"{}\",\"pos\":{},\"align\":{align},\"size\":{size}",
+            Escape(text),
+            Pos([x, y])
+        );
+        let origin = Origin {
+            target: &self.target,
+            module: Cow::Borrowed(""),
+            file: Cow::Borrowed(self.file),
+            line: self.line,
+        };
+        vlogger.emit(origin, &self.surface, &color, false, body);
+        Ok(())
+    }
+    fn estimate_text_size<TStyle: BackendTextStyle>(
+        &self,
+        text: &str,
+        style: &TStyle,
+    ) -> Result<(u32, u32), DrawingErrorKind<Infallible>> {
+        // the fonts of the browser are unknown, so the size is estimated from an average character width.
+        let size = style.size();
+        let width = text.chars().count() as f64 * size * 0.6;
+        Ok((width.ceil() as u32, size.ceil() as u32))
+    }
+    fn blit_bitmap(
+        &mut self,
+        pos: BackendCoord,
+        (width, height): (u32, u32),
+        src: &[u8],
+    ) -> Result<(), DrawingErrorKind<Infallible>> {
+        let pixels = src
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+            .collect();
+        let shape = Shape::Image {
+            min: point(pos),
+            size: [width as f64, height as f64],
+            data: ImageData::Rgba {
+                width,
+                height,
+                pixels,
+            },
+        };
+        let white = BackendColor {
+            alpha: 1.0,
+            rgb: (255, 255, 255),
+        };
+        self.vlog(shape, white, 0, false);
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+//! Priorities of records for the selective dropping under load.
+
+use crate::scoped::scoped;
+use std::cell::Cell;
+use v_log::Color;
+
+/// The priority of a record, see [`Builder::queue_limit`](crate::Builder::queue_limit).
+///
+/// By default records with [`Color::Error`] have high priority, text messages and
+/// records with [`Color::Warn`] have normal priority and all other visuals have low priority.
+/// The default can be overridden with [`with_priority`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Dropped first when the queue exceeds the limit.
+    Low,
+    /// Dropped when the queue exceeds twice the limit.
+    Normal,
+    /// Never dropped.
+    High,
+}
+
+thread_local! {
+    static PRIORITY: Cell<Option<Priority>> = const { Cell::new(None) };
+}
+
+/// Set the priority of all records vlogged by the current thread inside of `f`.
+///
+/// ```
+/// use v_log::point;
+/// use web_vlog::Priority;
+///
+/// // this point must not be dropped, even if the viewer can't keep up.
+/// web_vlog::with_priority(Priority::High, || {
+///     point!("path", [1.0, 2.0], 5.0, Base);
+/// });
+/// ```
+pub fn with_priority<R>(priority: Priority, f: impl FnOnce() -> R) -> R {
+    scoped(&PRIORITY, priority, f)
+}
+
+impl Priority {
+    /// The priority of a visual with the color on the current thread.
+    pub(crate) fn of(color: &Color, message: bool) -> Self {
+        if let Some(priority) = PRIORITY.with(Cell::get) {
+            return priority;
+        }
+        match color {
+            Color::Error => Self::High,
+            Color::Warn => Self::Normal,
+            _ if message => Self::Normal,
+            _ => Self::Low,
+        }
+    }
+    /// Check if a record with this priority is sent with `queued` messages in the queue.
+    pub(crate) fn admit(self, queued: usize, limit: usize) -> bool {
+        match self {
+            Self::Low => queued < limit,
+            Self::Normal => queued < limit.saturating_mul(2),
+            Self::High => true,
+        }
+    }
+}
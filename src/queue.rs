@@ -0,0 +1,424 @@
+//! The send queues of the connected clients.
+//!
+//! A dispatcher thread takes the messages from the vlogging threads and copies them into
+//! a bounded queue per client, so a slow client only delays itself. When its queue is full,
+//! records and samples for this client are dropped and counted, while other messages are still queued.
+//! A client, which has twice the limit waiting and didn't take a message for [`STALLED`], is disconnected.
+//!
+//! Clearing a surface hides everything vlogged on it before, so the messages of the surface,
+//! which still wait in the queue, are discarded. A client, which falls behind an animation,
+//! then skips the obsolete frames and stays live, like the viewer does with its own queue.
+//! The viewer keeps the inspected values when a surface is cleared, so they are never
+//! discarded, and once [frames are marked](crate::frame) nothing is discarded, as the viewer keeps the history.
+//!
+//! The records dropped on the vlogging threads by the [`Builder::queue_limit`](crate::Builder::queue_limit)
+//! or the [`RateLimit`](crate::RateLimit) are counted per surface and the dispatcher sends the counts to all
+//! clients every [`DROPS_INTERVAL`]. The records dropped for a single client are reported by its connection.
+
+use crate::{
+    channel::{Receiver, RecvTimeoutError},
+    frame, snapshot, stats,
+    wire::{self, Message},
+    Packet, CLIENTS, QUEUED, WAIT,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{atomic::Ordering, Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+/// How often the dispatcher checks if the server was stopped while clients are connected.
+const POLL: Duration = Duration::from_millis(100);
+
+/// The time without progress, after which a client with a full queue is disconnected.
+const STALLED: Duration = Duration::from_secs(5);
+
+/// How often the clients are told about the records dropped per surface.
+pub(crate) const DROPS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The default number of messages waiting for a client, see [`Builder::client_queue`](crate::Builder::client_queue).
+pub(crate) const DEFAULT_LIMIT: usize = 4096;
+
+/// Check if the message is an inspected value, which the viewer keeps when its surface is cleared.
+fn is_inspected(message: &Message) -> bool {
+    matches!(message, Message::Record { body, .. } if body.starts_with("\"insp\":"))
+}
+
+struct QueueState {
+    messages: VecDeque<Arc<Message>>,
+    /// The number of records dropped per surface since the client was last notified.
+    dropped: HashMap<String, u64>,
+    /// The time the client last took a message.
+    taken: Instant,
+    closed: bool,
+}
+
+/// The messages waiting to be sent to a single client.
+struct ClientQueue {
+    state: Mutex<QueueState>,
+    ready: Condvar,
+    limit: usize,
+}
+
+impl ClientQueue {
+    fn push(&self, message: Arc<Message>) {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return;
+        }
+        if let (Message::Clear(surface), false) = (&*message, frame::marked()) {
+            let mut coalesced = 0;
+            state.messages.retain(|queued| {
+                let keep = queued.surface() != Some(surface) || is_inspected(queued);
+                if !keep && !matches!(**queued, Message::Clear(_)) {
+                    coalesced += 1;
+                }
+                keep
+            });
+            stats::add_coalesced(coalesced);
+        }
+        if state.messages.len() >= self.limit {
+            if let Message::Record { surface, .. } | Message::Sample { surface, .. } = &*message {
+                *state.dropped.entry(surface.to_string()).or_default() += 1;
+                stats::add_client_dropped();
+                return;
+            }
+            if state.messages.len() >= self.limit * 2 && state.taken.elapsed() >= STALLED {
+                chatter!(warn, "vlogging client can't keep up, disconnecting it");
+                state.closed = true;
+                state.messages.clear();
+                self.ready.notify_all();
+                return;
+            }
+        }
+        state.messages.push_back(message);
+        self.ready.notify_all();
+    }
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.ready.notify_all();
+    }
+}
+
+struct ClientsState {
+    queues: Vec<Arc<ClientQueue>>,
+    stopped: bool,
+}
+
+/// The queues of all connected clients of a server.
+pub(crate) struct Clients {
+    state: Mutex<ClientsState>,
+    changed: Condvar,
+    limit: usize,
+}
+
+impl Clients {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            state: Mutex::new(ClientsState {
+                queues: vec![],
+                stopped: false,
+            }),
+            changed: Condvar::new(),
+            limit: limit.max(1),
+        }
+    }
+    /// Add a queue for a new client, which receives all messages from now on.
+    pub(crate) fn register(self: &Arc<Self>) -> Client {
+        let queue = Arc::new(ClientQueue {
+            state: Mutex::new(QueueState {
+                messages: VecDeque::new(),
+                dropped: HashMap::new(),
+                taken: Instant::now(),
+                closed: false,
+            }),
+            ready: Condvar::new(),
+            limit: self.limit,
+        });
+        self.state.lock().unwrap().queues.push(queue.clone());
+        self.changed.notify_all();
+        let mut guard = WAIT.0.lock().unwrap();
+        *guard += 1;
+        CLIENTS.store(*guard, Ordering::Relaxed);
+        WAIT.1.notify_all();
+        Client {
+            clients: self.clone(),
+            queue,
+        }
+    }
+    /// Wait until a client is connected. Returns false if the server was stopped before.
+    pub(crate) fn wait_for_client(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        let state = self
+            .changed
+            .wait_while(state, |s| s.queues.is_empty() && !s.stopped)
+            .unwrap();
+        !state.stopped
+    }
+    /// The number of connected clients.
+    pub(crate) fn count(&self) -> usize {
+        self.state.lock().unwrap().queues.len()
+    }
+    /// Wait while the number of connected clients fulfills the condition, but at most for `timeout`
+    /// and not after the server was stopped. Returns true if it timed out.
+    pub(crate) fn wait_timeout_while(
+        &self,
+        timeout: Duration,
+        mut condition: impl FnMut(usize) -> bool,
+    ) -> bool {
+        let state = self.state.lock().unwrap();
+        let (_state, waited) = self
+            .changed
+            .wait_timeout_while(state, timeout, |s| !s.stopped && condition(s.queues.len()))
+            .unwrap();
+        waited.timed_out()
+    }
+    /// Stop the dispatcher and close the queues of all clients.
+    pub(crate) fn stop(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.stopped = true;
+        for queue in &state.queues {
+            queue.close();
+        }
+        self.changed.notify_all();
+    }
+    /// Copy the messages into the queues of all clients until the server is stopped.
+    pub(crate) fn dispatch(&self, rx: Receiver<Packet>) {
+        // the messages received when no client is connected wait for the next client.
+        // They still count as queued, so the queue limit applies to them.
+        let mut held: VecDeque<Arc<Message>> = VecDeque::new();
+        let mut reported = Instant::now();
+        loop {
+            // the retained state is kept without clients, so the messages are taken from the queue anyway.
+            let retaining = snapshot::enabled();
+            {
+                let state = self.state.lock().unwrap();
+                let state = if retaining {
+                    state
+                } else {
+                    // wait for a client, so the messages vlogged before the first connection aren't lost.
+                    self.changed
+                        .wait_while(state, |s| s.queues.is_empty() && !s.stopped)
+                        .unwrap()
+                };
+                if state.stopped {
+                    break;
+                }
+                if !state.queues.is_empty() {
+                    if reported.elapsed() >= DROPS_INTERVAL {
+                        reported = Instant::now();
+                        let drops = stats::take_surface_drops();
+                        if !drops.is_empty() {
+                            let message = Arc::new(Message::Setting(wire::drops(&drops)));
+                            for queue in &state.queues {
+                                queue.push(message.clone());
+                            }
+                        }
+                    }
+                    for message in held.drain(..) {
+                        QUEUED.fetch_sub(1, Ordering::Relaxed);
+                        for queue in &state.queues {
+                            queue.push(message.clone());
+                        }
+                    }
+                }
+            }
+            let packet = match rx.recv_timeout(POLL) {
+                Ok(packet) => packet,
+                Err(RecvTimeoutError::Timeout) => {
+                    // take the periodic snapshots while nothing is vlogged.
+                    if retaining {
+                        snapshot::tick();
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            stats::add_received();
+            #[cfg(feature = "self-profile")]
+            stats::add_queue_wait(packet.queued.elapsed());
+            if packet.retain {
+                snapshot::retain(&packet.message);
+            }
+            held.push_back(Arc::new(packet.message));
+        }
+        self.stop();
+    }
+}
+
+/// The queue of a connected client. Dropping it removes the client.
+pub(crate) struct Client {
+    clients: Arc<Clients>,
+    queue: Arc<ClientQueue>,
+}
+
+impl Client {
+    /// The next message if one is waiting.
+    pub(crate) fn try_pop(&self) -> Option<Arc<Message>> {
+        let mut state = self.queue.state.lock().unwrap();
+        state.taken = Instant::now();
+        state.messages.pop_front()
+    }
+    /// Wait for the next message. Returns `None` if the client should be disconnected.
+    pub(crate) fn pop(&self) -> Option<Arc<Message>> {
+        let state = self.queue.state.lock().unwrap();
+        let mut state = self
+            .queue
+            .ready
+            .wait_while(state, |s| s.messages.is_empty() && !s.closed)
+            .unwrap();
+        state.taken = Instant::now();
+        state.messages.pop_front()
+    }
+    /// Wait for the next message for at most `timeout`.
+    pub(crate) fn pop_timeout(&self, timeout: Duration) -> Result<Arc<Message>, RecvTimeoutError> {
+        let state = self.queue.state.lock().unwrap();
+        let (mut state, _) = self
+            .queue
+            .ready
+            .wait_timeout_while(state, timeout, |s| s.messages.is_empty() && !s.closed)
+            .unwrap();
+        state.taken = Instant::now();
+        match state.messages.pop_front() {
+            Some(message) => Ok(message),
+            None if state.closed => Err(RecvTimeoutError::Disconnected),
+            None => Err(RecvTimeoutError::Timeout),
+        }
+    }
+    /// The number of records dropped per surface for this client since the last call.
+    pub(crate) fn take_dropped(&self) -> HashMap<String, u64> {
+        std::mem::take(&mut self.queue.state.lock().unwrap().dropped)
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.queue.close();
+        let mut state = self.clients.state.lock().unwrap();
+        state
+            .queues
+            .retain(|queue| !Arc::ptr_eq(queue, &self.queue));
+        drop(state);
+        self.clients.changed.notify_all();
+        let mut guard = WAIT.0.lock().unwrap();
+        *guard -= 1;
+        CLIENTS.store(*guard, Ordering::Relaxed);
+        WAIT.1.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(limit: usize) -> ClientQueue {
+        ClientQueue {
+            state: Mutex::new(QueueState {
+                messages: VecDeque::new(),
+                dropped: HashMap::new(),
+                taken: Instant::now(),
+                closed: false,
+            }),
+            ready: Condvar::new(),
+            limit,
+        }
+    }
+
+    fn sample(surface: &str, x: u32) -> Arc<Message> {
+        Arc::new(Message::Sample {
+            surface: surface.into(),
+            series: "s".to_owned(),
+            body: format!("\"xy\":[{x},0]").into(),
+        })
+    }
+
+    /// The queued messages as `surface:x` for samples and `clear surface` for clears.
+    fn queued(queue: &ClientQueue) -> Vec<String> {
+        let state = queue.state.lock().unwrap();
+        state
+            .messages
+            .iter()
+            .map(|message| match &**message {
+                Message::Sample { surface, body, .. } => {
+                    let x = body.as_str().trim_start_matches("\"xy\":[");
+                    format!("{surface}:{}", &x[..x.find(',').unwrap()])
+                }
+                Message::Clear(surface) => format!("clear {surface}"),
+                _ => "other".to_owned(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn clear_discards_only_its_surface() {
+        let queue = queue(DEFAULT_LIMIT);
+        for x in 0..3 {
+            queue.push(sample("a", x));
+            queue.push(sample("b", x));
+        }
+        queue.push(Arc::new(Message::Setting("{}".to_owned())));
+        queue.push(Arc::new(Message::Clear("a".to_owned())));
+        queue.push(sample("a", 3));
+        assert_eq!(
+            queued(&queue),
+            ["b:0", "b:1", "b:2", "other", "clear a", "a:3"]
+        );
+        // a second clear also replaces the first one.
+        queue.push(Arc::new(Message::Clear("a".to_owned())));
+        assert_eq!(queued(&queue), ["b:0", "b:1", "b:2", "other", "clear a"]);
+    }
+
+    #[test]
+    fn inspected_values_survive_clears() {
+        let queue = queue(DEFAULT_LIMIT);
+        let inspected = |body: &str| {
+            Arc::new(Message::Record {
+                surface: "a".into(),
+                meta: wire::Meta {
+                    target: "inspect".into(),
+                    module: "".into(),
+                    file: "src/main.rs".into(),
+                    line: 1,
+                    time: 0.0,
+                    wall: 0,
+                    tid: 1,
+                    thread: "main".into(),
+                },
+                body: body.to_owned().into(),
+            })
+        };
+        queue.push(inspected("\"insp\":\"arm\",\"val\":1"));
+        queue.push(inspected("\"msg\":\"hidden\""));
+        queue.push(sample("a", 0));
+        queue.push(Arc::new(Message::Clear("a".to_owned())));
+        assert_eq!(queued(&queue), ["other", "clear a"]);
+    }
+
+    #[test]
+    fn full_queue_drops_records_but_not_clears() {
+        let queue = queue(2);
+        for x in 0..4 {
+            queue.push(sample("a", x));
+        }
+        queue.push(Arc::new(Message::Clear("b".to_owned())));
+        assert_eq!(queued(&queue), ["a:0", "a:1", "clear b"]);
+        assert_eq!(queue.state.lock().unwrap().dropped["a"], 2);
+    }
+
+    #[test]
+    fn clients_are_counted_per_server() {
+        let clients = Arc::new(Clients::new(DEFAULT_LIMIT));
+        let other = Arc::new(Clients::new(DEFAULT_LIMIT));
+        let _viewer = other.register();
+        assert_eq!(clients.count(), 0);
+        assert!(clients.wait_timeout_while(Duration::from_millis(10), |count| count == 0));
+        let client = clients.register();
+        assert_eq!(clients.count(), 1);
+        assert!(!clients.wait_timeout_while(Duration::from_secs(5), |count| count == 0));
+        drop(client);
+        assert_eq!(clients.count(), 0);
+        clients.stop();
+        assert!(!clients.wait_timeout_while(Duration::from_secs(5), |count| count == 0));
+        assert!(!clients.wait_for_client());
+    }
+}
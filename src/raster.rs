@@ -0,0 +1,483 @@
+//! Rendering of the retained surfaces into PNG images without a browser.
+//!
+//! The retained records of a surface are converted into an SVG document resembling
+//! the viewer, which is then rasterized with `resvg`. Only the geometric visuals are drawn:
+//! points, lines, labels, polygons, ellipses, arcs, rectangles and paths.
+//! Plots, tables, images and the other composite visuals are skipped,
+//! as are arrowheads, vertex markers and custom dash patterns.
+
+use crate::{
+    json::{self, Value},
+    page::HtmlEscape,
+    snapshot, view,
+};
+use resvg::{tiny_skia, usvg};
+use std::{fmt::Write, io, path::Path};
+
+/// The colors of the default theme of the viewer for the css variables in the messages.
+const THEME: [(&str, &str); 9] = [
+    ("var(--healthy)", "#7D0"),
+    ("var(--base)", "#fff"),
+    ("var(--warn)", "#FC0"),
+    ("var(--error)", "#F45"),
+    ("var(--info)", "#58F"),
+    ("var(--x)", "#F15"),
+    ("var(--y)", "#0F3"),
+    ("var(--z)", "#25F"),
+    ("var(--mis)", "#D0E"),
+];
+
+/// Keys of the visuals, which can't be rendered without the viewer.
+const SKIPPED: [&str; 14] = [
+    "msg", "clear", "series", "xy", "callout", "area", "grad", "mesh", "table", "prog", "box",
+    "vf", "hist", "img",
+];
+
+fn color(value: Option<&Value>) -> Option<&str> {
+    let Some(Value::String(css)) = value else {
+        return None;
+    };
+    Some(
+        THEME
+            .iter()
+            .find(|(var, _)| var == css)
+            .map_or(css, |(_, hex)| hex),
+    )
+}
+
+fn number(value: Option<&Value>) -> Option<f64> {
+    match value {
+        Some(Value::Number(n)) => Some(*n),
+        _ => None,
+    }
+}
+
+fn point(value: Option<&Value>) -> Option<[f64; 2]> {
+    match value {
+        Some(Value::Array(array)) => Some([number(array.first())?, number(array.get(1))?]),
+        _ => None,
+    }
+}
+
+/// The stroke width of the viewer, where a size of 0 means a width relative to the view.
+fn stroke_width(size: f64) -> String {
+    if size == 0.0 {
+        "2%".to_owned()
+    } else {
+        size.to_string()
+    }
+}
+
+/// Builds the SVG elements of a surface and tracks their bounds.
+struct SvgSurface {
+    body: String,
+    min: [f64; 2],
+    max: [f64; 2],
+    y_up: bool,
+}
+
+impl SvgSurface {
+    fn include(&mut self, [x, y]: [f64; 2], radius: f64) {
+        self.min = [self.min[0].min(x - radius), self.min[1].min(y - radius)];
+        self.max = [self.max[0].max(x + radius), self.max[1].max(y + radius)];
+    }
+    /// Add a text, which stays upright on surfaces with the y axis pointing up.
+    fn text(
+        &mut self,
+        [x, y]: [f64; 2],
+        size: &str,
+        color: &str,
+        anchor: &str,
+        baseline: &str,
+        text: &str,
+    ) {
+        let position = if self.y_up {
+            format!("transform=\"translate({x} {y}) scale(1 -1)\"")
+        } else {
+            format!("x=\"{x}\" y=\"{y}\"")
+        };
+        write!(
+            &mut self.body,
+            "<text {position} font-size=\"{size}\" fill=\"{color}\" text-anchor=\"{anchor}\" dominant-baseline=\"{baseline}\">{}</text>",
+            HtmlEscape(text)
+        )
+        .unwrap();
+    }
+    /// Add a record, if it is one of the supported visuals.
+    fn record(&mut self, j: &Value) {
+        let col = color(j.get("col")).unwrap_or("#fff");
+        let fill = color(j.get("fill")).unwrap_or("none");
+        let size = number(j.get("size")).unwrap_or(1.0);
+        let th = stroke_width(size);
+        let label = match j.get("lbl") {
+            Some(Value::String(label)) if !label.is_empty() => Some(label.as_str()),
+            _ => None,
+        };
+        let opacity = number(j.get("op")).unwrap_or(1.0);
+        let stroke = format!("stroke=\"{col}\" stroke-width=\"{th}\" fill=\"{fill}\"");
+        write!(&mut self.body, "<g opacity=\"{opacity}\">").unwrap();
+        // the viewer offsets all visuals by half a pixel, so lines with integer coordinates are sharp.
+        if let (Some(c), Some(d)) = (point(j.get("rect")), point(j.get("dim"))) {
+            let [x, y] = [c[0] + 0.5, c[1] + 0.5];
+            let rot = number(j.get("rot")).unwrap_or(0.0).to_degrees();
+            write!(
+                &mut self.body,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" transform=\"rotate({rot} {x} {y})\" {stroke}/>",
+                x - d[0] / 2.0,
+                y - d[1] / 2.0,
+                d[0],
+                d[1]
+            )
+            .unwrap();
+            self.include(c, d[0].hypot(d[1]) / 2.0);
+            if let Some(label) = label {
+                self.text(
+                    [x - d[0] / 2.0, y - d[1] / 2.0],
+                    "16",
+                    col,
+                    "start",
+                    "ideographic",
+                    label,
+                );
+            }
+        } else if let (Some(c), Some(r)) = (point(j.get("ell")), point(j.get("r"))) {
+            let [x, y] = [c[0] + 0.5, c[1] + 0.5];
+            match j.get("arc").and_then(|arc| point(Some(arc))) {
+                Some([a0, a1]) if (a1 - a0).abs() < std::f64::consts::TAU => {
+                    let e = |w: f64| format!("{} {}", x + r[0] * w.cos(), y + r[1] * w.sin());
+                    let (start, end) = if fill == "none" {
+                        ("M", "")
+                    } else {
+                        ("L", "Z")
+                    };
+                    let prefix = if fill == "none" {
+                        String::new()
+                    } else {
+                        format!("M{x} {y}")
+                    };
+                    write!(
+                        &mut self.body,
+                        "<path d=\"{prefix}{start}{}A{} {} 0 {} {} {}{end}\" {stroke}/>",
+                        e(a0),
+                        r[0],
+                        r[1],
+                        u8::from((a1 - a0).abs() > std::f64::consts::PI),
+                        u8::from(a1 > a0),
+                        e(a1)
+                    )
+                    .unwrap();
+                }
+                _ => {
+                    let rot = number(j.get("rot")).unwrap_or(0.0).to_degrees();
+                    write!(
+                        &mut self.body,
+                        "<ellipse cx=\"{x}\" cy=\"{y}\" rx=\"{}\" ry=\"{}\" transform=\"rotate({rot} {x} {y})\" {stroke}/>",
+                        r[0], r[1]
+                    )
+                    .unwrap();
+                }
+            }
+            self.include(c, r[0].max(r[1]));
+            if let Some(label) = label {
+                self.text([x, y], "16", col, "middle", "central", label);
+            }
+        } else if let Some(Value::Array(points)) = j.get("pts") {
+            let points: Vec<[f64; 2]> = points.iter().filter_map(|p| point(Some(p))).collect();
+            if points.is_empty() {
+                self.body.push_str("</g>");
+                return;
+            }
+            self.body.push_str("<polygon points=\"");
+            for (i, &[x, y]) in points.iter().enumerate() {
+                if i > 0 {
+                    self.body.push(' ');
+                }
+                write!(&mut self.body, "{},{}", x + 0.5, y + 0.5).unwrap();
+            }
+            write!(&mut self.body, "\" stroke-linejoin=\"round\" {stroke}/>").unwrap();
+            let mut center = [0.0; 2];
+            for &p in &points {
+                self.include(p, 0.0);
+                center = [center[0] + p[0], center[1] + p[1]];
+            }
+            if let Some(label) = label {
+                let n = points.len() as f64;
+                let center = [center[0] / n + 0.5, center[1] / n + 0.5];
+                self.text(center, "16", col, "middle", "central", label);
+            }
+        } else if let Some(Value::String(path)) = j.get("path") {
+            write!(
+                &mut self.body,
+                "<path d=\"{path}\" stroke-linecap=\"round\" stroke-linejoin=\"round\" {stroke}/>"
+            )
+            .unwrap();
+            // the path data only contains absolute coordinate pairs.
+            let coords: Vec<f64> = path
+                .split(|c: char| c.is_ascii_alphabetic() || c == ' ')
+                .filter_map(|v| v.parse().ok())
+                .collect();
+            for pair in coords.chunks_exact(2) {
+                self.include([pair[0] - 0.5, pair[1] - 0.5], 0.0);
+            }
+            if let (Some(label), &[x, y, ..]) = (label, &coords[..]) {
+                self.text([x, y], "16", col, "start", "ideographic", label);
+            }
+        } else if let (Some(p1), Some(p2)) = (point(j.get("pos")), point(j.get("pos2"))) {
+            let style = match j.get("style") {
+                Some(Value::String(style)) => style.as_str(),
+                _ => "",
+            };
+            let dash = if style == "Dashed" {
+                format!(" stroke-dasharray=\"{size} {}\"", 2.0 * size)
+            } else {
+                String::new()
+            };
+            write!(
+                &mut self.body,
+                "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{col}\" stroke-width=\"{th}\" stroke-linecap=\"round\"{dash}/>",
+                p1[0] + 0.5,
+                p1[1] + 0.5,
+                p2[0] + 0.5,
+                p2[1] + 0.5
+            )
+            .unwrap();
+            self.include(p1, 0.0);
+            self.include(p2, 0.0);
+            if let Some(label) = label {
+                let mid = [(p1[0] + p2[0]) / 2.0, (p1[1] + p2[1]) / 2.0];
+                self.text(mid, "16", col, "middle", "ideographic", label);
+            }
+        } else if let Some(p) = point(j.get("pos")) {
+            let [x, y] = [p[0] + 0.5, p[1] + 0.5];
+            if let (Some(align), Some(label)) = (number(j.get("align")), label) {
+                let font = if size == 0.0 {
+                    "100%".to_owned()
+                } else {
+                    size.to_string()
+                };
+                let anchor = match align as u8 {
+                    0 => "start",
+                    2 => "end",
+                    _ => "middle",
+                };
+                let baseline = if align as u8 == 3 {
+                    "ideographic"
+                } else {
+                    "central"
+                };
+                self.text([x, y], &font, col, anchor, baseline, label);
+                self.include(p, 0.0);
+            } else {
+                self.point(p, j, col, size, label);
+            }
+        }
+        self.body.push_str("</g>");
+    }
+    /// Add a point with the point styles of the viewer.
+    fn point(&mut self, p: [f64; 2], j: &Value, col: &str, size: f64, label: Option<&str>) {
+        let [x, y] = [p[0] + 0.5, p[1] + 0.5];
+        let style = match j.get("style") {
+            Some(Value::String(style)) => style.as_str(),
+            _ => "",
+        };
+        // the styles starting with "Point" have a size relative to the view.
+        let relative = style.starts_with('P');
+        let unit = if relative { "%" } else { "" };
+        let outline = style.ends_with("ne") || (!style.contains("Fi") && !relative);
+        let paint = if outline {
+            format!("stroke=\"{col}\" stroke-width=\"2%\" fill=\"none\"")
+        } else {
+            format!("fill=\"{col}\"")
+        };
+        let dash = if style.starts_with("Da") {
+            " stroke-dasharray=\"4 3\""
+        } else {
+            ""
+        };
+        write!(&mut self.body, "<g transform=\"translate({x} {y})\">").unwrap();
+        if style.ends_with("le") || style == "Point" || style == "PointOutline" {
+            let fill = if style == "FilledCircle" || style == "Point" {
+                col
+            } else {
+                "none"
+            };
+            let stroke = if outline {
+                format!("stroke=\"{col}\" stroke-width=\"2%\"")
+            } else {
+                String::new()
+            };
+            write!(
+                &mut self.body,
+                "<circle r=\"{}{unit}\" fill=\"{fill}\" {stroke}{dash}/>",
+                size / 2.0
+            )
+            .unwrap();
+        } else if style.contains("Di") || style.contains("Sq") {
+            let half = if relative { size * 0.443 } else { size / 2.0 };
+            let rotate = if style.contains("Di") {
+                " transform=\"rotate(45)\""
+            } else {
+                ""
+            };
+            write!(
+                &mut self.body,
+                "<rect x=\"{}{unit}\" y=\"{}{unit}\" width=\"{}{unit}\" height=\"{}{unit}\"{rotate} {paint}{dash}/>",
+                -half,
+                -half,
+                half * 2.0,
+                half * 2.0
+            )
+            .unwrap();
+        } else if style == "PointCross" {
+            let h = size / 2.8284;
+            for k in [-h, h] {
+                write!(
+                    &mut self.body,
+                    "<line x1=\"{}%\" y1=\"{}%\" x2=\"{h}%\" y2=\"{k}%\" stroke=\"{col}\" stroke-width=\"2%\"/>",
+                    -h, -k
+                )
+                .unwrap();
+            }
+        }
+        if let Some(label) = label {
+            // short labels fit inside of the point.
+            let inside = label.len() as f64 * 10.0 <= size && style != "PointCross";
+            let filled = style.contains('F') || (relative && !style.ends_with("ne"));
+            let color = if inside && filled { "white" } else { col };
+            let font = if relative { "100%" } else { "16" };
+            let (y, baseline) = if inside {
+                (0.0, "central")
+            } else {
+                (-size / 2.0, "ideographic")
+            };
+            self.text([0.0, y], font, color, "middle", baseline, label);
+        }
+        self.body.push_str("</g>");
+        self.include(p, if relative { 0.0 } else { size / 2.0 });
+    }
+}
+
+/// Build an SVG document of the retained records of the surface with the given size in pixels.
+///
+/// The view is the one set with [`SurfaceConfig::view`](crate::SurfaceConfig::view),
+/// or the bounds of all visuals. Returns `None` if nothing is retained for the surface.
+pub(crate) fn surface_svg(surface: &str, [width, height]: [u32; 2]) -> Option<String> {
+    let mut view = None;
+    let mut y_up = false;
+    for setting in view::settings() {
+        let Some(conf) = json::parse(&setting) else {
+            continue;
+        };
+        if matches!(conf.get("conf"), Some(Value::String(s)) if s == surface) {
+            y_up = conf.get("yup") == Some(&Value::Bool(true));
+            if let Some(Value::Array(rect)) = conf.get("rect") {
+                let r: Vec<f64> = rect.iter().filter_map(|v| number(Some(v))).collect();
+                view = (r.len() == 4).then(|| [r[0], r[1], r[2], r[3]]);
+            }
+        }
+    }
+    let mut records = vec![];
+    for message in snapshot::retained_messages() {
+        let Some(j) = json::parse(&message) else {
+            continue;
+        };
+        let on_surface = matches!(j.get("surf"), Some(Value::String(s)) if s == surface);
+        if on_surface && !SKIPPED.iter().any(|key| j.get(key).is_some()) {
+            let layer = number(j.get("layer")).unwrap_or(0.0);
+            records.push((layer, j));
+        }
+    }
+    if records.is_empty() {
+        return None;
+    }
+    // higher layers are drawn on top, otherwise the records are drawn in order.
+    records.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut svg = SvgSurface {
+        body: String::new(),
+        min: [f64::INFINITY; 2],
+        max: [f64::NEG_INFINITY; 2],
+        y_up,
+    };
+    for (_, j) in &records {
+        svg.record(j);
+    }
+    let [x, y, w, h] = view.unwrap_or_else(|| {
+        if svg.min[0] > svg.max[0] {
+            return [0.0, 0.0, 1.0, 1.0];
+        }
+        // leave a margin around the visuals, like the fitting in the viewer.
+        let size = [svg.max[0] - svg.min[0], svg.max[1] - svg.min[1]];
+        let margin = (size[0].max(size[1]) * 0.05).max(1.0);
+        [
+            svg.min[0] - margin,
+            svg.min[1] - margin,
+            size[0] + 2.0 * margin,
+            size[1] + 2.0 * margin,
+        ]
+    });
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"{x} {y} {w} {h}\" font-family=\"sans-serif\">"
+    );
+    if y_up {
+        // mirror the view at its center line, like the viewer does.
+        write!(
+            &mut out,
+            "<g transform=\"matrix(1 0 0 -1 0 {})\">",
+            2.0 * y + h
+        )
+        .unwrap();
+    } else {
+        out.push_str("<g>");
+    }
+    out.push_str(&svg.body);
+    out.push_str("</g></svg>");
+    Some(out)
+}
+
+/// Rasterize the retained records of the surface and write them as PNG to the path.
+pub(crate) fn export_png(surface: &str, path: &Path, resolution: [u32; 2]) -> io::Result<()> {
+    let svg = surface_svg(surface, resolution).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("nothing is retained on the surface \"{surface}\""),
+        )
+    })?;
+    let mut options = usvg::Options::default();
+    let fonts = options.fontdb_mut();
+    fonts.load_system_fonts();
+    // the generic sans-serif family defaults to Arial, which is missing on many CI machines.
+    let query = usvg::fontdb::Query {
+        families: &[usvg::fontdb::Family::SansSerif],
+        ..Default::default()
+    };
+    if fonts.query(&query).is_none() {
+        // prefer any sans font over the other installed fonts.
+        let fallback = fonts
+            .faces()
+            .filter_map(|face| face.families.first())
+            .min_by_key(|(family, _)| !family.contains("Sans"))
+            .map(|(family, _)| family.clone());
+        if let Some(family) = fallback {
+            fonts.set_sans_serif_family(family);
+        }
+    }
+    let tree = usvg::Tree::from_str(&svg, &options)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+    let mut pixmap = tiny_skia::Pixmap::new(resolution[0], resolution[1]).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the resolution must not be zero",
+        )
+    })?;
+    // the background of the viewer.
+    pixmap.fill(tiny_skia::Color::from_rgba8(0x22, 0x22, 0x22, 0xFF));
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::identity(),
+        &mut pixmap.as_mut(),
+    );
+    let png = pixmap
+        .encode_png()
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+    std::fs::write(path, png)
+}
@@ -0,0 +1,96 @@
+//! Rate limits for the records of a target or surface.
+
+use crate::stats;
+use std::{collections::HashMap, sync::Mutex, time::Instant};
+
+/// What shares a token bucket of a [`RateLimit`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RateLimitKey {
+    /// All records of a target share a bucket.
+    Target,
+    /// All records on a surface share a bucket.
+    Surface,
+}
+
+/// A limit for the records per second, see [`Builder::rate_limit`](crate::Builder::rate_limit).
+///
+/// Every target or surface has a token bucket, which holds up to `burst` records and refills
+/// with `per_second` records per second. Records arriving at an empty bucket are dropped and
+/// counted in [`Stats::rate_limited`](crate::Stats::rate_limited). This way a hot loop only
+/// silences its own target or surface, while all others stay live.
+///
+/// ```
+/// use web_vlog::RateLimit;
+///
+/// // at most 100 records per second per surface, with bursts of up to 1000 records.
+/// web_vlog::Builder::new().rate_limit(RateLimit::per_surface(100.0).burst(1000.0));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    per_second: f64,
+    burst: f64,
+    key: RateLimitKey,
+}
+
+impl RateLimit {
+    /// Limit the records of every target to `per_second`, with bursts of up to one second worth of records.
+    pub const fn per_target(per_second: f64) -> Self {
+        Self {
+            per_second,
+            burst: per_second,
+            key: RateLimitKey::Target,
+        }
+    }
+    /// Limit the records on every surface to `per_second`, with bursts of up to one second worth of records.
+    pub const fn per_surface(per_second: f64) -> Self {
+        Self {
+            per_second,
+            burst: per_second,
+            key: RateLimitKey::Surface,
+        }
+    }
+    /// Set the maximal number of records, which are let through at once after a quiet period.
+    pub const fn burst(mut self, burst: f64) -> Self {
+        self.burst = burst;
+        self
+    }
+}
+
+/// The token buckets of a [`RateLimit`].
+pub(crate) struct Limiter {
+    limit: RateLimit,
+    /// The tokens of every key at the time of the last update.
+    buckets: Mutex<HashMap<String, (f64, Instant)>>,
+}
+
+impl Limiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+    /// Take a token from the bucket of the target or surface. Returns false if the record is dropped.
+    pub fn admit(&self, target: &str, surface: &str) -> bool {
+        let key = match self.limit.key {
+            RateLimitKey::Target => target,
+            RateLimitKey::Surface => surface,
+        };
+        let now = Instant::now();
+        let burst = self.limit.burst.max(1.0);
+        let mut buckets = self.buckets.lock().unwrap();
+        let (tokens, last) = match buckets.get_mut(key) {
+            Some(bucket) => bucket,
+            None => buckets.entry(key.to_owned()).or_insert((burst, now)),
+        };
+        let refill = now.duration_since(*last).as_secs_f64() * self.limit.per_second;
+        *tokens = (*tokens + refill).min(burst);
+        *last = now;
+        if *tokens < 1.0 {
+            stats::add_rate_limited(surface);
+            return false;
+        }
+        *tokens -= 1.0;
+        true
+    }
+}
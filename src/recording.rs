@@ -0,0 +1,311 @@
+//! The line-delimited JSON format of recorded sessions, see [`Builder::record_to`](crate::Builder::record_to).
+//!
+//! A recording is a text file with one JSON object per line. The first line is the header
+//! `{"recording":1,"protocol":2,"wall":millis}` with the version of the recording format,
+//! the version of the wire format of the messages and the wall-clock time of the start of the
+//! recording in milliseconds since the unix epoch.
+//! Every following line is a message `{"t":seconds,"msg":message}`, where `t` is the time since
+//! the start of the recording and `message` is a message of the wire format as sent to the viewer,
+//! e.g. `{"t":0.25,"msg":{"clear":1,"surf":"map"}}`. The messages use the basic wire format
+//! without the dictionary and with complete `"meta"` objects, so every line can be read on its own.
+//!
+//! ```
+//! use std::time::Duration;
+//! use web_vlog::recording::{Recorder, RecordingReader};
+//!
+//! let mut recorder = Recorder::new(Vec::new()).unwrap();
+//! recorder
+//!     .write_message_at(Duration::from_millis(250), r#"{"clear":1,"surf":"map"}"#)
+//!     .unwrap();
+//! let text = String::from_utf8(recorder.into_inner()).unwrap();
+//! let lines: Vec<_> = text.lines().collect();
+//! assert!(lines[0].starts_with(r#"{"recording":1,"#));
+//! assert_eq!(lines[1], r#"{"t":0.25,"msg":{"clear":1,"surf":"map"}}"#);
+//!
+//! let mut reader = RecordingReader::new(text.as_bytes());
+//! let (time, message) = reader.next_message().unwrap().unwrap();
+//! assert_eq!(time, Duration::from_millis(250));
+//! assert_eq!(message, r#"{"clear":1,"surf":"map"}"#);
+//! assert!(reader.next_message().unwrap().is_none());
+//! ```
+//!
+//! A recording can be shown in the viewer again with [`replay`](crate::replay).
+//!
+//! With the `gzip` feature, recordings created with a path ending in `.gz` are gzip compressed
+//! and compressed recordings are decompressed transparently when they are opened.
+//! The compressed stream is flushed like the plain file, so an interrupted compressed
+//! recording can still be read up to the last flush.
+
+use crate::{
+    channel::{channel, Receiver, TryRecvError},
+    json,
+    queue::Clients,
+    wire::{self, Message},
+    Packet, WebVLogger,
+};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// The version of the recording format.
+pub const RECORDING_VERSION: u32 = 1;
+
+/// Writes a recording, see the [module documentation](self).
+pub struct Recorder<W: Write> {
+    writer: W,
+    start: Instant,
+    encoder: wire::Encoder,
+}
+
+impl Recorder<Box<dyn Write + Send>> {
+    /// Create or truncate the file at `path` and write the header of a recording to it.
+    ///
+    /// With the `gzip` feature, the file is compressed if the path ends with `.gz`.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = BufWriter::new(File::create(path)?);
+        #[cfg(feature = "gzip")]
+        if path.extension().map_or(false, |ext| ext == "gz") {
+            let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+            return Self::new(Box::new(encoder));
+        }
+        Self::new(Box::new(file))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    /// Create a recorder, which writes to `writer`, and write the header.
+    /// The time of the messages is measured from now.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        let wall = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis());
+        writeln!(
+            writer,
+            "{{\"recording\":{RECORDING_VERSION},\"protocol\":{},\"wall\":{wall}}}",
+            wire::PROTOCOL_VERSION
+        )?;
+        Ok(Self {
+            writer,
+            start: Instant::now(),
+            encoder: wire::Encoder::new(false),
+        })
+    }
+    /// Write a message of the wire format with the time since the recorder was created.
+    pub fn write_message(&mut self, message: &str) -> io::Result<()> {
+        self.write_message_at(self.start.elapsed(), message)
+    }
+    /// Write a message of the wire format with the time since the start of the recording.
+    ///
+    /// The message has to be a JSON object on a single line. This doesn't flush the underlying writer.
+    pub fn write_message_at(&mut self, time: Duration, message: &str) -> io::Result<()> {
+        let message = message.trim();
+        if !message.starts_with('{') || message.contains('\n') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "message is not a JSON object on a single line",
+            ));
+        }
+        writeln!(
+            self.writer,
+            "{{\"t\":{},\"msg\":{message}}}",
+            time.as_secs_f64()
+        )
+    }
+    /// Encode and write a message of the vlogger.
+    pub(crate) fn record(&mut self, message: &Message) -> io::Result<()> {
+        match self.encoder.encode(message, 1) {
+            Some(msg) => self.write_message(&msg),
+            None => Ok(()),
+        }
+    }
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+    /// Get a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+    /// Unwrap the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads a recording, see the [module documentation](self).
+pub struct RecordingReader<R: BufRead> {
+    reader: R,
+    line: String,
+    skipped: usize,
+}
+
+/// The magic bytes at the start of gzip compressed files.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+impl RecordingReader<Box<dyn BufRead + Send>> {
+    /// Open the recording at `path`.
+    ///
+    /// With the `gzip` feature, compressed recordings are decompressed while reading.
+    ///
+    /// # Errors
+    ///
+    /// Without the `gzip` feature, opening a compressed recording returns an error
+    /// of the kind [`io::ErrorKind::InvalidData`].
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        if file.fill_buf()?.starts_with(&GZIP_MAGIC) {
+            #[cfg(feature = "gzip")]
+            return Ok(Self::new(Box::new(BufReader::new(
+                flate2::bufread::MultiGzDecoder::new(file),
+            ))));
+            #[cfg(not(feature = "gzip"))]
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "reading compressed recordings requires the `gzip` feature",
+            ));
+        }
+        Ok(Self::new(Box::new(file)))
+    }
+}
+
+impl<R: BufRead> RecordingReader<R> {
+    /// Create a reader, which reads the recording from `reader`.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+            skipped: 0,
+        }
+    }
+    /// The number of damaged lines skipped so far, e.g. the last line of an interrupted recording.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+    /// Read the next message and its time since the start of the recording.
+    ///
+    /// Returns `None` at the end of the recording. Headers are skipped, so concatenated
+    /// recordings can be read as one.
+    ///
+    /// # Errors
+    ///
+    /// Besides IO errors, an error of the kind [`io::ErrorKind::InvalidData`] is returned
+    /// if the recording has a newer version than [`RECORDING_VERSION`].
+    pub fn next_message(&mut self) -> io::Result<Option<(Duration, String)>> {
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return Ok(None),
+                Ok(_) => {}
+                // an interrupted compressed recording ends without the trailer of the stream.
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    if !self.line.is_empty() {
+                        self.skipped += 1;
+                    }
+                    return Ok(None);
+                }
+                Err(err) => return Err(err),
+            }
+            let line = self.line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some(value) = json::parse(line) else {
+                self.skipped += 1;
+                continue;
+            };
+            if let Some(json::Value::Number(version)) = value.get("recording") {
+                if *version > RECORDING_VERSION as f64 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unsupported recording version {version}"),
+                    ));
+                }
+                continue;
+            }
+            match (value.get("t"), value.get("msg")) {
+                (Some(json::Value::Number(t)), Some(msg @ json::Value::Object(_)))
+                    if t.is_finite() && *t >= 0.0 =>
+                {
+                    return Ok(Some((Duration::from_secs_f64(*t), msg.to_string())));
+                }
+                _ => self.skipped += 1,
+            }
+        }
+    }
+}
+
+/// Send the messages of the recording to the viewer of `vlogger` with their original timing divided by `speed`.
+/// The replay starts when a client of the server connects.
+pub(crate) fn play<R: BufRead>(
+    mut reader: RecordingReader<R>,
+    vlogger: &WebVLogger,
+    clients: Option<&Clients>,
+    speed: f64,
+) {
+    if clients.map_or(false, |clients| !clients.wait_for_client()) {
+        return;
+    }
+    let start = Instant::now();
+    loop {
+        let (time, msg) = match reader.next_message() {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(err) => {
+                log::error!("failed to read the vlog recording: {err}");
+                break;
+            }
+        };
+        if speed.is_finite() && speed > 0.0 {
+            let due = start + time.div_f64(speed);
+            std::thread::sleep(due.saturating_duration_since(Instant::now()));
+        }
+        // the messages are already encoded, so they are sent as they are.
+        if !vlogger.send(Message::Setting(msg)) {
+            return;
+        }
+    }
+    if reader.skipped() > 0 {
+        log::warn!(
+            "skipped {} damaged lines of the vlog recording",
+            reader.skipped()
+        );
+    }
+}
+
+/// Start a thread, which records the messages from `rx` to the file and passes them on.
+///
+/// Returns the receiver of the passed on messages for the server thread.
+pub(crate) fn relay(path: &Path, rx: Receiver<Packet>) -> io::Result<Receiver<Packet>> {
+    let mut recorder = Some(Recorder::create(path)?);
+    let (sender, relayed) = channel();
+    std::thread::Builder::new()
+        .name("web-vlog-recorder".to_owned())
+        .spawn(move || loop {
+            let packet = match rx.try_recv() {
+                Ok(packet) => packet,
+                Err(TryRecvError::Empty) => {
+                    // write everything to the file before waiting, so it is complete after a crash.
+                    if let Some(Err(err)) = recorder.as_mut().map(Recorder::flush) {
+                        log::error!("failed to write the vlog recording: {err}");
+                        recorder = None;
+                    }
+                    match rx.recv() {
+                        Ok(packet) => packet,
+                        Err(_) => return,
+                    }
+                }
+                Err(TryRecvError::Disconnected) => return,
+            };
+            if let Some(Err(err)) = recorder.as_mut().map(|r| r.record(&packet.message)) {
+                log::error!("failed to write the vlog recording: {err}");
+                recorder = None;
+            }
+            // keep recording, even if the server has stopped.
+            let _ = sender.send(packet);
+        })?;
+    Ok(relayed)
+}
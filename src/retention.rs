@@ -0,0 +1,248 @@
+//! The retention policy for the records retained by the vlogger.
+
+use crate::stats;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+static POLICY: Mutex<RetentionPolicy> = Mutex::new(RetentionPolicy::new());
+
+/// Limits for the records retained by the vlogger, see [`Builder::retention`](crate::Builder::retention).
+///
+/// The retained records of a surface are all records since it was last cleared.
+/// When a limit is exceeded, the oldest records are evicted first and counted in [`Stats::evicted`](crate::Stats::evicted).
+/// By default at most [`DEFAULT_MAX_ENTITIES`](RetentionPolicy::DEFAULT_MAX_ENTITIES) records are kept per surface
+/// and [`DEFAULT_MAX_BYTES`](RetentionPolicy::DEFAULT_MAX_BYTES) in total, so a long running program doesn't run out of memory.
+///
+/// ```
+/// use std::time::Duration;
+/// use web_vlog::RetentionPolicy;
+///
+/// let policy = RetentionPolicy::new()
+///     .max_entities(1_000)
+///     .max_age(Duration::from_secs(60))
+///     .max_bytes(16 << 20);
+/// web_vlog::Builder::new().auto_snapshot(Duration::from_secs(1), 5).retention(policy);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    max_entities: Option<usize>,
+    max_age: Option<Duration>,
+    max_bytes: Option<usize>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetentionPolicy {
+    /// The default limit of the retained records per surface.
+    pub const DEFAULT_MAX_ENTITIES: usize = 10_000;
+    /// The default limit of the total size of the retained records in bytes.
+    pub const DEFAULT_MAX_BYTES: usize = 64 << 20;
+
+    /// Create the default policy with [`DEFAULT_MAX_ENTITIES`](Self::DEFAULT_MAX_ENTITIES)
+    /// and [`DEFAULT_MAX_BYTES`](Self::DEFAULT_MAX_BYTES).
+    pub const fn new() -> Self {
+        Self::unlimited()
+            .max_entities(Self::DEFAULT_MAX_ENTITIES)
+            .max_bytes(Self::DEFAULT_MAX_BYTES)
+    }
+    /// Create a policy without limits, which keeps every record since the last clear of its surface.
+    pub const fn unlimited() -> Self {
+        Self {
+            max_entities: None,
+            max_age: None,
+            max_bytes: None,
+        }
+    }
+    /// Limit the number of retained records per surface.
+    pub const fn max_entities(mut self, max: usize) -> Self {
+        self.max_entities = Some(max);
+        self
+    }
+    /// Evict records, which are older than `max`.
+    pub const fn max_age(mut self, max: Duration) -> Self {
+        self.max_age = Some(max);
+        self
+    }
+    /// Limit the total size of the retained records of all surfaces in bytes.
+    pub const fn max_bytes(mut self, max: usize) -> Self {
+        self.max_bytes = Some(max);
+        self
+    }
+}
+
+/// Get the current retention policy.
+pub fn retention() -> RetentionPolicy {
+    *POLICY.lock().unwrap()
+}
+
+/// Change the retention policy at runtime. The new limits are applied with the next record.
+pub fn set_retention(policy: RetentionPolicy) {
+    *POLICY.lock().unwrap() = policy;
+}
+
+/// A retained record.
+struct Entry {
+    /// The order of the records over all surfaces.
+    seq: u64,
+    time: Instant,
+    msg: String,
+}
+
+/// The retained records of all surfaces, which are kept within the [`RetentionPolicy`].
+#[derive(Default)]
+pub(crate) struct Retained {
+    surfaces: BTreeMap<String, VecDeque<Entry>>,
+    seq: u64,
+    bytes: usize,
+}
+
+impl Retained {
+    /// Add an encoded record to a surface and evict records according to the current policy.
+    pub fn push(&mut self, surface: &str, msg: String) {
+        self.bytes += msg.len();
+        self.seq += 1;
+        let entry = Entry {
+            seq: self.seq,
+            time: Instant::now(),
+            msg,
+        };
+        match self.surfaces.get_mut(surface) {
+            Some(records) => records.push_back(entry),
+            None => {
+                self.surfaces
+                    .insert(surface.to_owned(), VecDeque::from([entry]));
+            }
+        }
+        self.evict(surface, &retention());
+    }
+    /// Forget the records of a surface. This is not counted as eviction.
+    pub fn clear(&mut self, surface: &str) {
+        if let Some(records) = self.surfaces.remove(surface) {
+            self.bytes -= records.iter().map(|e| e.msg.len()).sum::<usize>();
+        }
+    }
+    /// Iterate over the surfaces and their retained records.
+    pub fn surfaces(&self) -> impl Iterator<Item = (&str, impl Iterator<Item = &str>)> {
+        self.surfaces
+            .iter()
+            .map(|(surface, records)| (surface.as_str(), records.iter().map(|e| e.msg.as_str())))
+    }
+    fn evict(&mut self, surface: &str, policy: &RetentionPolicy) {
+        let mut evicted = 0;
+        if let (Some(max), Some(records)) = (policy.max_entities, self.surfaces.get_mut(surface)) {
+            while records.len() > max {
+                let entry = records.pop_front().unwrap();
+                self.bytes -= entry.msg.len();
+                evicted += 1;
+            }
+        }
+        if let Some(max) = policy.max_age {
+            for records in self.surfaces.values_mut() {
+                while records.front().map_or(false, |e| e.time.elapsed() > max) {
+                    let entry = records.pop_front().unwrap();
+                    self.bytes -= entry.msg.len();
+                    evicted += 1;
+                }
+            }
+        }
+        if let Some(max) = policy.max_bytes {
+            while self.bytes > max {
+                // evict the oldest record over all surfaces.
+                let Some(records) = self
+                    .surfaces
+                    .values_mut()
+                    .filter(|records| !records.is_empty())
+                    .min_by_key(|records| records[0].seq)
+                else {
+                    break;
+                };
+                let entry = records.pop_front().unwrap();
+                self.bytes -= entry.msg.len();
+                evicted += 1;
+            }
+        }
+        self.surfaces.retain(|_, records| !records.is_empty());
+        stats::add_evicted(evicted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retained(records: &[(&str, &str)]) -> Retained {
+        let mut retained = Retained::default();
+        for (surface, msg) in records {
+            retained.push(surface, (*msg).to_owned());
+        }
+        retained
+    }
+
+    fn contents(retained: &Retained) -> Vec<(&str, Vec<&str>)> {
+        retained
+            .surfaces()
+            .map(|(surface, records)| (surface, records.collect()))
+            .collect()
+    }
+
+    #[test]
+    fn max_entities_evicts_the_oldest_of_the_surface() {
+        let mut retained = retained(&[("a", "1"), ("b", "2"), ("a", "3"), ("a", "4")]);
+        retained.evict("a", &RetentionPolicy::unlimited().max_entities(2));
+        assert_eq!(
+            contents(&retained),
+            [("a", vec!["3", "4"]), ("b", vec!["2"])]
+        );
+        assert_eq!(retained.bytes, 3);
+    }
+
+    #[test]
+    fn max_bytes_evicts_the_oldest_of_all_surfaces() {
+        let mut retained = retained(&[("a", "11"), ("b", "22"), ("a", "33"), ("b", "44")]);
+        retained.evict("b", &RetentionPolicy::unlimited().max_bytes(5));
+        assert_eq!(contents(&retained), [("a", vec!["33"]), ("b", vec!["44"])]);
+        assert_eq!(retained.bytes, 4);
+        // emptied surfaces are removed.
+        retained.evict("b", &RetentionPolicy::unlimited().max_bytes(2));
+        assert_eq!(contents(&retained), [("b", vec!["44"])]);
+    }
+
+    #[test]
+    fn max_age_evicts_the_old_records_of_all_surfaces() {
+        let mut retained = retained(&[("a", "1"), ("b", "2"), ("a", "3")]);
+        let old = Instant::now() - Duration::from_secs(10);
+        retained.surfaces.get_mut("a").unwrap()[0].time = old;
+        retained.surfaces.get_mut("b").unwrap()[0].time = old;
+        retained.evict(
+            "a",
+            &RetentionPolicy::unlimited().max_age(Duration::from_secs(5)),
+        );
+        assert_eq!(contents(&retained), [("a", vec!["3"])]);
+        assert_eq!(retained.bytes, 1);
+    }
+
+    #[test]
+    fn clear_is_not_eviction() {
+        let mut retained = retained(&[("a", "1"), ("b", "22")]);
+        retained.clear("b");
+        assert_eq!(contents(&retained), [("a", vec!["1"])]);
+        assert_eq!(retained.bytes, 1);
+    }
+
+    #[test]
+    fn default_is_bounded() {
+        let policy = RetentionPolicy::default();
+        assert_eq!(
+            policy.max_entities,
+            Some(RetentionPolicy::DEFAULT_MAX_ENTITIES)
+        );
+        assert_eq!(policy.max_bytes, Some(RetentionPolicy::DEFAULT_MAX_BYTES));
+    }
+}
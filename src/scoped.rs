@@ -0,0 +1,67 @@
+//! Thread local settings, which apply to the vlogging calls inside of a closure.
+
+use std::{
+    cell::{Cell, RefCell},
+    thread::LocalKey,
+};
+
+/// Set the thread local `key` to `value` while `f` runs.
+/// The previous value is restored afterwards, even if `f` panics.
+pub(crate) fn scoped<T: Copy + 'static, R>(
+    key: &'static LocalKey<Cell<Option<T>>>,
+    value: T,
+    f: impl FnOnce() -> R,
+) -> R {
+    struct Guard<T: Copy + 'static>(&'static LocalKey<Cell<Option<T>>>, Option<T>);
+    impl<T: Copy + 'static> Drop for Guard<T> {
+        fn drop(&mut self) {
+            self.0.with(|cell| cell.set(self.1));
+        }
+    }
+    let _guard = Guard(key, key.with(|cell| cell.replace(Some(value))));
+    f()
+}
+
+/// Like [`scoped`] for values, which aren't `Copy`.
+pub(crate) fn scoped_ref<T: 'static, R>(
+    key: &'static LocalKey<RefCell<Option<T>>>,
+    value: T,
+    f: impl FnOnce() -> R,
+) -> R {
+    struct Guard<T: 'static>(&'static LocalKey<RefCell<Option<T>>>, Option<T>);
+    impl<T: 'static> Drop for Guard<T> {
+        fn drop(&mut self) {
+            self.0.with(|cell| *cell.borrow_mut() = self.1.take());
+        }
+    }
+    let _guard = Guard(key, key.with(|cell| cell.borrow_mut().replace(value)));
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    thread_local! {
+        static VALUE: Cell<Option<u32>> = const { Cell::new(None) };
+        static NAME: RefCell<Option<String>> = const { RefCell::new(None) };
+    }
+
+    #[test]
+    fn nested_values_are_restored() {
+        scoped(&VALUE, 1, || {
+            scoped(&VALUE, 2, || assert_eq!(VALUE.with(Cell::get), Some(2)));
+            assert_eq!(VALUE.with(Cell::get), Some(1));
+        });
+        assert_eq!(VALUE.with(Cell::get), None);
+    }
+
+    #[test]
+    fn values_are_restored_after_a_panic() {
+        let result = std::panic::catch_unwind(|| {
+            scoped_ref(&NAME, "inner".to_owned(), || panic!("vlogging failed"))
+        });
+        assert!(result.is_err());
+        assert_eq!(NAME.with(|name| name.borrow().clone()), None);
+    }
+}
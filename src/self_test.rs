@@ -0,0 +1,146 @@
+//! A synthetic load to measure the throughput of the vlogger on the current machine.
+
+use crate::{stats, Stats, CLIENTS, QUEUED};
+use std::{
+    fmt,
+    sync::atomic::Ordering,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The surface the synthetic records are vlogged on.
+const SURFACE: &str = "web-vlog self test";
+
+/// The records vlogged at once before the rate is checked again.
+const BATCH: u64 = 1000;
+
+/// The longest time to wait for the server thread to take the queued records after the load.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The result of a [`self_test`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct SelfTestReport {
+    /// Number of records vlogged by the test.
+    pub records: u64,
+    /// The time spent vlogging the records.
+    pub duration: Duration,
+    /// The time from the start until the server thread took all queued records.
+    pub drained: Duration,
+    /// Number of messages the server thread took from the queue during the test.
+    ///
+    /// While no client is connected, the messages stay in the queue and nothing is received.
+    pub received: u64,
+    /// Number of records dropped by the backpressure policies during the test.
+    pub dropped: u64,
+    /// Number of message bytes written to the websockets of all clients during the test.
+    pub bytes_sent: u64,
+}
+
+impl SelfTestReport {
+    /// The achieved rate of vlogged records per second.
+    pub fn records_per_sec(&self) -> f64 {
+        rate(self.records, self.duration)
+    }
+    /// The rate of messages taken by the server thread per second, including the time to drain the queue.
+    pub fn received_per_sec(&self) -> f64 {
+        rate(self.received, self.drained)
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} records in {:.2?} ({:.0}/s), {} received in {:.2?} ({:.0}/s), {} dropped, {} bytes sent",
+            self.records,
+            self.duration,
+            self.records_per_sec(),
+            self.received,
+            self.drained,
+            self.received_per_sec(),
+            self.dropped,
+            self.bytes_sent
+        )
+    }
+}
+
+fn rate(count: u64, duration: Duration) -> f64 {
+    if duration.is_zero() {
+        0.0
+    } else {
+        count as f64 / duration.as_secs_f64()
+    }
+}
+
+/// The number of records dropped by any backpressure policy.
+fn dropped(stats: &Stats) -> u64 {
+    stats.dropped + stats.client_dropped + stats.rate_limited
+}
+
+/// Vlog synthetic records at `records_per_sec` for `duration` and report the achieved throughput.
+///
+/// The records are points and messages on the surface `web-vlog self test` with the target
+/// `web_vlog::self_test`, which go through the global vlogger like any other record.
+/// Afterwards the test waits up to a few seconds for the server thread to take the queued records.
+/// Run it with a viewer connected, as the records are only sent when a client is connected.
+/// The rate is limited by the vlogging thread, so a very large rate measures the maximal throughput.
+///
+/// ```no_run
+/// web_vlog::init();
+/// web_vlog::wait_for_connection();
+/// let report = web_vlog::self_test(100_000, std::time::Duration::from_secs(5));
+/// println!("{report}");
+/// ```
+pub fn self_test(records_per_sec: u64, duration: Duration) -> SelfTestReport {
+    let before = stats::stats();
+    v_log::clear!(SURFACE);
+    let start = Instant::now();
+    let mut records = 0;
+    loop {
+        let elapsed = start.elapsed();
+        if elapsed >= duration {
+            break;
+        }
+        let due = (elapsed.as_secs_f64() * records_per_sec as f64) as u64;
+        if records >= due {
+            thread::sleep(Duration::from_micros(100));
+            continue;
+        }
+        let end = due.min(records + BATCH);
+        while records < end {
+            let i = records;
+            let angle = i as f64 * 0.01;
+            if i % 10 == 9 {
+                v_log::message!(SURFACE, "self test record {}", i);
+            } else {
+                v_log::point!(
+                    SURFACE,
+                    [angle.cos() * 100.0, angle.sin() * 100.0],
+                    3.0,
+                    Base,
+                    "{}",
+                    i
+                );
+            }
+            records += 1;
+        }
+    }
+    let duration = start.elapsed();
+    while QUEUED.load(Ordering::Relaxed) > 0
+        && CLIENTS.load(Ordering::Relaxed) > 0
+        && start.elapsed() < duration + DRAIN_TIMEOUT
+    {
+        thread::sleep(Duration::from_millis(1));
+    }
+    let drained = start.elapsed();
+    let after = stats::stats();
+    SelfTestReport {
+        records,
+        duration,
+        drained,
+        received: after.received - before.received,
+        dropped: dropped(&after) - dropped(&before),
+        bytes_sent: after.bytes_sent - before.bytes_sent,
+    }
+}
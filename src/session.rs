@@ -0,0 +1,44 @@
+//! Persistence of the retained state across process restarts, see [`Builder::persist_session`](crate::Builder::persist_session).
+
+use crate::{
+    json::{self, Value},
+    recording::{Recorder, RecordingReader},
+    snapshot,
+};
+use std::{io, path::Path, time::Duration};
+
+/// The prefix of the surfaces showing the state of the previous run.
+pub(crate) const LAST_RUN: &str = "last run/";
+
+/// Write the retained state of all surfaces as a recording to the path.
+pub(crate) fn save(path: &Path) -> io::Result<()> {
+    let mut recorder = Recorder::create(path)?;
+    for message in snapshot::retained_messages() {
+        recorder.write_message_at(Duration::ZERO, &message)?;
+    }
+    recorder.flush()
+}
+
+/// Read the session saved by the previous run, with every surface moved to `last run/<surface>`.
+///
+/// A missing file is an empty session, as there is no previous run.
+pub(crate) fn load(path: &Path) -> io::Result<Vec<String>> {
+    let mut reader = match RecordingReader::open(path) {
+        Ok(reader) => reader,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+    let mut messages = vec![];
+    while let Some((_, message)) = reader.next_message()? {
+        let Some(Value::Object(mut object)) = json::parse(&message) else {
+            continue;
+        };
+        for (key, value) in &mut object {
+            if let (Value::String(surface), "surf") = (value, key.as_str()) {
+                surface.insert_str(0, LAST_RUN);
+            }
+        }
+        messages.push(Value::Object(object).to_string());
+    }
+    Ok(messages)
+}
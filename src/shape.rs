@@ -0,0 +1,1556 @@
+//! Visuals beyond the ones of `v_log`, which are only supported by web-vlog.
+//!
+//! They are vlogged with the macros of this crate, e.g. [`polygon!`](crate::polygon!),
+//! which go through the same filtering and serialization as the records of `v_log`.
+
+use crate::{
+    fields,
+    json::{self, Num, Pos},
+    wire::Message,
+    write_color, write_css, FieldValue, Origin, VLOGGER,
+};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use std::{borrow::Cow, fmt, fmt::Write as _};
+use v_log::Color;
+
+/// A visual, which is not supported by `v_log` itself.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum Shape {
+    /// A closed polygon through the points.
+    Polygon(Vec<[f64; 2]>),
+    /// An ellipse with the radii along its axes, which are rotated by `rotation` radians.
+    /// It is a circle if both radii are equal.
+    Ellipse {
+        center: [f64; 2],
+        radii: [f64; 2],
+        rotation: f64,
+    },
+    /// A circular arc from the `start` to the `stop` angle in radians.
+    /// The angles are measured from the x axis towards the y axis.
+    /// When filled, the sector of the circle is filled.
+    Arc {
+        center: [f64; 2],
+        radius: f64,
+        start: f64,
+        stop: f64,
+    },
+    /// A rectangle with the `size` `[width, height]` rotated by `rotation` radians around its center.
+    Rect {
+        center: [f64; 2],
+        size: [f64; 2],
+        rotation: f64,
+    },
+    /// A path of straight and curved segments from `start`, see [`PathSegment`].
+    /// When filled, the path is closed for the fill, but not for the stroke.
+    Path {
+        start: [f64; 2],
+        segments: Vec<PathSegment>,
+    },
+    /// A raster image from the `min` corner, which is stretched to the `size` `[width, height]`.
+    /// The stroke color is used for the label and, if the width is not 0, for a frame around the image.
+    Image {
+        min: [f64; 2],
+        size: [f64; 2],
+        data: ImageData,
+    },
+    /// A histogram from the `min` corner with the `size` `[width, height]`,
+    /// where the tallest bar has the full height. The `edges` are the bin boundaries,
+    /// so there is one count less than edges. The fill defaults to the stroke color.
+    Histogram {
+        min: [f64; 2],
+        size: [f64; 2],
+        edges: Vec<f64>,
+        counts: Vec<f64>,
+    },
+    /// A grid of 2D vectors drawn as arrows, see [`VectorField`].
+    VectorField(VectorField),
+    /// The label as multiline text on a background rectangle, see [`TextBox`].
+    TextBox(TextBox),
+    /// A progress bar, which replaces the previous progress bar with the same `id` on the surface.
+    /// The bars are stacked in the top left corner of the surface in the order they first appear.
+    Progress {
+        id: String,
+        current: f64,
+        total: f64,
+    },
+    /// A table, see [`Table`].
+    Table(Table),
+    /// A triangle mesh, where each triangle references three `vertices` by their index.
+    ///
+    /// Without a fill, the triangles are filled with the stroke color. With a fill, they are filled
+    /// with the fill color and the edges are drawn as wireframe in the stroke color.
+    /// Triangles with indices out of range are skipped.
+    Mesh {
+        vertices: Vec<[f64; 2]>,
+        triangles: Vec<[u32; 3]>,
+    },
+    /// An open polyline, whose color changes gradually along its length, e.g. to encode time along a trajectory.
+    ///
+    /// With one color per point, the color is interpolated between the points. Otherwise the colors
+    /// are spread evenly along the length of the line, so two colors give the start and the end color.
+    GradientLine {
+        points: Vec<[f64; 2]>,
+        colors: Vec<Color>,
+    },
+    /// The area between the `upper` and the `lower` polyline, e.g. a confidence band or an integral.
+    /// Both polylines are drawn from left to right and the area is closed between their ends.
+    /// If `lower` is empty, the area extends from `upper` to `y = 0`.
+    /// The fill defaults to the stroke color with reduced opacity.
+    Area {
+        upper: Vec<[f64; 2]>,
+        lower: Vec<[f64; 2]>,
+    },
+    /// The label in a bubble, which is connected to the `anchor` with a leader line.
+    /// The viewer places the bubble next to the anchor, where it doesn't overlap other callouts.
+    /// The fill is the background of the bubble and defaults to a translucent dark background.
+    Callout { anchor: [f64; 2] },
+}
+
+/// A small table for [`Shape::Table`], e.g. live solver statistics.
+///
+/// A table replaces the previous table with the same `id` on the surface.
+/// The text is drawn in the stroke color on the fill color, which defaults to a translucent dark background.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Table {
+    pub id: String,
+    /// The top left corner of the table.
+    pub min: [f64; 2],
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<FieldValue>>,
+}
+
+impl Table {
+    /// Create an empty table with the column headers.
+    pub fn new(id: impl Into<String>, min: [f64; 2], headers: &[&str]) -> Self {
+        Self {
+            id: id.into(),
+            min,
+            headers: headers.iter().map(|h| (*h).to_owned()).collect(),
+            rows: vec![],
+        }
+    }
+    /// Append a row. Rows may have fewer or more cells than there are headers.
+    pub fn row(mut self, cells: impl IntoIterator<Item = FieldValue>) -> Self {
+        self.rows.push(cells.into_iter().collect());
+        self
+    }
+}
+
+/// The layout of a [`Shape::TextBox`], e.g. to show a small state dump on a surface.
+///
+/// The text is the label of the visual and may contain newlines. It is drawn in the stroke color
+/// on the fill color, which defaults to a translucent dark background. If the stroke width is not 0,
+/// the box gets a frame in the stroke color.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextBox {
+    /// The top left corner of the box.
+    pub min: [f64; 2],
+    /// The width at which lines are wrapped between words, including the padding. 0 disables the wrapping.
+    pub wrap: f64,
+    /// The space between the text and the border of the box.
+    pub padding: f64,
+}
+
+impl TextBox {
+    /// Create a text box at the corner without wrapping and with a padding of 4.
+    pub fn new(min: [f64; 2]) -> Self {
+        Self {
+            min,
+            wrap: 0.0,
+            padding: 4.0,
+        }
+    }
+    /// Wrap lines longer than the width.
+    pub fn wrap(mut self, width: f64) -> Self {
+        self.wrap = width;
+        self
+    }
+    /// Set the space between the text and the border of the box.
+    pub fn padding(mut self, padding: f64) -> Self {
+        self.padding = padding;
+        self
+    }
+}
+
+/// A grid of 2D vectors for [`Shape::VectorField`], e.g. a velocity field.
+///
+/// The vectors are given in rows from the `min` corner with `columns` vectors per row and
+/// are drawn as arrows starting at their grid point. An incomplete last row is ignored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VectorField {
+    pub min: [f64; 2],
+    /// The distance `[dx, dy]` between neighbouring grid points.
+    pub spacing: [f64; 2],
+    pub columns: usize,
+    pub vectors: Vec<[f64; 2]>,
+    pub normalization: Normalization,
+    /// Color the arrows by their magnitude relative to the longest vector
+    /// instead of using the stroke color.
+    pub colormap: Option<Colormap>,
+}
+
+impl VectorField {
+    /// Create a vector field with [`Normalization::Max`] and without a colormap.
+    pub fn new(min: [f64; 2], spacing: [f64; 2], columns: usize, vectors: Vec<[f64; 2]>) -> Self {
+        Self {
+            min,
+            spacing,
+            columns,
+            vectors,
+            normalization: Normalization::Max,
+            colormap: None,
+        }
+    }
+    /// Set how the vectors are scaled to arrows.
+    pub fn normalization(mut self, normalization: Normalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+    /// Color the arrows by the magnitude of the vectors.
+    pub fn colormap(mut self, colormap: Colormap) -> Self {
+        self.colormap = Some(colormap);
+        self
+    }
+}
+
+/// How the vectors of a [`VectorField`] are scaled to arrows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Normalization {
+    /// Multiply the vectors by the factor, e.g. to compare multiple fields.
+    Scale(f64),
+    /// Scale the vectors, so the longest arrow is as long as the grid spacing.
+    Max,
+    /// Draw all arrows as long as the grid spacing, so only the direction is shown.
+    Unit,
+}
+
+/// The pixels of a [`Shape::Image`], which are embedded into the message as base64.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImageData {
+    /// Raw 8 bit RGBA pixels in rows from the top left.
+    /// Images where the number of bytes is not `width * height * 4` are not vlogged, but logged as error.
+    Rgba {
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    },
+    /// A complete PNG file, which is passed to the viewer unchanged.
+    Png(Vec<u8>),
+}
+
+impl ImageData {
+    /// Color a 2D scalar field with `width` values per row, e.g. an occupancy grid or a cost map.
+    ///
+    /// The values are mapped linearly from `range` `[min, max]` to the colormap,
+    /// which defaults to the range of the finite values. Non-finite values are transparent.
+    /// An incomplete last row is ignored.
+    pub fn heatmap(
+        width: u32,
+        values: &[f64],
+        range: Option<[f64; 2]>,
+        colormap: Colormap,
+    ) -> Self {
+        let height = values.len().checked_div(width as usize).unwrap_or(0);
+        let values = &values[..width as usize * height];
+        let [min, max] = range.unwrap_or_else(|| {
+            values
+                .iter()
+                .filter(|v| v.is_finite())
+                .fold([f64::INFINITY, f64::NEG_INFINITY], |[min, max], &v| {
+                    [min.min(v), max.max(v)]
+                })
+        });
+        let scale = if max > min { 1.0 / (max - min) } else { 0.0 };
+        let pixels = values
+            .iter()
+            .flat_map(|&v| {
+                if v.is_finite() {
+                    colormap.map((v - min) * scale)
+                } else {
+                    [0; 4]
+                }
+            })
+            .collect();
+        Self::Rgba {
+            width,
+            height: height as u32,
+            pixels,
+        }
+    }
+}
+
+/// A colormap for [`ImageData::heatmap`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Colormap {
+    /// From black to white.
+    Grayscale,
+    /// The perceptually uniform colormap from dark purple over blue and green to yellow.
+    Viridis,
+    /// A diverging colormap from blue over light gray to red, for values around 0.
+    Coolwarm,
+}
+
+impl Colormap {
+    /// Map `t` in `[0, 1]` to an opaque RGBA color. Values outside are clamped.
+    pub fn map(self, t: f64) -> [u8; 4] {
+        let stops: &[[u8; 3]] = match self {
+            Self::Grayscale => &[[0, 0, 0], [255, 255, 255]],
+            Self::Viridis => &[
+                [0x44, 0x01, 0x54],
+                [0x3b, 0x52, 0x8b],
+                [0x21, 0x91, 0x8c],
+                [0x5e, 0xc9, 0x62],
+                [0xfd, 0xe7, 0x25],
+            ],
+            Self::Coolwarm => &[[0x3b, 0x4c, 0xc0], [0xdd, 0xdd, 0xdd], [0xb4, 0x04, 0x26]],
+        };
+        // NaN is clamped to 0 as well.
+        let t = if t > 0.0 { t.min(1.0) } else { 0.0 } * (stops.len() - 1) as f64;
+        let i = (t as usize).min(stops.len() - 2);
+        let f = t - i as f64;
+        let [a, b] = [stops[i], stops[i + 1]];
+        let mix = |k: usize| (a[k] as f64 + (b[k] as f64 - a[k] as f64) * f).round() as u8;
+        [mix(0), mix(1), mix(2), 255]
+    }
+}
+
+/// A segment of a [`Shape::Path`], which continues from the end of the previous segment.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathSegment {
+    /// A straight line to the end point.
+    Line([f64; 2]),
+    /// A quadratic Bezier curve with the control point and the end point.
+    Quadratic([f64; 2], [f64; 2]),
+    /// A cubic Bezier curve with the two control points and the end point.
+    Cubic([f64; 2], [f64; 2], [f64; 2]),
+}
+
+impl Shape {
+    /// A histogram of the finite samples with `bins` bins of equal width between
+    /// the smallest and the largest sample, see [`Shape::Histogram`].
+    pub fn histogram(min: [f64; 2], size: [f64; 2], samples: &[f64], bins: usize) -> Self {
+        let bins = bins.max(1);
+        let [lo, hi] = samples
+            .iter()
+            .filter(|v| v.is_finite())
+            .fold([f64::INFINITY, f64::NEG_INFINITY], |[lo, hi], &v| {
+                [lo.min(v), hi.max(v)]
+            });
+        let [lo, hi] = if lo > hi {
+            [0.0, 1.0]
+        } else if lo == hi {
+            [lo - 0.5, hi + 0.5]
+        } else {
+            [lo, hi]
+        };
+        let width = (hi - lo) / bins as f64;
+        let mut counts = vec![0.0; bins];
+        for v in samples.iter().filter(|v| v.is_finite()) {
+            // the largest sample belongs to the last bin.
+            counts[(((v - lo) / width) as usize).min(bins - 1)] += 1.0;
+        }
+        Self::Histogram {
+            min,
+            size,
+            edges: (0..=bins).map(|i| lo + width * i as f64).collect(),
+            counts,
+        }
+    }
+    /// A smooth Catmull-Rom spline through all the points, made of cubic Bezier curves.
+    pub fn catmull_rom(points: &[[f64; 2]]) -> Self {
+        let Some(&start) = points.first() else {
+            return Self::Path {
+                start: [0.0; 2],
+                segments: vec![],
+            };
+        };
+        let n = points.len();
+        // the tangent at each point is parallel to the line through its neighbours.
+        let tangent = |i: usize| {
+            let prev = points[i.saturating_sub(1)];
+            let next = points[(i + 1).min(n - 1)];
+            [(next[0] - prev[0]) / 6.0, (next[1] - prev[1]) / 6.0]
+        };
+        let segments = (1..n)
+            .map(|i| {
+                let [p0, p1] = [points[i - 1], points[i]];
+                let [t0, t1] = [tangent(i - 1), tangent(i)];
+                PathSegment::Cubic(
+                    [p0[0] + t0[0], p0[1] + t0[1]],
+                    [p1[0] - t1[0], p1[1] - t1[1]],
+                    p1,
+                )
+            })
+            .collect();
+        Self::Path { start, segments }
+    }
+}
+
+/// The stroke and fill of a [`Shape`].
+#[derive(Clone, Copy, Debug)]
+pub struct ShapeStyle {
+    pub stroke: Color,
+    /// The fill color. `None` leaves the shape unfilled.
+    pub fill: Option<Color>,
+    /// The width of the stroke in pixels. A width of 0 scales with the zoom like [`v_log`] lines.
+    pub width: f64,
+}
+
+/// The location of a vlogging macro call.
+#[doc(hidden)]
+pub struct Location<'a> {
+    pub target: &'a str,
+    pub module: &'static str,
+    pub file: &'static str,
+    pub line: u32,
+}
+
+/// Vlog a shape. Use the macros, e.g. [`polygon!`](crate::polygon!), instead of calling this directly.
+#[doc(hidden)]
+pub fn vlog(
+    location: &Location,
+    surface: &str,
+    shape: Shape,
+    style: &ShapeStyle,
+    args: fmt::Arguments,
+) {
+    let vlogger = VLOGGER.read().unwrap();
+    let Some(vlogger) = &*vlogger else {
+        return;
+    };
+    // check the image first, so a broken image doesn't use up the rate limit.
+    if let Shape::Image {
+        data: ImageData::Rgba {
+            width,
+            height,
+            pixels,
+        },
+        ..
+    } = &shape
+    {
+        if pixels.len() as u64 != *width as u64 * *height as u64 * 4 {
+            log::error!(
+                "{}:{}: the image has {} bytes instead of {width}x{height} RGBA pixels",
+                location.file,
+                location.line,
+                pixels.len()
+            );
+            return;
+        }
+    }
+    if !vlogger.accepts(location.target, surface)
+        || !vlogger.admit(location.target, surface, &style.stroke, false)
+    {
+        return;
+    }
+    #[cfg(feature = "self-profile")]
+    let _timer = crate::stats::VlogTimer::start();
+    let mut body = crate::body_buffer();
+    crate::with_escaped(&args, |label| write!(&mut body, "\"lbl\":\"{label}\"")).unwrap();
+    match shape {
+        Shape::Callout { anchor: [x, y] } => {
+            write!(&mut body, ",\"callout\":{}", Pos([x, y])).unwrap();
+        }
+        Shape::Area { upper, lower } => {
+            for (key, points) in [("area", &upper), ("lower", &lower)] {
+                write!(&mut body, ",\"{key}\":[").unwrap();
+                for (i, point) in points.iter().enumerate() {
+                    if i > 0 {
+                        body.push(',');
+                    }
+                    write!(&mut body, "{}", Pos(*point)).unwrap();
+                }
+                body.push(']');
+            }
+        }
+        Shape::GradientLine { points, colors } => {
+            body.push_str(",\"grad\":[");
+            for (i, point) in points.iter().enumerate() {
+                if i > 0 {
+                    body.push(',');
+                }
+                write!(&mut body, "{}", Pos(*point)).unwrap();
+            }
+            body.push_str("],\"gcol\":[");
+            for (i, color) in colors.iter().enumerate() {
+                if i > 0 {
+                    body.push(',');
+                }
+                write_css(&mut body, color);
+            }
+            body.push(']');
+        }
+        Shape::Mesh {
+            vertices,
+            triangles,
+        } => {
+            body.push_str(",\"mesh\":[");
+            for (i, &[x, y]) in vertices.iter().enumerate() {
+                if i > 0 {
+                    body.push(',');
+                }
+                write!(&mut body, "{},{}", Num(x), Num(y)).unwrap();
+            }
+            body.push_str("],\"tri\":[");
+            let valid = triangles
+                .iter()
+                .filter(|t| t.iter().all(|&i| (i as usize) < vertices.len()));
+            for (i, [a, b, c]) in valid.enumerate() {
+                if i > 0 {
+                    body.push(',');
+                }
+                write!(&mut body, "{a},{b},{c}").unwrap();
+            }
+            body.push(']');
+        }
+        Shape::Table(table) => {
+            let [x, y] = table.min;
+            write!(
+                &mut body,
+                ",\"table\":\"{}\",\"pos\":{},\"head\":[",
+                json::Escape(&table.id),
+                Pos([x, y])
+            )
+            .unwrap();
+            for (i, header) in table.headers.iter().enumerate() {
+                if i > 0 {
+                    body.push(',');
+                }
+                write!(&mut body, "\"{}\"", json::Escape(header)).unwrap();
+            }
+            body.push_str("],\"rows\":[");
+            for (i, row) in table.rows.iter().enumerate() {
+                body.push_str(if i > 0 { ",[" } else { "[" });
+                for (i, cell) in row.iter().enumerate() {
+                    if i > 0 {
+                        body.push(',');
+                    }
+                    fields::write_value(&mut body, cell);
+                }
+                body.push(']');
+            }
+            body.push(']');
+        }
+        Shape::Progress { id, current, total } => {
+            write!(
+                &mut body,
+                ",\"prog\":\"{}\",\"cur\":{},\"tot\":{}",
+                json::Escape(&id),
+                Num(current),
+                Num(total)
+            )
+            .unwrap();
+        }
+        Shape::TextBox(TextBox {
+            min: [x, y],
+            wrap,
+            padding,
+        }) => {
+            write!(
+                &mut body,
+                ",\"box\":{},\"wrap\":{},\"pad\":{}",
+                Pos([x, y]),
+                Num(wrap),
+                Num(padding)
+            )
+            .unwrap();
+        }
+        Shape::VectorField(field) => {
+            let rows = field.vectors.len().checked_div(field.columns).unwrap_or(0);
+            let vectors = &field.vectors[..field.columns * rows];
+            let longest = vectors
+                .iter()
+                .map(|[x, y]| x.hypot(*y))
+                .filter(|m| m.is_finite())
+                .fold(0.0, f64::max);
+            let cell = field.spacing[0].abs().min(field.spacing[1].abs());
+            let scale = match field.normalization {
+                Normalization::Scale(scale) => scale,
+                Normalization::Max if longest > 0.0 => cell / longest,
+                Normalization::Max => 0.0,
+                Normalization::Unit => cell,
+            };
+            write!(
+                &mut body,
+                ",\"vf\":{},\"step\":{},\"cols\":{},\"scale\":{},\"max\":{}",
+                Pos(field.min),
+                Pos(field.spacing),
+                field.columns,
+                Num(scale),
+                Num(longest)
+            )
+            .unwrap();
+            if field.normalization == Normalization::Unit {
+                body.push_str(",\"unit\":1");
+            }
+            if let Some(colormap) = field.colormap {
+                // a palette is enough to color by magnitude, so the viewer doesn't need the colormaps.
+                body.push_str(",\"cmap\":[");
+                for i in 0..16 {
+                    let [r, g, b, _] = colormap.map(i as f64 / 15.0);
+                    if i > 0 {
+                        body.push(',');
+                    }
+                    write!(&mut body, "\"#{r:02x}{g:02x}{b:02x}\"").unwrap();
+                }
+                body.push(']');
+            }
+            body.push_str(",\"vec\":[");
+            for (i, [vx, vy]) in vectors.iter().enumerate() {
+                if i > 0 {
+                    body.push(',');
+                }
+                // non-finite vectors are not drawn.
+                if vx.is_finite() && vy.is_finite() {
+                    write!(&mut body, "{}", Pos([*vx, *vy])).unwrap();
+                } else {
+                    body.push_str("null");
+                }
+            }
+            body.push(']');
+        }
+        Shape::Histogram {
+            min: [x, y],
+            size: [w, h],
+            edges,
+            counts,
+        } => {
+            write!(
+                &mut body,
+                ",\"hist\":{},\"dim\":{}",
+                Pos([x, y]),
+                Pos([w, h])
+            )
+            .unwrap();
+            for (key, values) in [("edges", &edges), ("counts", &counts)] {
+                write!(&mut body, ",\"{key}\":[").unwrap();
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        body.push(',');
+                    }
+                    write!(&mut body, "{}", Num(*v)).unwrap();
+                }
+                body.push(']');
+            }
+        }
+        Shape::Image {
+            min: [x, y],
+            size: [w, h],
+            data,
+        } => {
+            write!(
+                &mut body,
+                ",\"img\":{},\"dim\":{}",
+                Pos([x, y]),
+                Pos([w, h])
+            )
+            .unwrap();
+            match data {
+                ImageData::Rgba {
+                    width,
+                    height,
+                    pixels,
+                } => {
+                    write!(
+                        &mut body,
+                        ",\"px\":[{width},{height}],\"rgba\":\"{}\"",
+                        BASE64_STANDARD.encode(pixels)
+                    )
+                    .unwrap();
+                }
+                ImageData::Png(png) => {
+                    write!(&mut body, ",\"png\":\"{}\"", BASE64_STANDARD.encode(png)).unwrap();
+                }
+            }
+        }
+        Shape::Polygon(points) => {
+            body.push_str(",\"pts\":[");
+            for (i, point) in points.iter().enumerate() {
+                if i > 0 {
+                    body.push(',');
+                }
+                write!(&mut body, "{}", Pos(*point)).unwrap();
+            }
+            body.push(']');
+        }
+        Shape::Ellipse {
+            center: [x, y],
+            radii: [rx, ry],
+            rotation,
+        } => {
+            write!(
+                &mut body,
+                ",\"ell\":{},\"r\":{},\"rot\":{}",
+                Pos([x, y]),
+                Pos([rx, ry]),
+                Num(rotation)
+            )
+            .unwrap();
+        }
+        Shape::Arc {
+            center: [x, y],
+            radius,
+            start,
+            stop,
+        } => {
+            write!(
+                &mut body,
+                ",\"ell\":{},\"r\":{},\"rot\":0,\"arc\":{}",
+                Pos([x, y]),
+                Pos([radius, radius]),
+                Pos([start, stop])
+            )
+            .unwrap();
+        }
+        Shape::Rect {
+            center: [x, y],
+            size: [w, h],
+            rotation,
+        } => {
+            write!(
+                &mut body,
+                ",\"rect\":{},\"dim\":{},\"rot\":{}",
+                Pos([x, y]),
+                Pos([w, h]),
+                Num(rotation)
+            )
+            .unwrap();
+        }
+        Shape::Path {
+            start: [x, y],
+            segments,
+        } => {
+            // the path is sent as SVG path data, with the same offset as the other visuals.
+            let p = |[x, y]: [f64; 2]| (Num(x + 0.5), Num(y + 0.5));
+            write!(&mut body, ",\"path\":\"M{} {}", Num(x + 0.5), Num(y + 0.5)).unwrap();
+            for segment in segments {
+                match segment {
+                    PathSegment::Line(e) => {
+                        let e = p(e);
+                        write!(&mut body, "L{} {}", e.0, e.1)
+                    }
+                    PathSegment::Quadratic(c, e) => {
+                        let (c, e) = (p(c), p(e));
+                        write!(&mut body, "Q{} {} {} {}", c.0, c.1, e.0, e.1)
+                    }
+                    PathSegment::Cubic(c1, c2, e) => {
+                        let (c1, c2, e) = (p(c1), p(c2), p(e));
+                        write!(
+                            &mut body,
+                            "C{} {} {} {} {} {}",
+                            c1.0, c1.1, c2.0, c2.1, e.0, e.1
+                        )
+                    }
+                }
+                .unwrap();
+            }
+            body.push('"');
+            crate::line::write_stroke(&mut body);
+        }
+    }
+    write!(&mut body, ",\"size\":{}", Num(style.width)).unwrap();
+    if let Some(fill) = &style.fill {
+        write_color(&mut body, "fill", fill);
+    }
+    let origin = Origin {
+        target: location.target,
+        module: Cow::Borrowed(location.module),
+        file: Cow::Borrowed(location.file),
+        line: location.line,
+    };
+    vlogger.emit(origin, surface, &style.stroke, false, body);
+}
+
+/// Append a sample to a series of a line chart. Use [`plot!`](crate::plot!) instead of calling this directly.
+#[doc(hidden)]
+pub fn sample(target: &str, surface: &str, series: &str, [x, y]: [f64; 2], color: Option<Color>) {
+    if !x.is_finite() || !y.is_finite() {
+        return;
+    }
+    let vlogger = VLOGGER.read().unwrap();
+    let Some(vlogger) = &*vlogger else {
+        return;
+    };
+    if !vlogger.accepts(target, surface)
+        || !vlogger.admit(
+            target,
+            surface,
+            color.as_ref().unwrap_or(&Color::Base),
+            false,
+        )
+    {
+        return;
+    }
+    let mut body = format!("\"xy\":{}", Pos([x, y]));
+    if let Some(color) = &color {
+        write_color(&mut body, "col", color);
+    }
+    vlogger.send(Message::Sample {
+        surface: crate::intern(surface),
+        series: series.to_owned(),
+        body: body.into(),
+    });
+}
+
+/// The common part of the shape macros.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __shape {
+    ($target:expr, $surface:expr, $shape:expr, $stroke:expr
+        $(, fill: $fill:expr)? $(, width: $width:expr)? $(, $fmt:literal $(, $arg:expr)*)? $(,)?) => {
+        $crate::shape::vlog(
+            &$crate::shape::Location {
+                target: $target,
+                module: module_path!(),
+                file: file!(),
+                line: line!(),
+            },
+            &$surface,
+            $shape,
+            &$crate::shape::ShapeStyle {
+                stroke: $stroke,
+                fill: None $(.or(Some($fill)))?,
+                width: None $(.or(Some($width)))?.unwrap_or(0.0),
+            },
+            format_args!(concat!("" $(, $fmt)?) $($(, $arg)*)?),
+        )
+    };
+}
+
+/// Vlog a closed polygon with an optional fill and label.
+///
+/// The stroke width defaults to 0, which scales with the zoom like the lines of [`v_log`].
+///
+/// ```
+/// use v_log::Color;
+///
+/// let hull = [[0.0, 0.0], [10.0, 0.0], [5.0, 8.0]];
+/// web_vlog::polygon!("physics", hull, Color::Base);
+/// web_vlog::polygon!("physics", hull, Color::Warn, fill: Color::Hex(0xFFCC0040));
+/// web_vlog::polygon!(target: "collision", "physics", hull, Color::Error, width: 2.0, "hull {}", 1);
+/// ```
+#[macro_export]
+macro_rules! polygon {
+    (target: $target:expr, $surface:expr, $points:expr, $($rest:tt)+) => {
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::Polygon(::std::iter::IntoIterator::into_iter($points).collect()),
+            $($rest)+
+        )
+    };
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::polygon!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Vlog a circle with an optional fill and label, see [`polygon!`](crate::polygon!) for the options.
+///
+/// ```
+/// use v_log::Color;
+///
+/// web_vlog::circle!("physics", [5.0, 5.0], 3.0, Color::Base, fill: Color::Info, "r = {}", 3.0);
+/// ```
+#[macro_export]
+macro_rules! circle {
+    (target: $target:expr, $surface:expr, $center:expr, $radius:expr, $($rest:tt)+) => {{
+        let radius: f64 = $radius;
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::Ellipse {
+                center: $center,
+                radii: [radius, radius],
+                rotation: 0.0,
+            },
+            $($rest)+
+        )
+    }};
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::circle!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Vlog an ellipse with the radii `[rx, ry]` rotated by `rotation` radians,
+/// see [`polygon!`](crate::polygon!) for the options.
+///
+/// ```
+/// use v_log::Color;
+///
+/// web_vlog::ellipse!("physics", [5.0, 5.0], [4.0, 2.0], 0.5, Color::Base);
+/// ```
+#[macro_export]
+macro_rules! ellipse {
+    (target: $target:expr, $surface:expr, $center:expr, $radii:expr, $rotation:expr, $($rest:tt)+) => {
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::Ellipse {
+                center: $center,
+                radii: $radii,
+                rotation: $rotation,
+            },
+            $($rest)+
+        )
+    };
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::ellipse!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Vlog a circular arc from the `start` to the `stop` angle in radians,
+/// see [`polygon!`](crate::polygon!) for the options. A filled arc fills the sector of the circle.
+///
+/// ```
+/// use v_log::Color;
+///
+/// let angle = std::f64::consts::FRAC_PI_2;
+/// web_vlog::arc!("physics", [5.0, 5.0], 3.0, 0.0, angle, Color::Base, fill: Color::Healthy);
+/// ```
+#[macro_export]
+macro_rules! arc {
+    (target: $target:expr, $surface:expr, $center:expr, $radius:expr, $start:expr, $stop:expr, $($rest:tt)+) => {
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::Arc {
+                center: $center,
+                radius: $radius,
+                start: $start,
+                stop: $stop,
+            },
+            $($rest)+
+        )
+    };
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::arc!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Vlog an axis-aligned rectangle from the `min` corner with the `size` `[width, height]`,
+/// see [`polygon!`](crate::polygon!) for the options.
+///
+/// ```
+/// use v_log::Color;
+///
+/// web_vlog::rect!("vision", [10.0, 20.0], [30.0, 15.0], Color::Healthy, "person {:.0}%", 97.0);
+/// ```
+#[macro_export]
+macro_rules! rect {
+    (target: $target:expr, $surface:expr, $min:expr, $size:expr, $($rest:tt)+) => {{
+        let [x, y]: [f64; 2] = $min;
+        let [w, h]: [f64; 2] = $size;
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::Rect {
+                center: [x + w / 2.0, y + h / 2.0],
+                size: [w, h],
+                rotation: 0.0,
+            },
+            $($rest)+
+        )
+    }};
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::rect!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Vlog a rectangle with the `size` `[width, height]` rotated by `rotation` radians around its `center`,
+/// see [`polygon!`](crate::polygon!) for the options.
+///
+/// ```
+/// use v_log::Color;
+///
+/// web_vlog::rotated_rect!("physics", [5.0, 5.0], [4.0, 2.0], 0.3, Color::Base, fill: Color::Info);
+/// ```
+#[macro_export]
+macro_rules! rotated_rect {
+    (target: $target:expr, $surface:expr, $center:expr, $size:expr, $rotation:expr, $($rest:tt)+) => {
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::Rect {
+                center: $center,
+                size: $size,
+                rotation: $rotation,
+            },
+            $($rest)+
+        )
+    };
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::rotated_rect!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Vlog a path of straight and curved [`PathSegment`]s from `start`,
+/// see [`polygon!`](crate::polygon!) for the options.
+///
+/// ```
+/// use v_log::Color;
+/// use web_vlog::shape::PathSegment::*;
+///
+/// let segments = [Quadratic([5.0, 10.0], [10.0, 0.0]), Cubic([12.0, -5.0], [18.0, 5.0], [20.0, 0.0])];
+/// web_vlog::bezier!("planner", [0.0, 0.0], segments, Color::Info, width: 2.0);
+/// ```
+#[macro_export]
+macro_rules! bezier {
+    (target: $target:expr, $surface:expr, $start:expr, $segments:expr, $($rest:tt)+) => {
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::Path {
+                start: $start,
+                segments: ::std::iter::IntoIterator::into_iter($segments).collect(),
+            },
+            $($rest)+
+        )
+    };
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::bezier!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Vlog a smooth Catmull-Rom spline through the points, see [`Shape::catmull_rom`]
+/// and [`polygon!`](crate::polygon!) for the options.
+///
+/// ```
+/// use v_log::Color;
+///
+/// let waypoints = [[0.0, 0.0], [10.0, 5.0], [20.0, 0.0], [30.0, 5.0]];
+/// web_vlog::spline!("planner", waypoints, Color::Healthy, "trajectory");
+/// ```
+#[macro_export]
+macro_rules! spline {
+    (target: $target:expr, $surface:expr, $points:expr, $($rest:tt)+) => {
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::catmull_rom(
+                &::std::iter::IntoIterator::into_iter($points).collect::<::std::vec::Vec<[f64; 2]>>(),
+            ),
+            $($rest)+
+        )
+    };
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::spline!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Vlog a raster image from the `min` corner stretched to the `size` `[width, height]`,
+/// see [`ImageData`] for the supported formats and [`polygon!`](crate::polygon!) for the options.
+///
+/// The pixels are embedded into the message, so large images should be vlogged sparingly.
+///
+/// ```
+/// use v_log::Color;
+/// use web_vlog::shape::ImageData;
+///
+/// // a 2x2 checkerboard, scaled up to 20x20.
+/// let pixels = [[0, 0, 0, 255], [255; 4], [255; 4], [0, 0, 0, 255]].concat();
+/// let image = ImageData::Rgba { width: 2, height: 2, pixels };
+/// web_vlog::image!("camera", [0.0, 0.0], [20.0, 20.0], image, Color::Base, width: 1.0, "frame {}", 7);
+/// ```
+#[macro_export]
+macro_rules! image {
+    (target: $target:expr, $surface:expr, $min:expr, $size:expr, $data:expr, $($rest:tt)+) => {
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::Image {
+                min: $min,
+                size: $size,
+                data: $data,
+            },
+            $($rest)+
+        )
+    };
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::image!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Vlog a 2D scalar field with `width` values per row as a heatmap from the `min` corner
+/// stretched to the `size` `[width, height]`, see [`ImageData::heatmap`].
+/// The values are auto-scaled to the [`Colormap`]. For a fixed range, use [`image!`](crate::image!)
+/// with [`ImageData::heatmap`] directly. See [`polygon!`](crate::polygon!) for the options.
+///
+/// ```
+/// use v_log::Color;
+/// use web_vlog::shape::Colormap;
+///
+/// let costs: Vec<f64> = (0..64).map(|i| ((i % 8) * (i / 8)) as f64).collect();
+/// web_vlog::heatmap!("planner", [0.0, 0.0], [80.0, 80.0], 8, &costs, Colormap::Viridis, Color::Base, "costs");
+/// ```
+#[macro_export]
+macro_rules! heatmap {
+    (target: $target:expr, $surface:expr, $min:expr, $size:expr, $width:expr, $values:expr, $colormap:expr, $($rest:tt)+) => {
+        $crate::image!(
+            target: $target,
+            $surface,
+            $min,
+            $size,
+            $crate::shape::ImageData::heatmap($width, $values, None, $colormap),
+            $($rest)+
+        )
+    };
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::heatmap!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Append the sample `(x, y)` to a series of the line chart on the surface.
+///
+/// The viewer draws all series of the surface into one chart with axes, ticks and a legend,
+/// which are scaled to fit all samples. Samples should be appended in increasing `x`.
+/// Without a color, the series get distinct colors in the order they first appear.
+/// Non-finite samples are ignored. The samples are kept until the surface is cleared.
+///
+/// ```
+/// use v_log::Color;
+///
+/// for step in 0..100 {
+///     let loss = 1.0 / (step as f64 + 1.0);
+///     web_vlog::plot!("training", "loss", step as f64, loss);
+///     web_vlog::plot!("training", "target", step as f64, 0.1, Color::Healthy);
+/// }
+/// ```
+#[macro_export]
+macro_rules! plot {
+    (target: $target:expr, $surface:expr, $series:expr, $x:expr, $y:expr $(, $color:expr)? $(,)?) => {
+        $crate::shape::sample(
+            $target,
+            &$surface,
+            &$series,
+            [$x, $y],
+            None $(.or(Some($color)))?,
+        )
+    };
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::plot!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Vlog a histogram from the `min` corner with the `size` `[width, height]`,
+/// either from the bin `edges` and `counts` or from raw samples with a number of bins,
+/// see [`Shape::Histogram`] and [`polygon!`](crate::polygon!) for the options.
+///
+/// ```
+/// use v_log::Color;
+///
+/// let edges = [0.0, 1.0, 2.0, 5.0];
+/// web_vlog::histogram!("stats", [0.0, 0.0], [60.0, 40.0], edges, [3.0, 7.0, 2.0], Color::Info);
+/// let latencies = [1.2, 1.5, 0.9, 3.1, 1.1, 1.4];
+/// web_vlog::histogram!("stats", [0.0, 50.0], [60.0, 40.0], samples: &latencies, bins: 4, Color::Base, fill: Color::Info, "latency");
+/// ```
+#[macro_export]
+macro_rules! histogram {
+    (target: $target:expr, $surface:expr, $min:expr, $size:expr, samples: $samples:expr, bins: $bins:expr, $($rest:tt)+) => {
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::histogram($min, $size, $samples, $bins),
+            $($rest)+
+        )
+    };
+    (target: $target:expr, $surface:expr, $min:expr, $size:expr, $edges:expr, $counts:expr, $($rest:tt)+) => {
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::Histogram {
+                min: $min,
+                size: $size,
+                edges: ::std::iter::IntoIterator::into_iter($edges).collect(),
+                counts: ::std::iter::IntoIterator::into_iter($counts).collect(),
+            },
+            $($rest)+
+        )
+    };
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::histogram!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Vlog a grid of 2D vectors as arrows, see [`VectorField`] and [`polygon!`](crate::polygon!) for the options.
+///
+/// ```
+/// use v_log::Color;
+/// use web_vlog::shape::{Colormap, VectorField};
+///
+/// // a vortex on a 10x10 grid.
+/// let vectors = (0..100).map(|i| [(i / 10) as f64 - 4.5, 4.5 - (i % 10) as f64]).collect();
+/// let field = VectorField::new([0.0, 0.0], [10.0, 10.0], 10, vectors).colormap(Colormap::Viridis);
+/// web_vlog::vector_field!("fluid", field, Color::Base, width: 1.0, "velocity");
+/// ```
+#[macro_export]
+macro_rules! vector_field {
+    (target: $target:expr, $surface:expr, $field:expr, $($rest:tt)+) => {
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::VectorField($field),
+            $($rest)+
+        )
+    };
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::vector_field!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Vlog multiline text on a background rectangle, see [`TextBox`] and [`polygon!`](crate::polygon!) for the options.
+///
+/// ```
+/// use v_log::Color;
+/// use web_vlog::shape::TextBox;
+///
+/// let state = ("idle", 3, [1.5, 2.0]);
+/// let layout = TextBox::new([10.0, 10.0]).wrap(200.0);
+/// web_vlog::text_box!("robot", layout, Color::Base, "state:\n{:#?}", state);
+/// web_vlog::text_box!("robot", TextBox::new([10.0, 100.0]), Color::Warn, fill: Color::Hex(0x402000C0), width: 1.0, "low battery");
+/// ```
+#[macro_export]
+macro_rules! text_box {
+    (target: $target:expr, $surface:expr, $layout:expr, $($rest:tt)+) => {
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::TextBox($layout),
+            $($rest)+
+        )
+    };
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::text_box!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Show the progress `current` of `total` as a bar on the surface, see [`Shape::Progress`].
+///
+/// Repeated updates with the same `id` replace the bar instead of adding a new one.
+/// Without a label, the `id` is shown. See [`polygon!`](crate::polygon!) for the options.
+///
+/// ```
+/// use v_log::Color;
+///
+/// for chunk in 0..=10 {
+///     web_vlog::progress!("status", "download", chunk as f64, 10.0, Color::Info);
+/// }
+/// web_vlog::progress!("status", "solver", 0.25, 1.0, Color::Healthy, "solving {}", "model.obj");
+/// ```
+#[macro_export]
+macro_rules! progress {
+    (target: $target:expr, $surface:expr, $id:expr, $current:expr, $total:expr, $($rest:tt)+) => {
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::Progress {
+                id: ::std::string::ToString::to_string(&$id),
+                current: $current,
+                total: $total,
+            },
+            $($rest)+
+        )
+    };
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::progress!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Vlog a small table, which replaces the previous table with the same id,
+/// see [`Table`] and [`polygon!`](crate::polygon!) for the options.
+///
+/// ```
+/// use v_log::Color;
+/// use web_vlog::shape::Table;
+///
+/// let stats = Table::new("solver", [0.0, 0.0], &["iteration", "residual", "status"])
+///     .row([1.into(), 0.5.into(), "running".into()])
+///     .row([2.into(), 0.01.into(), "converged".into()]);
+/// web_vlog::table!("status", stats, Color::Base);
+/// ```
+#[macro_export]
+macro_rules! table {
+    (target: $target:expr, $surface:expr, $table:expr, $($rest:tt)+) => {
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::Table($table),
+            $($rest)+
+        )
+    };
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::table!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Vlog a triangle mesh, see [`Shape::Mesh`] and [`polygon!`](crate::polygon!) for the options.
+///
+/// ```
+/// use v_log::Color;
+///
+/// let vertices = [[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]];
+/// let triangles = [[0, 1, 2], [0, 2, 3]];
+/// web_vlog::mesh!("geometry", vertices, triangles, Color::Info);
+/// // with a wireframe
+/// web_vlog::mesh!("geometry", vertices, triangles, Color::Base, fill: Color::Hex(0x5588FF80), width: 1.0, "quad");
+/// ```
+#[macro_export]
+macro_rules! mesh {
+    (target: $target:expr, $surface:expr, $vertices:expr, $triangles:expr, $($rest:tt)+) => {
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::Mesh {
+                vertices: ::std::iter::IntoIterator::into_iter($vertices).collect(),
+                triangles: ::std::iter::IntoIterator::into_iter($triangles).collect(),
+            },
+            $($rest)+
+        )
+    };
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::mesh!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Vlog an open polyline with a color gradient along it, see [`Shape::GradientLine`].
+/// The first color is used for the label. See [`polygon!`](crate::polygon!) for the options,
+/// except that there is no stroke color and no fill.
+///
+/// ```
+/// use v_log::Color;
+///
+/// let trajectory = [[0.0, 0.0], [10.0, 5.0], [20.0, 3.0], [30.0, 10.0]];
+/// web_vlog::gradient_line!("robot", trajectory, [Color::Info, Color::Error], width: 2.0, "t = 0..3s");
+/// let speed = [Color::Healthy, Color::Healthy, Color::Warn, Color::Error];
+/// web_vlog::gradient_line!("robot", trajectory, speed);
+/// ```
+#[macro_export]
+macro_rules! gradient_line {
+    (target: $target:expr, $surface:expr, $points:expr, $colors:expr $(, $($rest:tt)+)?) => {{
+        let colors: ::std::vec::Vec<$crate::__Color> = ::std::iter::IntoIterator::into_iter($colors).collect();
+        let stroke = ::std::clone::Clone::clone(colors.first().unwrap_or(&$crate::__Color::Base));
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::GradientLine {
+                points: ::std::iter::IntoIterator::into_iter($points).collect(),
+                colors,
+            },
+            stroke,
+            $($($rest)+)?
+        )
+    }};
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::gradient_line!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Vlog a filled area below a polyline down to a baseline or between two polylines,
+/// see [`Shape::Area`] and [`polygon!`](crate::polygon!) for the options.
+///
+/// ```
+/// use v_log::Color;
+///
+/// let mean = [[0.0, 50.0], [10.0, 40.0], [20.0, 45.0]];
+/// let high = [[0.0, 45.0], [10.0, 32.0], [20.0, 40.0]];
+/// let low = [[0.0, 55.0], [10.0, 48.0], [20.0, 50.0]];
+/// web_vlog::area!("forecast", high, low, Color::Info, "95% interval");
+/// web_vlog::area!("forecast", mean, baseline: 60.0, Color::Base, fill: Color::Hex(0xFFFFFF20));
+/// ```
+#[macro_export]
+macro_rules! area {
+    (target: $target:expr, $surface:expr, $points:expr, baseline: $baseline:expr, $($rest:tt)+) => {{
+        let upper: ::std::vec::Vec<[f64; 2]> = ::std::iter::IntoIterator::into_iter($points).collect();
+        let baseline: f64 = $baseline;
+        let lower = match (upper.first(), upper.last()) {
+            (Some(first), Some(last)) => vec![[first[0], baseline], [last[0], baseline]],
+            _ => vec![],
+        };
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::Area { upper, lower },
+            $($rest)+
+        )
+    }};
+    (target: $target:expr, $surface:expr, $upper:expr, $lower:expr, $($rest:tt)+) => {
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::Area {
+                upper: ::std::iter::IntoIterator::into_iter($upper).collect(),
+                lower: ::std::iter::IntoIterator::into_iter($lower).collect(),
+            },
+            $($rest)+
+        )
+    };
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::area!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+/// Annotate a point with the label in a bubble with a leader line, see [`Shape::Callout`]
+/// and [`polygon!`](crate::polygon!) for the options.
+///
+/// ```
+/// use v_log::Color;
+///
+/// web_vlog::callout!("training", [120.0, 35.0], Color::Warn, "first divergence at step {}", 120);
+/// ```
+#[macro_export]
+macro_rules! callout {
+    (target: $target:expr, $surface:expr, $anchor:expr, $($rest:tt)+) => {
+        $crate::__shape!(
+            $target,
+            $surface,
+            $crate::shape::Shape::Callout { anchor: $anchor },
+            $($rest)+
+        )
+    };
+    ($surface:expr, $($rest:tt)+) => {
+        $crate::callout!(target: module_path!(), $surface, $($rest)+)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The edges and the counts of a histogram.
+    fn histogram(samples: &[f64], bins: usize) -> (Vec<f64>, Vec<f64>) {
+        match Shape::histogram([0.0; 2], [100.0; 2], samples, bins) {
+            Shape::Histogram { edges, counts, .. } => (edges, counts),
+            _ => unreachable!(),
+        }
+    }
+
+    fn path(points: &[[f64; 2]]) -> ([f64; 2], Vec<PathSegment>) {
+        match Shape::catmull_rom(points) {
+            Shape::Path { start, segments } => (start, segments),
+            _ => unreachable!(),
+        }
+    }
+
+    fn rgba(image: ImageData) -> (u32, u32, Vec<[u8; 4]>) {
+        match image {
+            ImageData::Rgba {
+                width,
+                height,
+                pixels,
+            } => (
+                width,
+                height,
+                pixels.chunks(4).map(|p| p.try_into().unwrap()).collect(),
+            ),
+            ImageData::Png(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn histogram_puts_the_largest_sample_into_the_last_bin() {
+        let (edges, counts) = histogram(&[0.0, 1.0, 2.0, 3.0, 4.0], 4);
+        assert_eq!(edges, [0.0, 1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(counts, [1.0, 1.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn histogram_of_equal_samples_is_centered() {
+        let (edges, counts) = histogram(&[2.0, 2.0], 2);
+        assert_eq!(edges, [1.5, 2.0, 2.5]);
+        assert_eq!(counts, [0.0, 2.0]);
+    }
+
+    #[test]
+    fn histogram_ignores_non_finite_samples() {
+        let (edges, counts) = histogram(&[f64::NAN, 1.0, f64::INFINITY, 3.0], 2);
+        assert_eq!(edges, [1.0, 2.0, 3.0]);
+        assert_eq!(counts, [1.0, 1.0]);
+        // without finite samples and bins, there is a single empty bin.
+        let (edges, counts) = histogram(&[f64::NAN, f64::NEG_INFINITY], 0);
+        assert_eq!(edges, [0.0, 1.0]);
+        assert_eq!(counts, [0.0]);
+    }
+
+    #[test]
+    fn catmull_rom_of_few_points() {
+        assert_eq!(path(&[]), ([0.0; 2], vec![]));
+        assert_eq!(path(&[[1.0, 2.0]]), ([1.0, 2.0], vec![]));
+        // with two points, both tangents point along the line.
+        assert_eq!(
+            path(&[[0.0, 0.0], [6.0, 3.0]]),
+            (
+                [0.0, 0.0],
+                vec![PathSegment::Cubic([1.0, 0.5], [5.0, 2.5], [6.0, 3.0])]
+            )
+        );
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_all_points() {
+        let points = [[0.0, 0.0], [10.0, 5.0], [20.0, -5.0], [30.0, 0.0]];
+        let (start, segments) = path(&points);
+        assert_eq!(start, points[0]);
+        assert_eq!(segments.len(), 3);
+        for (segment, point) in segments.iter().zip(&points[1..]) {
+            let PathSegment::Cubic(_, _, end) = segment else {
+                panic!("not a cubic segment: {segment:?}");
+            };
+            assert_eq!(end, point);
+        }
+        // the curve is smooth at the inner points.
+        let PathSegment::Cubic(_, [x, y], _) = segments[0] else {
+            unreachable!()
+        };
+        let PathSegment::Cubic([x2, y2], _, _) = segments[1] else {
+            unreachable!()
+        };
+        assert_eq!([x + x2, y + y2], [20.0, 10.0]);
+    }
+
+    #[test]
+    fn heatmap_ignores_the_incomplete_last_row() {
+        let (width, height, pixels) = rgba(ImageData::heatmap(
+            2,
+            &[0.0, 1.0, 2.0, 3.0, 4.0],
+            None,
+            Colormap::Grayscale,
+        ));
+        assert_eq!((width, height, pixels.len()), (2, 2, 4));
+        assert_eq!(pixels[0], [0, 0, 0, 255]);
+        assert_eq!(pixels[3], [255, 255, 255, 255]);
+        let (_, height, pixels) = rgba(ImageData::heatmap(0, &[1.0], None, Colormap::Grayscale));
+        assert_eq!((height, pixels.len()), (0, 0));
+    }
+
+    #[test]
+    fn heatmap_clamps_to_the_range_and_hides_non_finite_values() {
+        let (_, _, pixels) = rgba(ImageData::heatmap(
+            4,
+            &[-1.0, f64::NAN, 0.5, 2.0],
+            Some([0.0, 1.0]),
+            Colormap::Grayscale,
+        ));
+        assert_eq!(
+            pixels,
+            [
+                [0, 0, 0, 255],
+                [0, 0, 0, 0],
+                [128, 128, 128, 255],
+                [255, 255, 255, 255]
+            ]
+        );
+    }
+
+    #[test]
+    fn colormap_clamps_its_input() {
+        for colormap in [Colormap::Grayscale, Colormap::Viridis, Colormap::Coolwarm] {
+            assert_eq!(colormap.map(-1.0), colormap.map(0.0));
+            assert_eq!(colormap.map(f64::NAN), colormap.map(0.0));
+            assert_eq!(colormap.map(2.0), colormap.map(1.0));
+        }
+        assert_eq!(Colormap::Viridis.map(0.0), [0x44, 0x01, 0x54, 255]);
+        assert_eq!(Colormap::Viridis.map(1.0), [0xfd, 0xe7, 0x25, 255]);
+        assert_eq!(Colormap::Coolwarm.map(0.5), [0xdd, 0xdd, 0xdd, 255]);
+    }
+}
@@ -0,0 +1,327 @@
+//! Periodic in-memory snapshots of the retained surfaces for post-mortem debugging.
+//!
+//! The retained state of a surface are all records since it was last cleared,
+//! limited by the [`RetentionPolicy`](crate::RetentionPolicy).
+//! Every snapshot stores the state of all surfaces as a [recording](crate::recording),
+//! where all messages are at the start of the recording. Every surface starts with a clear
+//! message, so a snapshot can be replayed without knowing what was shown before.
+//!
+//! The vlogging threads only mark their messages for retention. The messages are encoded and
+//! retained by the thread, which takes them from the queue, and it also takes the periodic snapshots,
+//! so they are taken while nothing is vlogged as well. Reading the retained state first waits
+//! a moment for the messages still on their way, so it includes everything vlogged before.
+
+use crate::{
+    recording::Recorder,
+    retention::Retained,
+    wire::{self, Message},
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Condvar, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// The snapshot state, if snapshots are enabled with [`Builder::auto_snapshot`](crate::Builder::auto_snapshot).
+static SNAPSHOTS: Mutex<Option<Snapshots>> = Mutex::new(None);
+
+/// Set when the snapshots are enabled, so the queue is drained even while no client is connected.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The number of messages marked for retention.
+static MARKED: AtomicU64 = AtomicU64::new(0);
+
+/// The number of marked messages, which were retained or discarded.
+static SETTLED: (Mutex<u64>, Condvar) = (Mutex::new(0), Condvar::new());
+
+/// The longest time reading the retained state waits for the marked messages,
+/// e.g. while the upstream server of a forwarding vlogger can't be reached.
+const SETTLE: Duration = Duration::from_millis(500);
+
+struct Snapshots {
+    interval: Duration,
+    keep: usize,
+    /// Encodes the records in the basic format, so the snapshots don't depend on each other.
+    encoder: wire::Encoder,
+    /// The encoded records since the last clear of each surface.
+    retained: Retained,
+    last: Instant,
+    ring: VecDeque<Snapshot>,
+    /// The retained records of every surface by the name of the checkpoint.
+    checkpoints: HashMap<String, Vec<(String, Vec<String>)>>,
+}
+
+struct Snapshot {
+    /// Wall-clock time in milliseconds since the unix epoch.
+    wall: u128,
+    data: Vec<u8>,
+}
+
+impl Snapshots {
+    /// Serialize the current state of all surfaces into the ring.
+    fn take(&mut self) {
+        // writing to a `Vec` can't fail and the encoded messages are JSON objects on a single line.
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+        for (surface, records) in self.retained.surfaces() {
+            if let Some(clear) = self.encoder.encode(&Message::Clear(surface.to_owned()), 1) {
+                recorder.write_message_at(Duration::ZERO, &clear).unwrap();
+            }
+            for msg in records {
+                recorder.write_message_at(Duration::ZERO, msg).unwrap();
+            }
+        }
+        if self.ring.len() >= self.keep {
+            self.ring.pop_front();
+        }
+        self.ring.push_back(Snapshot {
+            wall: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis()),
+            data: recorder.into_inner(),
+        });
+        self.last = Instant::now();
+    }
+    /// Apply a message to the retained state and take a snapshot if the interval has passed.
+    fn apply(&mut self, message: &Message) {
+        // take the snapshot before applying the message, as time has passed since it was produced.
+        if self.last.elapsed() >= self.interval {
+            self.take();
+        }
+        match message {
+            Message::Record { surface, .. } | Message::Sample { surface, .. } => {
+                if let Some(msg) = self.encoder.encode(message, 1) {
+                    self.retained.push(surface, msg);
+                }
+            }
+            Message::Clear(surface) => {
+                self.retained.clear(surface);
+            }
+            Message::Setting(_) | Message::Flush => {}
+        }
+    }
+}
+
+/// Wait until the messages marked before are retained, or for at most [`SETTLE`].
+fn settle() {
+    let marked = MARKED.load(Ordering::SeqCst);
+    let settled = SETTLED.0.lock().unwrap();
+    drop(
+        SETTLED
+            .1
+            .wait_timeout_while(settled, SETTLE, |settled| *settled < marked)
+            .unwrap(),
+    );
+}
+
+/// Count a marked message as settled.
+fn settled() {
+    *SETTLED.0.lock().unwrap() += 1;
+    SETTLED.1.notify_all();
+}
+
+/// The encoded messages of the current retained state, where every surface starts with a clear message.
+pub(crate) fn retained_messages() -> Vec<String> {
+    settle();
+    let mut snapshots = SNAPSHOTS.lock().unwrap();
+    let Some(snapshots) = &mut *snapshots else {
+        return vec![];
+    };
+    let mut messages = vec![];
+    for (surface, records) in snapshots.retained.surfaces() {
+        if let Some(clear) = snapshots
+            .encoder
+            .encode(&Message::Clear(surface.to_owned()), 1)
+        {
+            messages.push(clear);
+        }
+        messages.extend(records.map(str::to_owned));
+    }
+    messages
+}
+
+/// Save the retained records of all surfaces as checkpoint and return the number of saved records.
+pub(crate) fn checkpoint(name: &str) -> usize {
+    settle();
+    let mut snapshots = SNAPSHOTS.lock().unwrap();
+    let Some(snapshots) = &mut *snapshots else {
+        return 0;
+    };
+    let surfaces: Vec<(String, Vec<String>)> = snapshots
+        .retained
+        .surfaces()
+        .map(|(surface, records)| (surface.to_owned(), records.map(str::to_owned).collect()))
+        .collect();
+    let count = surfaces.iter().map(|(_, records)| records.len()).sum();
+    snapshots.checkpoints.insert(name.to_owned(), surfaces);
+    count
+}
+
+/// Reset the surfaces of the checkpoint to their saved records.
+///
+/// Returns the encoded messages, which clear the surfaces and resend the records,
+/// or `None` if there is no checkpoint with the name.
+pub(crate) fn restore(name: &str) -> Option<Vec<String>> {
+    settle();
+    let mut snapshots = SNAPSHOTS.lock().unwrap();
+    let snapshots = snapshots.as_mut()?;
+    let surfaces = snapshots.checkpoints.get(name)?.clone();
+    let mut messages = vec![];
+    for (surface, records) in surfaces {
+        snapshots.retained.clear(&surface);
+        if let Some(clear) = snapshots
+            .encoder
+            .encode(&Message::Clear(surface.clone()), 1)
+        {
+            messages.push(clear);
+        }
+        for msg in records {
+            messages.push(msg.clone());
+            snapshots.retained.push(&surface, msg);
+        }
+    }
+    Some(messages)
+}
+
+/// Enable the snapshots. Already retained state is kept if they were enabled before.
+pub(crate) fn enable(interval: Duration, keep: usize) {
+    let mut snapshots = SNAPSHOTS.lock().unwrap();
+    match &mut *snapshots {
+        Some(snapshots) => {
+            snapshots.interval = interval;
+            snapshots.keep = keep.max(1);
+        }
+        None => {
+            *snapshots = Some(Snapshots {
+                interval,
+                keep: keep.max(1),
+                encoder: wire::Encoder::new(false),
+                retained: Retained::default(),
+                last: Instant::now(),
+                ring: VecDeque::new(),
+                checkpoints: HashMap::new(),
+            })
+        }
+    }
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Check if the snapshots are enabled.
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Mark a message for retention before it is queued. It has to be passed to [`retain`] or [`discard`].
+pub(crate) fn mark() {
+    MARKED.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Apply a marked message to the retained state and take a snapshot if the interval has passed.
+pub(crate) fn retain(message: &Message) {
+    if let Some(snapshots) = &mut *SNAPSHOTS.lock().unwrap() {
+        snapshots.apply(message);
+    }
+    settled();
+}
+
+/// Forget a marked message, which couldn't be queued.
+pub(crate) fn discard() {
+    settled();
+}
+
+/// Take a snapshot if the interval has passed since the last one.
+pub(crate) fn tick() {
+    if let Some(snapshots) = &mut *SNAPSHOTS.lock().unwrap() {
+        if snapshots.last.elapsed() >= snapshots.interval {
+            snapshots.take();
+        }
+    }
+}
+
+/// Write the snapshots to the directory and return the paths of the written files.
+///
+/// If `wait` is false and the snapshots are currently in use, nothing is written.
+/// This avoids a deadlock when called from a panic inside of the vlogger.
+pub(crate) fn dump(dir: &Path, wait: bool) -> io::Result<Vec<PathBuf>> {
+    let mut snapshots = if wait {
+        settle();
+        SNAPSHOTS.lock().unwrap_or_else(|e| e.into_inner())
+    } else {
+        match SNAPSHOTS.try_lock() {
+            Ok(snapshots) => snapshots,
+            Err(std::sync::TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(std::sync::TryLockError::WouldBlock) => return Ok(vec![]),
+        }
+    };
+    let Some(snapshots) = &mut *snapshots else {
+        return Ok(vec![]);
+    };
+    // include the latest state as well.
+    snapshots.take();
+    std::fs::create_dir_all(dir)?;
+    let mut paths = vec![];
+    for snapshot in &snapshots.ring {
+        let path = dir.join(format!("snapshot-{}.vlog", snapshot.wall));
+        std::fs::write(&path, &snapshot.data)?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Install a panic hook, which dumps the snapshots into the temporary directory
+/// before calling the previously installed hook.
+pub(crate) fn install_panic_hook() {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let dir = std::env::temp_dir().join("web-vlog-snapshots");
+        match dump(&dir, false) {
+            Ok(paths) if !paths.is_empty() => {
+                eprintln!(
+                    "web-vlog: dumped {} surface snapshots to {}",
+                    paths.len(),
+                    dir.display()
+                );
+            }
+            Ok(_) => {}
+            Err(err) => eprintln!("web-vlog: failed to dump the surface snapshots: {err}"),
+        }
+        hook(info);
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::RecordingReader;
+
+    #[test]
+    fn snapshots_are_recordings() {
+        let mut snapshots = Snapshots {
+            interval: Duration::MAX,
+            keep: 2,
+            encoder: wire::Encoder::new(false),
+            retained: Retained::default(),
+            last: Instant::now(),
+            ring: VecDeque::new(),
+            checkpoints: HashMap::new(),
+        };
+        snapshots
+            .retained
+            .push("map", r#"{"surf":"map","body":1}"#.to_owned());
+        snapshots.take();
+        let mut reader = RecordingReader::new(&snapshots.ring[0].data[..]);
+        let mut messages = vec![];
+        while let Some((time, message)) = reader.next_message().unwrap() {
+            assert_eq!(time, Duration::ZERO);
+            messages.push(message);
+        }
+        assert_eq!(reader.skipped(), 0);
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains(r#""clear""#), "{}", messages[0]);
+        assert_eq!(messages[1], r#"{"surf":"map","body":1}"#);
+    }
+}
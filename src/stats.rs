@@ -0,0 +1,337 @@
+//! Statistics about the vlogger itself.
+//!
+//! With the `self-profile` feature the overhead of the vlogger on the vlogging
+//! threads is measured. Without it, the profiling counters stay at zero and cost nothing.
+//! The statistics are served as JSON on `/stats` and in the Prometheus text format on `/metrics`
+//! by the vlogging server, so they can be scraped by existing monitoring.
+
+#[cfg(feature = "self-profile")]
+use std::time::Instant;
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// Statistics about the vlogger, see [`stats`].
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Stats {
+    /// Number of records which passed the filters and were serialized.
+    ///
+    /// Only counted with the `self-profile` feature.
+    pub vlog_calls: u64,
+    /// Total time spent inside [`v_log::VLog::vlog`] on the vlogging threads.
+    ///
+    /// Only measured with the `self-profile` feature.
+    pub vlog_time: Duration,
+    /// Total bytes allocated for serialized messages.
+    ///
+    /// Only measured with the `self-profile` feature.
+    pub bytes_allocated: u64,
+    /// Total time messages waited in the queue before the server thread picked them up.
+    ///
+    /// Only measured with the `self-profile` feature.
+    pub queue_wait: Duration,
+    /// The longest time a single message waited in the queue.
+    ///
+    /// Only measured with the `self-profile` feature.
+    pub queue_wait_max: Duration,
+    /// Number of records dropped, because the queue exceeded the [`Builder::queue_limit`](crate::Builder::queue_limit).
+    pub dropped: u64,
+    /// Number of records dropped for a single client, because its queue exceeded the
+    /// [`Builder::client_queue`](crate::Builder::client_queue).
+    pub client_dropped: u64,
+    /// Number of records, which were discarded before they were sent to a client,
+    /// because their surface was cleared in the meantime.
+    pub coalesced: u64,
+    /// Number of records dropped by the [`RateLimit`](crate::RateLimit).
+    pub rate_limited: u64,
+    /// Number of retained records evicted by the [`RetentionPolicy`](crate::RetentionPolicy).
+    pub evicted: u64,
+    /// Number of messages the server thread took from the queue of the vlogging threads.
+    pub received: u64,
+    /// Number of message bytes written to the websockets of all clients, without the framing.
+    pub bytes_sent: u64,
+    /// Number of messages currently waiting for the server thread.
+    pub queued: usize,
+    /// Number of currently connected clients.
+    pub clients: usize,
+}
+
+impl Stats {
+    /// The average time spent inside a single [`v_log::VLog::vlog`] call.
+    pub fn vlog_time_avg(&self) -> Duration {
+        if self.vlog_calls == 0 {
+            Duration::ZERO
+        } else {
+            self.vlog_time / self.vlog_calls.min(u32::MAX as u64) as u32
+        }
+    }
+}
+
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+static RECEIVED: AtomicU64 = AtomicU64::new(0);
+static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+static CLIENT_DROPPED: AtomicU64 = AtomicU64::new(0);
+static COALESCED: AtomicU64 = AtomicU64::new(0);
+static EVICTED: AtomicU64 = AtomicU64::new(0);
+static RATE_LIMITED: AtomicU64 = AtomicU64::new(0);
+/// The records dropped on the vlogging threads per surface since the clients were last told.
+static SURFACE_DROPS: Mutex<Option<HashMap<String, u64>>> = Mutex::new(None);
+#[cfg(feature = "self-profile")]
+static VLOG_CALLS: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "self-profile")]
+static VLOG_NANOS: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "self-profile")]
+static BYTES_ALLOCATED: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "self-profile")]
+static QUEUE_WAIT_NANOS: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "self-profile")]
+static QUEUE_WAIT_MAX_NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// Get the current statistics of the vlogger.
+///
+/// The counters are global and accumulate from the start of the process.
+/// The running vlogger serves them as JSON on the `/stats` path and in the Prometheus text format
+/// on the `/metrics` path as well.
+pub fn stats() -> Stats {
+    #[allow(unused_mut)]
+    let mut stats = Stats {
+        dropped: DROPPED.load(Ordering::Relaxed),
+        client_dropped: CLIENT_DROPPED.load(Ordering::Relaxed),
+        coalesced: COALESCED.load(Ordering::Relaxed),
+        evicted: EVICTED.load(Ordering::Relaxed),
+        rate_limited: RATE_LIMITED.load(Ordering::Relaxed),
+        received: RECEIVED.load(Ordering::Relaxed),
+        bytes_sent: BYTES_SENT.load(Ordering::Relaxed),
+        queued: crate::QUEUED.load(Ordering::Relaxed),
+        clients: crate::CLIENTS.load(Ordering::Relaxed),
+        ..Default::default()
+    };
+    #[cfg(feature = "self-profile")]
+    {
+        stats.vlog_calls = VLOG_CALLS.load(Ordering::Relaxed);
+        stats.vlog_time = Duration::from_nanos(VLOG_NANOS.load(Ordering::Relaxed));
+        stats.bytes_allocated = BYTES_ALLOCATED.load(Ordering::Relaxed);
+        stats.queue_wait = Duration::from_nanos(QUEUE_WAIT_NANOS.load(Ordering::Relaxed));
+        stats.queue_wait_max = Duration::from_nanos(QUEUE_WAIT_MAX_NANOS.load(Ordering::Relaxed));
+    }
+    stats
+}
+
+/// The current statistics as JSON object for the `/stats` endpoint. Durations are in seconds.
+pub(crate) fn to_json() -> String {
+    let s = stats();
+    let mut out = String::new();
+    write!(
+        &mut out,
+        "{{\"received\":{},\"bytes_sent\":{},\"queued\":{},\"clients\":{},\"dropped\":{},\"client_dropped\":{},\"coalesced\":{},\"rate_limited\":{},\"evicted\":{}",
+        s.received,
+        s.bytes_sent,
+        s.queued,
+        s.clients,
+        s.dropped,
+        s.client_dropped,
+        s.coalesced,
+        s.rate_limited,
+        s.evicted,
+    )
+    .unwrap();
+    #[cfg(feature = "self-profile")]
+    write!(
+        &mut out,
+        ",\"vlog_calls\":{},\"vlog_time\":{},\"bytes_allocated\":{},\"queue_wait\":{},\"queue_wait_max\":{}",
+        s.vlog_calls,
+        s.vlog_time.as_secs_f64(),
+        s.bytes_allocated,
+        s.queue_wait.as_secs_f64(),
+        s.queue_wait_max.as_secs_f64(),
+    )
+    .unwrap();
+    out.push('}');
+    out
+}
+
+/// The current statistics in the Prometheus text format for the `/metrics` endpoint.
+///
+/// The totals are counters, so the rates like records per second are derived by `rate()` in Prometheus.
+pub(crate) fn to_prometheus() -> String {
+    let s = stats();
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(&str, f64)]| {
+        write!(
+            out,
+            "# HELP web_vlog_{name} {help}\n# TYPE web_vlog_{name} {kind}\n"
+        )
+        .unwrap();
+        for (labels, value) in samples {
+            writeln!(out, "web_vlog_{name}{labels} {value}").unwrap();
+        }
+    };
+    metric(
+        "clients",
+        "gauge",
+        "Number of currently connected clients.",
+        &[("", s.clients as f64)],
+    );
+    metric(
+        "queued",
+        "gauge",
+        "Number of messages waiting for the server thread.",
+        &[("", s.queued as f64)],
+    );
+    metric(
+        "received_total",
+        "counter",
+        "Number of messages the server thread took from the queue.",
+        &[("", s.received as f64)],
+    );
+    metric(
+        "sent_bytes_total",
+        "counter",
+        "Number of message bytes written to the websockets of all clients.",
+        &[("", s.bytes_sent as f64)],
+    );
+    metric(
+        "dropped_total",
+        "counter",
+        "Number of records dropped by the backpressure policies.",
+        &[
+            ("{reason=\"queue_limit\"}", s.dropped as f64),
+            ("{reason=\"client_queue\"}", s.client_dropped as f64),
+            ("{reason=\"rate_limit\"}", s.rate_limited as f64),
+        ],
+    );
+    metric(
+        "coalesced_total",
+        "counter",
+        "Number of records discarded, because their surface was cleared before they were sent.",
+        &[("", s.coalesced as f64)],
+    );
+    metric(
+        "evicted_total",
+        "counter",
+        "Number of retained records evicted by the retention policy.",
+        &[("", s.evicted as f64)],
+    );
+    #[cfg(feature = "self-profile")]
+    {
+        metric(
+            "vlog_calls_total",
+            "counter",
+            "Number of records, which passed the filters and were serialized.",
+            &[("", s.vlog_calls as f64)],
+        );
+        metric(
+            "vlog_seconds_total",
+            "counter",
+            "Time spent vlogging on the vlogging threads.",
+            &[("", s.vlog_time.as_secs_f64())],
+        );
+        metric(
+            "allocated_bytes_total",
+            "counter",
+            "Number of bytes allocated for serialized messages.",
+            &[("", s.bytes_allocated as f64)],
+        );
+        metric(
+            "queue_wait_seconds_total",
+            "counter",
+            "Time the messages waited in the queue for the server thread.",
+            &[("", s.queue_wait.as_secs_f64())],
+        );
+        metric(
+            "queue_wait_max_seconds",
+            "gauge",
+            "The longest time a single message waited in the queue.",
+            &[("", s.queue_wait_max.as_secs_f64())],
+        );
+    }
+    out
+}
+
+/// Measures the time until it is dropped as time spent inside `vlog()`.
+#[cfg(feature = "self-profile")]
+pub(crate) struct VlogTimer(Instant);
+
+#[cfg(feature = "self-profile")]
+impl VlogTimer {
+    pub fn start() -> Self {
+        Self(Instant::now())
+    }
+}
+
+#[cfg(feature = "self-profile")]
+impl Drop for VlogTimer {
+    fn drop(&mut self) {
+        VLOG_CALLS.fetch_add(1, Ordering::Relaxed);
+        VLOG_NANOS.fetch_add(self.0.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "self-profile")]
+pub(crate) fn add_allocated(bytes: usize) {
+    BYTES_ALLOCATED.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+#[cfg(feature = "self-profile")]
+pub(crate) fn add_queue_wait(wait: Duration) {
+    let nanos = wait.as_nanos() as u64;
+    QUEUE_WAIT_NANOS.fetch_add(nanos, Ordering::Relaxed);
+    QUEUE_WAIT_MAX_NANOS.fetch_max(nanos, Ordering::Relaxed);
+}
+
+pub(crate) fn add_dropped(surface: &str) {
+    DROPPED.fetch_add(1, Ordering::Relaxed);
+    add_surface_drop(surface);
+}
+
+fn add_surface_drop(surface: &str) {
+    let mut drops = SURFACE_DROPS.lock().unwrap();
+    let drops = drops.get_or_insert_with(HashMap::new);
+    match drops.get_mut(surface) {
+        Some(count) => *count += 1,
+        None => {
+            drops.insert(surface.to_owned(), 1);
+        }
+    }
+}
+
+/// Take the records dropped per surface on the vlogging threads since the last call.
+pub(crate) fn take_surface_drops() -> HashMap<String, u64> {
+    SURFACE_DROPS.lock().unwrap().take().unwrap_or_default()
+}
+
+pub(crate) fn add_received() {
+    RECEIVED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn add_sent(bytes: usize) {
+    BYTES_SENT.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+pub(crate) fn add_client_dropped() {
+    CLIENT_DROPPED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn add_coalesced(records: u64) {
+    if records > 0 {
+        COALESCED.fetch_add(records, Ordering::Relaxed);
+    }
+}
+
+pub(crate) fn add_rate_limited(surface: &str) {
+    RATE_LIMITED.fetch_add(1, Ordering::Relaxed);
+    add_surface_drop(surface);
+}
+
+pub(crate) fn add_evicted(records: u64) {
+    if records > 0 {
+        EVICTED.fetch_add(records, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,110 @@
+//! Typed handles for surfaces.
+
+use std::fmt;
+
+/// A handle to a surface, created with [`surface!`](crate::surface!).
+///
+/// The handle dereferences to the surface name, so it can be used in place of the
+/// surface string in all `v_log` macros. Binding the surface to a variable once turns
+/// misspelled surfaces into compile errors instead of silently creating a new surface.
+///
+/// ```
+/// use v_log::macros::*;
+///
+/// let contacts = web_vlog::surface!("physics/contacts");
+/// point!(contacts, [1.0, 2.0], 5.0, Base);
+/// clear!(contacts);
+/// ```
+#[repr(transparent)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Surface(str);
+
+impl Surface {
+    /// Create a surface handle from its name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name is not a valid surface name, which is checked at compile time when called in a constant.
+    /// Valid names are not empty, don't start or end with whitespace and contain no control characters,
+    /// quotes or backslashes. This way they never have to be escaped in the wire format.
+    pub const fn new(name: &str) -> &Surface {
+        if let Err(msg) = check_name(name) {
+            panic!("{}", msg);
+        }
+        // SAFETY: `Surface` is a `repr(transparent)` wrapper around `str`.
+        unsafe { &*(name as *const str as *const Surface) }
+    }
+    /// The name of the surface.
+    pub const fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The projection of the z coordinate of a surface in the 3D mode, see [`Builder::surface_3d`](crate::Builder::surface_3d).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Projection {
+    /// Parallel projection, which keeps the sizes independent of the depth.
+    Orthographic,
+    /// Perspective projection, where distant visuals are drawn closer together.
+    Perspective,
+}
+
+/// Check if the name is a valid surface name, see [`Surface::new`].
+pub(crate) const fn check_name(name: &str) -> Result<(), &'static str> {
+    let bytes = name.as_bytes();
+    if bytes.is_empty() {
+        return Err("surface names must not be empty");
+    }
+    if bytes[0].is_ascii_whitespace() || bytes[bytes.len() - 1].is_ascii_whitespace() {
+        return Err("surface names must not start or end with whitespace");
+    }
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if b.is_ascii_control() || b == b'"' || b == b'\\' {
+            return Err("surface names must not contain control characters, quotes or backslashes");
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+impl std::ops::Deref for Surface {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Surface {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Surface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Surface").field(&&self.0).finish()
+    }
+}
+
+impl fmt::Display for Surface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Create a `&'static` [`Surface`] handle from a string literal.
+///
+/// The surface name is validated at compile time, see [`Surface::new`].
+///
+/// ```compile_fail
+/// let invalid = web_vlog::surface!("quoted \"surface\"");
+/// ```
+#[macro_export]
+macro_rules! surface {
+    ($name:literal) => {{
+        const SURFACE: &'static $crate::Surface = $crate::Surface::new($name);
+        SURFACE
+    }};
+}
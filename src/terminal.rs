@@ -0,0 +1,354 @@
+//! Rendering to the terminal, when no viewer connects, see [`Builder::terminal_fallback`](crate::Builder::terminal_fallback).
+//!
+//! The fallback registers as a client of the server, so the messages don't wait in the queue forever.
+//! Text messages are written to stderr as they arrive. Points, lines, outlines of shapes and labels
+//! are collected per surface and every [`RENDER`] the changed surfaces are drawn as a coarse grid
+//! of characters, which is scaled to the extent of their visuals. Other visuals are skipped.
+//! When a viewer connects, the fallback stops and takes over again after the viewer disconnected
+//! and no other one connected within the timeout.
+
+use crate::{
+    channel::RecvTimeoutError,
+    json::{self, Value},
+    queue::Clients,
+    wire::Message,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt::Write as _,
+    io::Write as _,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// How often the changed surfaces are drawn.
+const RENDER: Duration = Duration::from_secs(1);
+
+/// The size of the grid of a surface in characters.
+const COLUMNS: usize = 80;
+const ROWS: usize = 24;
+
+/// The most visuals kept per surface, older ones are forgotten.
+const MAX_VISUALS: usize = 10_000;
+
+/// The segments of the polygon approximating an ellipse.
+const ELLIPSE_SEGMENTS: usize = 16;
+
+/// A visual, which can be drawn with characters. The color is an ANSI SGR parameter like `31`.
+enum Visual {
+    Point([f64; 2], String),
+    Line([f64; 2], [f64; 2], String),
+    Text([f64; 2], String, u8, String),
+}
+
+impl Visual {
+    fn points(&self) -> impl Iterator<Item = [f64; 2]> {
+        let (a, b) = match self {
+            Self::Point(p, _) | Self::Text(p, ..) => (*p, None),
+            Self::Line(a, b, _) => (*a, Some(*b)),
+        };
+        std::iter::once(a).chain(b)
+    }
+}
+
+/// The terminal fallback of a server.
+struct Terminal {
+    surfaces: BTreeMap<String, VecDeque<Visual>>,
+    changed: BTreeSet<String>,
+    color: bool,
+}
+
+/// Render the messages to stderr, whenever no client connected within the `timeout`.
+pub(crate) fn run(clients: &Arc<Clients>, timeout: Duration, stop: &AtomicBool) {
+    let mut terminal = Terminal {
+        surfaces: BTreeMap::new(),
+        changed: BTreeSet::new(),
+        color: std::env::var_os("NO_COLOR").is_none(),
+    };
+    loop {
+        // wait until no client of this server was connected for the whole timeout.
+        let idle = clients.wait_timeout_while(timeout, |count| count == 0);
+        if stop.load(Ordering::SeqCst) {
+            return;
+        }
+        if !idle {
+            clients.wait_timeout_while(RENDER, |count| count > 0);
+            continue;
+        }
+        chatter!(
+            info,
+            "no vlogging client connected within {timeout:?}, rendering to the terminal instead"
+        );
+        let client = clients.register();
+        let mut rendered = Instant::now();
+        loop {
+            if stop.load(Ordering::SeqCst) {
+                return;
+            }
+            // the fallback itself is one of the clients, so a second one is a viewer.
+            if clients.count() > 1 {
+                chatter!(
+                    info,
+                    "a vlogging client connected, stopped rendering to the terminal"
+                );
+                break;
+            }
+            match client.pop_timeout(RENDER.saturating_sub(rendered.elapsed())) {
+                Ok(message) => {
+                    if let Message::Flush = *message {
+                        terminal.render();
+                        break;
+                    }
+                    terminal.handle(&message);
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            if rendered.elapsed() >= RENDER {
+                terminal.render();
+                rendered = Instant::now();
+            }
+        }
+        drop(client);
+        if stop.load(Ordering::SeqCst) {
+            return;
+        }
+    }
+}
+
+impl Terminal {
+    fn handle(&mut self, message: &Message) {
+        match message {
+            Message::Record {
+                surface,
+                meta,
+                body,
+            } => {
+                let Some(record) = json::parse(&format!("{{{}}}", body.as_str())) else {
+                    return;
+                };
+                let color = match record.get("col") {
+                    Some(Value::String(css)) => ansi(css),
+                    _ => String::new(),
+                };
+                if let Some(Value::String(msg)) = record.get("msg") {
+                    let line = format!("[{:.3}s] {} {surface}: {msg}", meta.time, meta.target);
+                    let mut stderr = std::io::stderr().lock();
+                    let _ = writeln!(stderr, "{}", self.paint(&line, &color));
+                    return;
+                }
+                let visuals = self.surfaces.entry(surface.to_string()).or_default();
+                let before = visuals.len();
+                add_visuals(&record, color, visuals);
+                while visuals.len() > MAX_VISUALS {
+                    visuals.pop_front();
+                }
+                if visuals.len() != before {
+                    self.changed.insert(surface.to_string());
+                }
+            }
+            Message::Clear(surface) => {
+                if let Some(visuals) = self.surfaces.get_mut(surface) {
+                    visuals.clear();
+                    self.changed.insert(surface.to_string());
+                }
+            }
+            Message::Sample { .. } | Message::Setting(_) | Message::Flush => {}
+        }
+    }
+    /// Draw the changed surfaces to stderr.
+    fn render(&mut self) {
+        let mut out = String::new();
+        for surface in std::mem::take(&mut self.changed) {
+            let Some(visuals) = self.surfaces.get(&surface) else {
+                continue;
+            };
+            writeln!(
+                out,
+                "── {surface} {}",
+                "─".repeat(COLUMNS.saturating_sub(surface.chars().count() + 4))
+            )
+            .unwrap();
+            let mut grid = vec![vec![(' ', ""); COLUMNS]; ROWS];
+            let mut min = [f64::INFINITY; 2];
+            let mut max = [f64::NEG_INFINITY; 2];
+            for [x, y] in visuals.iter().flat_map(Visual::points) {
+                min = [min[0].min(x), min[1].min(y)];
+                max = [max[0].max(x), max[1].max(y)];
+            }
+            let cell = |[x, y]: [f64; 2]| {
+                let scale = |v: f64, min: f64, max: f64, cells: usize| {
+                    if max > min {
+                        ((v - min) / (max - min) * (cells - 1) as f64).round() as isize
+                    } else {
+                        (cells / 2) as isize
+                    }
+                };
+                (
+                    scale(x, min[0], max[0], COLUMNS),
+                    scale(y, min[1], max[1], ROWS),
+                )
+            };
+            let mut set = |(c, r): (isize, isize), ch: char, color| {
+                if (0..COLUMNS as isize).contains(&c) && (0..ROWS as isize).contains(&r) {
+                    grid[r as usize][c as usize] = (ch, color);
+                }
+            };
+            for visual in visuals {
+                match visual {
+                    Visual::Point(p, color) => set(cell(*p), '*', color.as_str()),
+                    Visual::Line(a, b, color) => {
+                        let ((c0, r0), (c1, r1)) = (cell(*a), cell(*b));
+                        let (dc, dr) = (c1 - c0, r1 - r0);
+                        let ch = if dr == 0 {
+                            '-'
+                        } else if dc == 0 {
+                            '|'
+                        } else if (dc > 0) == (dr > 0) {
+                            '\\'
+                        } else {
+                            '/'
+                        };
+                        let steps = dc.abs().max(dr.abs()).max(1);
+                        for i in 0..=steps {
+                            let t = i as f64 / steps as f64;
+                            let c = c0 + (dc as f64 * t).round() as isize;
+                            let r = r0 + (dr as f64 * t).round() as isize;
+                            set((c, r), ch, color.as_str());
+                        }
+                    }
+                    Visual::Text(p, text, align, color) => {
+                        let (c, r) = cell(*p);
+                        let len = text.chars().count() as isize;
+                        let start = match align {
+                            0 => c,
+                            2 => c - len,
+                            _ => c - len / 2,
+                        };
+                        for (i, ch) in text.chars().enumerate() {
+                            set((start + i as isize, r), ch, color.as_str());
+                        }
+                    }
+                }
+            }
+            for row in grid {
+                let end = row
+                    .iter()
+                    .rposition(|(ch, _)| *ch != ' ')
+                    .map_or(0, |i| i + 1);
+                let mut current = "";
+                for &(ch, color) in &row[..end] {
+                    if self.color && color != current {
+                        write!(out, "\x1b[{}m", if color.is_empty() { "0" } else { color })
+                            .unwrap();
+                        current = color;
+                    }
+                    out.push(ch);
+                }
+                if self.color && !current.is_empty() {
+                    out.push_str("\x1b[0m");
+                }
+                out.push('\n');
+            }
+        }
+        if !out.is_empty() {
+            let _ = std::io::stderr().lock().write_all(out.as_bytes());
+        }
+    }
+    fn paint(&self, text: &str, color: &str) -> String {
+        if self.color && !color.is_empty() {
+            format!("\x1b[{color}m{text}\x1b[0m")
+        } else {
+            text.to_owned()
+        }
+    }
+}
+
+/// Add the drawable parts of a record to the visuals of its surface.
+fn add_visuals(record: &Value, color: String, visuals: &mut VecDeque<Visual>) {
+    let point = |key: &str| match record.get(key) {
+        Some(Value::Array(xy)) => match xy.as_slice() {
+            [Value::Number(x), Value::Number(y), ..] => Some([*x, *y]),
+            _ => None,
+        },
+        _ => None,
+    };
+    let mut outline = |points: &[[f64; 2]], closed: bool| {
+        for pair in points.windows(2) {
+            visuals.push_back(Visual::Line(pair[0], pair[1], color.clone()));
+        }
+        if let (true, [first, .., last]) = (closed, points) {
+            visuals.push_back(Visual::Line(*last, *first, color.clone()));
+        }
+    };
+    if let (Some(center), Some([w, h])) = (point("rect"), point("dim")) {
+        let [x, y] = [center[0] - w / 2.0, center[1] - h / 2.0];
+        outline(&[[x, y], [x + w, y], [x + w, y + h], [x, y + h]], true);
+    } else if let (Some([x, y]), Some([rx, ry])) = (point("ell"), point("r")) {
+        let points: Vec<_> = (0..ELLIPSE_SEGMENTS)
+            .map(|i| {
+                let angle = i as f64 / ELLIPSE_SEGMENTS as f64 * std::f64::consts::TAU;
+                [x + rx * angle.cos(), y + ry * angle.sin()]
+            })
+            .collect();
+        outline(&points, true);
+    } else if let Some(Value::Array(pts)) = record.get("pts") {
+        let points: Vec<_> = pts
+            .iter()
+            .filter_map(|p| match p {
+                Value::Array(xy) => match xy.as_slice() {
+                    [Value::Number(x), Value::Number(y), ..] => Some([*x, *y]),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        outline(&points, true);
+    } else if let Some(Value::String(path)) = record.get("path") {
+        // the control points of curves are good enough at the resolution of a terminal.
+        let numbers: Vec<f64> = path
+            .split(|c: char| c.is_ascii_alphabetic() || c == ' ')
+            .filter_map(|n| n.parse().ok())
+            .collect();
+        let points: Vec<_> = numbers
+            .chunks_exact(2)
+            .map(|xy| [xy[0] - 0.5, xy[1] - 0.5])
+            .collect();
+        outline(&points, false);
+    } else if let (Some(a), Some(b)) = (point("pos"), point("pos2")) {
+        visuals.push_back(Visual::Line(a, b, color));
+    } else if let Some(p) = point("pos") {
+        match (record.get("align"), record.get("lbl")) {
+            (Some(Value::Number(align)), Some(Value::String(label))) if !label.is_empty() => {
+                visuals.push_back(Visual::Text(p, label.clone(), *align as u8, color));
+            }
+            _ => visuals.push_back(Visual::Point(p, color)),
+        }
+    }
+}
+
+/// The ANSI SGR parameter for a css color of a record.
+fn ansi(css: &str) -> String {
+    let code = match css {
+        "var(--error)" | "var(--x)" => "31",
+        "var(--healthy)" | "var(--y)" => "32",
+        "var(--warn)" => "33",
+        "var(--info)" | "var(--z)" => "34",
+        "var(--mis)" => "35",
+        _ => {
+            let hex = css.strip_prefix('#').unwrap_or_default();
+            let channel = |i: usize| {
+                hex.get(i..i + 2)
+                    .and_then(|c| u8::from_str_radix(c, 16).ok())
+            };
+            return match (channel(0), channel(2), channel(4)) {
+                (Some(r), Some(g), Some(b)) => format!("38;2;{r};{g};{b}"),
+                _ => String::new(),
+            };
+        }
+    };
+    code.to_owned()
+}
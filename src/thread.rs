@@ -0,0 +1,82 @@
+//! Best-effort thread priority handling for the server threads.
+//!
+//! Only the platforms which allow changing the priority of a single thread are supported.
+//! On all other platforms the priority is ignored.
+
+/// The scheduling priority of the threads spawned by the vlogger.
+///
+/// See [`Builder::thread_priority`](crate::Builder::thread_priority).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ThreadPriority {
+    /// The lowest priority the platform supports for regular threads.
+    Lowest,
+    /// Slightly below the default priority of the platform, i.e. the nice value 5 on Linux
+    /// and `THREAD_PRIORITY_BELOW_NORMAL` on Windows. This doesn't depend on the priority
+    /// of the thread, which initialized the vlogger.
+    BelowNormal,
+    /// Don't change the priority.
+    #[default]
+    Normal,
+}
+
+/// Apply the priority to the calling thread.
+pub(crate) fn set_current_priority(priority: ThreadPriority) {
+    if priority != ThreadPriority::Normal && !platform::set_current_priority(priority) {
+        chatter!(debug, "failed to set the thread priority to {priority:?}");
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod platform {
+    use super::ThreadPriority;
+    use std::os::raw::{c_int, c_uint};
+
+    const PRIO_PROCESS: c_int = 0;
+
+    extern "C" {
+        fn setpriority(which: c_int, who: c_uint, prio: c_int) -> c_int;
+    }
+
+    pub fn set_current_priority(priority: ThreadPriority) -> bool {
+        let nice = match priority {
+            ThreadPriority::Lowest => 19,
+            ThreadPriority::BelowNormal => 5,
+            ThreadPriority::Normal => return true,
+        };
+        // On Linux the nice value is a per thread attribute and `who = 0` refers to the calling thread.
+        unsafe { setpriority(PRIO_PROCESS, 0, nice) == 0 }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::ThreadPriority;
+    use std::os::raw::{c_int, c_void};
+
+    const THREAD_PRIORITY_LOWEST: c_int = -2;
+    const THREAD_PRIORITY_BELOW_NORMAL: c_int = -1;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentThread() -> *mut c_void;
+        fn SetThreadPriority(thread: *mut c_void, priority: c_int) -> c_int;
+    }
+
+    pub fn set_current_priority(priority: ThreadPriority) -> bool {
+        let priority = match priority {
+            ThreadPriority::Lowest => THREAD_PRIORITY_LOWEST,
+            ThreadPriority::BelowNormal => THREAD_PRIORITY_BELOW_NORMAL,
+            ThreadPriority::Normal => return true,
+        };
+        unsafe { SetThreadPriority(GetCurrentThread(), priority) != 0 }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+mod platform {
+    use super::ThreadPriority;
+
+    pub fn set_current_priority(_priority: ThreadPriority) -> bool {
+        false
+    }
+}
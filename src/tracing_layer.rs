@@ -0,0 +1,119 @@
+//! Conversion of `tracing` events into text messages of a surface.
+
+use crate::{Origin, VLOGGER};
+use std::{
+    borrow::Cow,
+    fmt::{self, Write as _},
+};
+use tracing_core::{
+    field::{Field, Visit},
+    span, Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+use v_log::Color;
+
+/// A [`tracing_subscriber::Layer`], which shows the `tracing` events as text messages on a surface.
+///
+/// Every new span is shown as a message with its name and fields and the events inside of it
+/// are indented below it, so the messages are grouped by the spans like in a tree.
+/// The level is shown as the color of the message, `ERROR`, `WARN` and `INFO` map to the
+/// [`Color`]s of the same name and `DEBUG` and `TRACE` to [`Color::Base`].
+/// The events pass the target and surface filters of the vlogger like any other record.
+///
+/// ```
+/// use tracing_subscriber::prelude::*;
+///
+/// web_vlog::init();
+/// tracing_subscriber::registry().with(web_vlog::VLogLayer::new("tracing")).init();
+/// ```
+#[derive(Clone, Debug)]
+pub struct VLogLayer {
+    surface: String,
+}
+
+impl VLogLayer {
+    /// Create a layer, which shows the events on the surface.
+    pub fn new(surface: &str) -> Self {
+        Self {
+            surface: surface.to_owned(),
+        }
+    }
+    fn forward(
+        &self,
+        metadata: &'static tracing_core::Metadata<'static>,
+        depth: usize,
+        text: &str,
+    ) {
+        let vlogger = VLOGGER.read().unwrap();
+        let Some(vlogger) = &*vlogger else {
+            return;
+        };
+        let color = match *metadata.level() {
+            Level::ERROR => Color::Error,
+            Level::WARN => Color::Warn,
+            Level::INFO => Color::Info,
+            _ => Color::Base,
+        };
+        let origin = Origin {
+            target: metadata.target(),
+            module: Cow::Borrowed(metadata.module_path().unwrap_or("")),
+            file: Cow::Borrowed(metadata.file().unwrap_or("")),
+            line: metadata.line().unwrap_or(0),
+        };
+        // non-breaking spaces, so the indentation is not collapsed in the message table.
+        let indent = "\u{a0}\u{a0}".repeat(depth);
+        vlogger.forward(
+            origin,
+            &self.surface,
+            &color,
+            format_args!("{indent}{text}"),
+        );
+    }
+}
+
+/// Formats the fields as ` name=value`, with the `message` field first without its name.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    fields: String,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            write!(self.fields, " {}={value:?}", field.name()).unwrap();
+        }
+    }
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            write!(self.message, "{value:?}").unwrap();
+        } else {
+            write!(self.fields, " {}={value:?}", field.name()).unwrap();
+        }
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for VLogLayer {
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        let depth = span.scope().skip(1).count();
+        let text = format!("{}{}", span.name(), fields.fields);
+        self.forward(span.metadata(), depth, &text);
+    }
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if event.metadata().target().starts_with("web_vlog") {
+            return;
+        }
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let depth = ctx.event_scope(event).map_or(0, |scope| scope.count());
+        let text = format!("{}{}", fields.message, fields.fields);
+        self.forward(event.metadata(), depth, &text);
+    }
+}
@@ -0,0 +1,30 @@
+//! Expiry of visuals after a time to live.
+
+use crate::scoped::scoped;
+use std::{cell::Cell, time::Duration};
+
+thread_local! {
+    static TTL: Cell<Option<Duration>> = const { Cell::new(None) };
+}
+
+/// Let all visuals vlogged by the current thread inside of `f` disappear from the viewer after `ttl`.
+///
+/// This overrides the default of the surface set with [`Builder::surface_ttl`](crate::Builder::surface_ttl).
+/// Text messages in the message table don't expire.
+///
+/// ```
+/// use std::time::Duration;
+/// use v_log::point;
+///
+/// web_vlog::with_ttl(Duration::from_millis(500), || {
+///     point!("sensor", [1.0, 2.0], 5.0, Base);
+/// });
+/// ```
+pub fn with_ttl<R>(ttl: Duration, f: impl FnOnce() -> R) -> R {
+    scoped(&TTL, ttl, f)
+}
+
+/// The time to live set with [`with_ttl`] on the current thread.
+pub(crate) fn current() -> Option<Duration> {
+    TTL.with(Cell::get)
+}
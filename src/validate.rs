@@ -0,0 +1,330 @@
+//! A vlogger, which checks the instrumentation without starting a server.
+
+use crate::{json, surface, wire, wire::Message, WebVLogger};
+use std::{fmt, sync::Mutex};
+use v_log::{Record, VLog, Visual};
+
+/// The maximal number of distinct problems in the [`ValidationSummary`].
+const MAX_PROBLEMS: usize = 1000;
+
+static SUMMARY: Mutex<ValidationSummary> = Mutex::new(ValidationSummary {
+    records: 0,
+    problems: Vec::new(),
+});
+
+/// The rules checked by the validating vlogger, see [`Builder::validate_init`](crate::Builder::validate_init).
+///
+/// Independent of the rules, every record is checked for non-finite coordinates and sizes,
+/// surface names are checked like in [`Surface::new`](crate::Surface::new) and the serialized messages are checked to be valid JSON.
+#[derive(Clone, Debug)]
+pub struct ValidationRules {
+    max_label_len: usize,
+    surfaces: Vec<String>,
+}
+
+impl Default for ValidationRules {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ValidationRules {
+    /// Create the default rules, which allow labels up to 4096 bytes on all surfaces.
+    pub fn new() -> Self {
+        Self {
+            max_label_len: 4096,
+            surfaces: vec![],
+        }
+    }
+    /// Set the maximal length of labels and messages in bytes.
+    pub fn max_label_len(mut self, max: usize) -> Self {
+        self.max_label_len = max;
+        self
+    }
+    /// Add a surface to the surface whitelist.
+    /// If the whitelist is left empty, all valid surface names are allowed.
+    pub fn allow_surface(mut self, surface: &str) -> Self {
+        self.surfaces.push(surface.to_owned());
+        self
+    }
+}
+
+/// The kind of a [`Problem`] found by the validating vlogger.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProblemKind {
+    /// A coordinate or the size is NaN or infinite.
+    NonFinite,
+    /// The label is longer than [`ValidationRules::max_label_len`].
+    LabelTooLong(usize),
+    /// The surface name is invalid, with the reason.
+    InvalidSurface(&'static str),
+    /// The surface is not in the whitelist of the [`ValidationRules`].
+    UnknownSurface,
+    /// The serialized message is not valid JSON.
+    InvalidJson,
+}
+
+impl fmt::Display for ProblemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NonFinite => f.write_str("non-finite coordinate or size"),
+            Self::LabelTooLong(len) => write!(f, "label with {len} bytes is too long"),
+            Self::InvalidSurface(reason) => f.write_str(reason),
+            Self::UnknownSurface => f.write_str("surface is not in the whitelist"),
+            Self::InvalidJson => f.write_str("serialized message is not valid JSON"),
+        }
+    }
+}
+
+/// A problem with the instrumentation found by the validating vlogger.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Problem {
+    pub kind: ProblemKind,
+    pub surface: String,
+    /// The file of the vlogging call. Empty for clearing a surface.
+    pub file: String,
+    pub line: u32,
+    /// How often this problem occurred at this location.
+    pub count: u64,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: surface \"{}\": {}",
+            self.file, self.line, self.surface, self.kind
+        )?;
+        if self.count > 1 {
+            write!(f, " ({} times)", self.count)?;
+        }
+        Ok(())
+    }
+}
+
+/// The result of the validation, see [`validation_summary`].
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct ValidationSummary {
+    /// The number of checked records.
+    pub records: u64,
+    /// The distinct problems found so far, in the order they first occurred.
+    pub problems: Vec<Problem>,
+}
+
+impl ValidationSummary {
+    /// Check if no problems have been found.
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "checked {} records, found {} problems",
+            self.records,
+            self.problems.len()
+        )?;
+        for problem in &self.problems {
+            write!(f, "\n{problem}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Get the problems found by the validating vlogger so far.
+///
+/// ```
+/// use v_log::point;
+///
+/// web_vlog::validate_init();
+/// point!("surface", [f64::NAN, 2.0], 5.0, Base);
+/// let summary = web_vlog::validation_summary();
+/// assert!(!summary.is_ok(), "{summary}");
+/// ```
+pub fn validation_summary() -> ValidationSummary {
+    SUMMARY.lock().unwrap().clone()
+}
+
+/// Checks the messages of a vlogger when they are sent.
+pub(crate) struct Validator {
+    rules: ValidationRules,
+    encoder: Mutex<wire::Encoder>,
+}
+
+impl Validator {
+    pub fn new(rules: ValidationRules) -> Self {
+        Self {
+            rules,
+            encoder: Mutex::new(wire::Encoder::new(true)),
+        }
+    }
+    /// Check the surface and the serialization of a message and add the problems to the summary.
+    pub fn check(&self, message: &Message) {
+        let mut problems = vec![];
+        if let Some(surface) = message.surface() {
+            if let Err(reason) = surface::check_name(surface) {
+                problems.push(ProblemKind::InvalidSurface(reason));
+            } else if !self.rules.surfaces.is_empty()
+                && !self.rules.surfaces.iter().any(|s| s == surface)
+            {
+                problems.push(ProblemKind::UnknownSurface);
+            }
+        }
+        if let Some(msg) = self.encoder.lock().unwrap().encode(message, 1) {
+            if json::parse(&msg).is_none() {
+                problems.push(ProblemKind::InvalidJson);
+            }
+        }
+        let (file, line) = match message {
+            Message::Record { meta, .. } => (&*meta.file, meta.line),
+            _ => ("", 0),
+        };
+        let record = matches!(message, Message::Record { .. });
+        report(
+            message.surface().unwrap_or(""),
+            file,
+            line,
+            problems,
+            record,
+        );
+    }
+}
+
+/// A vlogger, which checks the records and passes them on to a [`WebVLogger`] with a [`Validator`].
+pub(crate) struct ValidatingVLogger {
+    pub inner: WebVLogger,
+    pub rules: ValidationRules,
+}
+
+/// Add the problems of a single message to the summary.
+fn report(surface: &str, file: &str, line: u32, problems: Vec<ProblemKind>, record: bool) {
+    let mut summary = SUMMARY.lock().unwrap();
+    if record {
+        summary.records += 1;
+    }
+    for kind in problems {
+        let existing = summary.problems.iter().position(|p| {
+            p.kind == kind && p.line == line && p.file == file && p.surface == surface
+        });
+        match existing {
+            Some(i) => summary.problems[i].count += 1,
+            None if summary.problems.len() < MAX_PROBLEMS => {
+                log::warn!("{file}:{line}: surface \"{surface}\": {kind}");
+                summary.problems.push(Problem {
+                    kind,
+                    surface: surface.to_owned(),
+                    file: file.to_owned(),
+                    line,
+                    count: 1,
+                });
+            }
+            None => {}
+        }
+    }
+}
+
+impl VLog for ValidatingVLogger {
+    fn enabled(&self, metadata: &v_log::Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+    fn vlog(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut problems = vec![];
+        let finite = match *record.visual() {
+            Visual::Message => true,
+            Visual::Label { x, y, z, .. } | Visual::Point { x, y, z, .. } => {
+                [x, y, z].iter().all(|v| v.is_finite())
+            }
+            Visual::Line {
+                x1,
+                y1,
+                z1,
+                x2,
+                y2,
+                z2,
+                ..
+            } => [x1, y1, z1, x2, y2, z2].iter().all(|v| v.is_finite()),
+        };
+        if !finite || !record.size().is_finite() {
+            problems.push(ProblemKind::NonFinite);
+        }
+        let len = record
+            .args()
+            .as_str()
+            .map_or_else(|| record.args().to_string().len(), str::len);
+        if len > self.rules.max_label_len {
+            problems.push(ProblemKind::LabelTooLong(len));
+        }
+        // the surface and the serialization are checked by the validator of `inner`.
+        self.inner.vlog(record);
+        report(
+            record.surface(),
+            record.file().unwrap_or(""),
+            record.line().unwrap_or(0),
+            problems,
+            false,
+        );
+    }
+    fn clear(&self, surface: &str) {
+        self.inner.clear(surface);
+    }
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::Meta;
+    use std::{borrow::Cow, sync::Arc};
+
+    fn record(surface: &str, line: u32, body: &str) -> Message {
+        Message::Record {
+            surface: surface.into(),
+            meta: Meta {
+                target: "my_crate".into(),
+                module: Cow::Borrowed("my_crate"),
+                file: Cow::Borrowed("src/validated.rs"),
+                line,
+                time: 0.0,
+                wall: 0,
+                tid: 1,
+                thread: Arc::from("main"),
+            },
+            body: body.to_owned().into(),
+        }
+    }
+
+    #[test]
+    fn problems_have_the_location_of_the_message() {
+        let validator = Validator::new(ValidationRules::new().allow_surface("known"));
+        validator.check(&record("known", 1, "\"msg\":\"ok\""));
+        validator.check(&record("known", 2, "\"msg\":"));
+        validator.check(&record("other", 3, "\"msg\":\"ok\""));
+        validator.check(&Message::Clear("other".to_owned()));
+        let summary = validation_summary();
+        let problems: Vec<_> = summary
+            .problems
+            .iter()
+            .filter(|p| p.file == "src/validated.rs" || p.surface == "other")
+            .map(|p| (p.kind.clone(), p.surface.as_str(), p.line))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                (ProblemKind::InvalidJson, "known", 2),
+                (ProblemKind::UnknownSurface, "other", 3),
+                (ProblemKind::UnknownSurface, "other", 0),
+            ]
+        );
+        assert!(summary.records >= 3);
+    }
+}
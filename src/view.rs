@@ -0,0 +1,119 @@
+//! Settings of the viewer per surface, which persist when the surface is cleared.
+
+use crate::{json::Escape, wire::Message, VLOGGER};
+use std::{fmt::Write as _, sync::Mutex};
+
+/// The current settings as key and json message. Every client gets them when it connects.
+static SETTINGS: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// Replace the setting with the key and send it to the connected client.
+fn set(key: String, json: String) {
+    {
+        let mut settings = SETTINGS.lock().unwrap();
+        match settings.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, value)) => *value = json.clone(),
+            None => settings.push((key, json.clone())),
+        }
+    }
+    if let Some(vlogger) = &*VLOGGER.read().unwrap() {
+        vlogger.send(Message::Setting(json));
+    }
+}
+
+/// The json messages of all current settings.
+pub(crate) fn settings() -> Vec<String> {
+    let settings = SETTINGS.lock().unwrap();
+    settings.iter().map(|(_, json)| json.clone()).collect()
+}
+
+/// Draw background axes and gridlines with coordinate labels on a surface.
+///
+/// The gridlines are `spacing` apart and cover the `extent` `[min, max]`. They stay one pixel wide
+/// and the labels keep their size when zooming. The grid is kept when the surface is cleared
+/// and replaces the previous grid of the surface. A spacing of 0 removes the grid.
+/// To set up grids before the vlogger is initialized, use [`Builder::surface_grid`](crate::Builder::surface_grid).
+///
+/// ```
+/// web_vlog::surface_grid("map", 50.0, [[-500.0, -500.0], [500.0, 500.0]]);
+/// ```
+pub fn surface_grid(surface: &str, spacing: f64, extent: [[f64; 2]; 2]) {
+    let [[x0, y0], [x1, y1]] = extent;
+    // non-finite numbers would make the message invalid json.
+    let spacing = if [spacing, x0, y0, x1, y1].iter().all(|v| v.is_finite()) {
+        spacing.abs()
+    } else {
+        0.0
+    };
+    set(
+        format!("grid:{surface}"),
+        format!(
+            "{{\"grid\":\"{}\",\"sp\":{spacing},\"ext\":[{x0},{y0},{x1},{y1}]}}",
+            Escape(surface)
+        ),
+    );
+}
+
+/// The configuration of how the viewer shows a surface, see [`configure_surface`].
+///
+/// ```
+/// use web_vlog::SurfaceConfig;
+///
+/// web_vlog::configure_surface("physics", SurfaceConfig::new().y_up(true).auto_fit(true));
+/// web_vlog::configure_surface("table1", SurfaceConfig::new().view([0.0, 0.0], [200.0, 100.0]));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SurfaceConfig {
+    y_up: bool,
+    view: Option<[f64; 4]>,
+    auto_fit: bool,
+}
+
+impl SurfaceConfig {
+    /// Create the default configuration, where the y axis points down like in SVG.
+    pub const fn new() -> Self {
+        Self {
+            y_up: false,
+            view: None,
+            auto_fit: false,
+        }
+    }
+    /// Let the y axis point up like in physics and math plots, instead of down.
+    ///
+    /// The surface is mirrored at the x axis, while text stays upright.
+    pub const fn y_up(mut self, y_up: bool) -> Self {
+        self.y_up = y_up;
+        self
+    }
+    /// Focus the viewer on the rectangle at `min` with `size`, when the surface is opened.
+    ///
+    /// The first surface with a view or [`auto_fit`](SurfaceConfig::auto_fit) is focused.
+    /// Double clicking the button of a surface in the viewer focuses it again.
+    pub const fn view(mut self, min: [f64; 2], size: [f64; 2]) -> Self {
+        self.view = Some([min[0], min[1], size[0], size[1]]);
+        self
+    }
+    /// Focus the viewer on the bounding box of the visuals of the surface, when it is opened.
+    ///
+    /// The view follows the visuals as they are added, until the user zooms or pans.
+    pub const fn auto_fit(mut self, auto_fit: bool) -> Self {
+        self.auto_fit = auto_fit;
+        self
+    }
+}
+
+/// Configure how the viewer shows a surface. The configuration is kept when the surface is cleared
+/// and replaces the previous configuration of the surface.
+/// To configure surfaces before the vlogger is initialized, use [`Builder::configure_surface`](crate::Builder::configure_surface).
+pub fn configure_surface(surface: &str, config: SurfaceConfig) {
+    let mut json = format!("{{\"conf\":\"{}\",\"yup\":{}", Escape(surface), config.y_up);
+    match config.view {
+        // non-finite numbers would make the message invalid json.
+        Some([x, y, w, h]) if [x, y, w, h].iter().all(|v| v.is_finite()) => {
+            write!(json, ",\"rect\":[{x},{y},{w},{h}]").unwrap();
+        }
+        _ if config.auto_fit => json.push_str(",\"fit\":true"),
+        _ => {}
+    }
+    json.push('}');
+    set(format!("conf:{surface}"), json);
+}
@@ -0,0 +1,684 @@
+//! The messages sent from the vlogging threads to the server thread and their
+//! encoding in the wire format.
+//!
+//! Every message on the wire is a json object. Records and clear messages reference their
+//! surface with `"surf"` and records carry their origin in `"meta"`. To keep the
+//! steady-state size small, the strings in these fields are interned per connection.
+//! The first time a string is used, it is added to the dictionary of the client by including
+//! `"dict":[id, "string", ...]` in the message. From then on, only the integer id is sent.
+//! Additionally the fields of `"meta"`, which are unchanged since the previous record,
+//! are omitted, so the client has to fill them in from the previous record.
+//!
+//! Samples of line charts are sent as `{"xy":[x,y],"series":name,"surf":name}` without `"meta"`,
+//! where the series name is interned like the surface name. The client keeps the samples of
+//! every series until the surface is cleared.
+//!
+//! Directly after the websocket upgrade, the server sends `{"hello":version,"caps":[...]}`
+//! with the [`PROTOCOL_VERSION`] and the client acknowledges with `{"ack":version}`.
+//! Clients, which don't acknowledge, only get messages without the compact encoding.
+//! Afterwards the surfaces in the 3D mode are announced with `{"view":surface,"proj":projection}`
+//! and a custom template for the links to the source is sent as `{"editor":template}`.
+//! A custom title of the page is sent as `{"title":title}`, so already open pages get it as well.
+//!
+//! Frame markers are sent as `{"frame":number}` in order with the records. The records after
+//! the marker belong to the frame with this number, so the client can step through the frames.
+//!
+//! When records are dropped, because the client can't keep up with its queue,
+//! the client is told the number of dropped records with `{"slow":count}`.
+//! Records dropped by any backpressure policy are reported at most once per second
+//! as `{"drops":{surface:count,...}}` with the counts since the last report, so the client
+//! can mark the affected surfaces. The surface names in this message aren't interned.
+//!
+//! Inspected values are records with `"insp":name` and the serialized value in `"val"`.
+//! The client shows them in a side panel, where a value replaces the previous one with the same
+//! surface and name, instead of drawing them on the surface.
+
+use crate::{json::Escape, Projection};
+use std::{borrow::Cow, collections::HashMap, fmt::Write as _, ops::Deref, sync::Arc};
+
+/// The version of the wire format. Version 1 is the format without the hello message.
+pub(crate) const PROTOCOL_VERSION: u32 = 2;
+
+/// The hello message, which is sent to the client directly after the websocket upgrade.
+pub(crate) fn hello() -> String {
+    format!("{{\"hello\":{PROTOCOL_VERSION},\"caps\":[\"dict\",\"meta\",\"rep\",\"trunc\",\"fields\",\"ttl\",\"pts\",\"ell\",\"rect\",\"path\",\"img\",\"plot\",\"hist\",\"vf\",\"3d\",\"op\",\"layer\",\"box\",\"prog\",\"table\",\"mesh\",\"arr\",\"dash\",\"grad\",\"vm\",\"area\",\"grid\",\"callout\",\"id\",\"editor\",\"title\",\"conf\",\"frame\",\"slow\",\"drops\",\"insp\"]}}")
+}
+
+/// The message, which switches a surface to the 3D mode with the projection.
+pub(crate) fn view(surface: &str, projection: Projection) -> String {
+    let proj = match projection {
+        Projection::Orthographic => "ortho",
+        Projection::Perspective => "persp",
+    };
+    format!("{{\"view\":\"{}\",\"proj\":\"{proj}\"}}", Escape(surface))
+}
+
+/// The message, which sets the template of the links from the visuals to their source.
+pub(crate) fn editor(template: &str) -> String {
+    format!("{{\"editor\":\"{}\"}}", Escape(template))
+}
+
+/// The message, which sets the title of the page.
+pub(crate) fn title(title: &str) -> String {
+    format!("{{\"title\":\"{}\"}}", Escape(title))
+}
+
+/// The marker of the start of the frame with the number.
+pub(crate) fn frame(frame: u64) -> String {
+    format!("{{\"frame\":{frame}}}")
+}
+
+/// The numbers of records dropped per surface since the last report.
+pub(crate) fn drops(drops: &HashMap<String, u64>) -> String {
+    let mut msg = "{\"drops\":{".to_owned();
+    for (i, (surface, count)) in drops.iter().enumerate() {
+        if i > 0 {
+            msg.push(',');
+        }
+        write!(msg, "\"{}\":{count}", Escape(surface)).unwrap();
+    }
+    msg.push_str("}}");
+    msg
+}
+
+/// Parse the acknowledgement `{"ack":version}` of the client.
+pub(crate) fn parse_ack(payload: &[u8]) -> Option<u32> {
+    let value = crate::json::parse(std::str::from_utf8(payload).ok()?)?;
+    match value.get("ack")? {
+        crate::json::Value::Number(n) if *n >= 0.0 => Some(*n as u32),
+        _ => None,
+    }
+}
+
+/// An error message for clients with an unsupported protocol version.
+/// It only uses the basic format, which every version of the viewer understands.
+pub(crate) fn version_error(version: u32) -> String {
+    format!("{{\"msg\":\"The viewer uses protocol version {version}, but web-vlog requires version {PROTOCOL_VERSION}. Reload the page.\",\"surf\":\"web-vlog\",\"meta\":{{\"target\":\"web-vlog\",\"file\":\"\",\"line\":0,\"time\":0,\"wall\":0}},\"col\":\"var(--error)\"}}")
+}
+
+/// The maximal number of interned strings per connection.
+/// Strings beyond that are sent inline to bound the memory use for dynamic surface names.
+const MAX_DICT_LEN: usize = 4096;
+
+/// The fields of the meta data, which are omitted if they are unchanged.
+const META_KEYS: [&str; 6] = ["target", "module", "file", "line", "tid", "thread"];
+
+/// A message from the vlogging threads to the server thread.
+pub(crate) enum Message {
+    /// A serialized record.
+    Record {
+        surface: Arc<str>,
+        meta: Meta,
+        /// The visual specific json fields.
+        body: Body,
+    },
+    /// A sample appended to a series of the line chart on the surface.
+    Sample {
+        surface: Arc<str>,
+        series: String,
+        /// The sample specific json fields.
+        body: Body,
+    },
+    /// A setting of the viewer, which is already encoded as json, see [`crate::view`].
+    Setting(String),
+    /// Clear the surface with the given name.
+    Clear(String),
+    /// Close the connection after all previous messages have been written.
+    Flush,
+}
+
+impl Message {
+    /// The number of bytes allocated for this message.
+    /// The interned surfaces and targets are shared by the messages, so they don't count.
+    #[cfg(feature = "self-profile")]
+    pub fn allocated(&self) -> usize {
+        match self {
+            Self::Record { meta, body, .. } => {
+                let cow = |s: &Cow<'static, str>| match s {
+                    Cow::Owned(s) => s.capacity(),
+                    Cow::Borrowed(_) => 0,
+                };
+                body.capacity() + cow(&meta.module) + cow(&meta.file)
+            }
+            Self::Sample { series, body, .. } => series.capacity() + body.capacity(),
+            Self::Clear(s) | Self::Setting(s) => s.capacity(),
+            Self::Flush => 0,
+        }
+    }
+}
+
+impl Message {
+    /// The surface of a record, a sample or clearing a surface.
+    pub fn surface(&self) -> Option<&str> {
+        match self {
+            Self::Record { surface, .. } | Self::Sample { surface, .. } => Some(surface),
+            Self::Clear(surface) => Some(surface),
+            Self::Setting(_) | Self::Flush => None,
+        }
+    }
+    /// Check if both messages are records with the same visual from the same origin.
+    /// The timestamps are ignored.
+    pub fn same_record(&self, other: &Message) -> bool {
+        match (self, other) {
+            (
+                Self::Record {
+                    surface,
+                    meta,
+                    body,
+                },
+                Self::Record {
+                    surface: surface2,
+                    meta: meta2,
+                    body: body2,
+                },
+            ) => {
+                body.as_str() == body2.as_str()
+                    && surface == surface2
+                    && meta.line == meta2.line
+                    && meta.tid == meta2.tid
+                    && meta.file == meta2.file
+                    && meta.target == meta2.target
+                    && meta.module == meta2.module
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The json fields of a record or sample without the enclosing braces.
+///
+/// The messages are shared by all clients, so with the `msgpack` feature the fields are
+/// transcoded once by the first client using MessagePack and the others reuse the transcoding.
+pub(crate) struct Body {
+    json: String,
+    #[cfg(feature = "msgpack")]
+    packed: std::sync::Mutex<Option<Arc<crate::msgpack::Fields>>>,
+}
+
+impl Body {
+    /// The fields transcoded to MessagePack, or `None` if they are not valid json.
+    #[cfg(feature = "msgpack")]
+    pub fn packed(&self) -> Option<Arc<crate::msgpack::Fields>> {
+        let mut packed = self.packed.lock().unwrap();
+        if packed.is_none() {
+            *packed = crate::msgpack::Fields::from_json(&self.json).map(Arc::new);
+        }
+        packed.clone()
+    }
+}
+
+impl From<String> for Body {
+    fn from(json: String) -> Self {
+        Self {
+            json,
+            #[cfg(feature = "msgpack")]
+            packed: Default::default(),
+        }
+    }
+}
+
+impl Deref for Body {
+    type Target = String;
+
+    fn deref(&self) -> &String {
+        &self.json
+    }
+}
+
+/// The origin of a record.
+pub(crate) struct Meta {
+    pub target: Arc<str>,
+    pub module: Cow<'static, str>,
+    pub file: Cow<'static, str>,
+    pub line: u32,
+    /// Monotonic time since init in seconds.
+    pub time: f64,
+    /// Wall-clock time in milliseconds since the unix epoch.
+    pub wall: u128,
+    pub tid: u64,
+    pub thread: Arc<str>,
+}
+
+/// Encodes messages in the wire format for one client.
+pub(crate) struct Encoder {
+    compact: bool,
+    dict: HashMap<String, u32>,
+    /// The strings added to the dictionary by the last call to [`Encoder::encode`].
+    added: Vec<String>,
+    /// The encoded values of the [`META_KEYS`] of the previous record.
+    prev: [String; 6],
+    /// Buffers for the encoded meta values of the current record.
+    /// After encoding a record, these are the values of the record before the previous one.
+    tokens: [String; 6],
+    /// If the last call to [`Encoder::encode`] has replaced `prev`.
+    replaced: bool,
+}
+
+impl Encoder {
+    /// Create an encoder. If `compact` is false, all strings are sent inline
+    /// and the meta data is always complete.
+    pub fn new(compact: bool) -> Self {
+        Self {
+            compact,
+            dict: HashMap::new(),
+            added: vec![],
+            prev: Default::default(),
+            tokens: Default::default(),
+            replaced: false,
+        }
+    }
+    /// Encode a message as json. Returns `None` for messages, which are not sent to the client.
+    ///
+    /// Records, which are repeated, get the number of repetitions as `"rep"` field.
+    pub fn encode(&mut self, message: &Message, repeat: usize) -> Option<String> {
+        self.encode_parts(message, repeat, true)
+    }
+    /// Encode a message like [`Encoder::encode`], but without the [`Body`] of records and samples.
+    #[cfg(feature = "msgpack")]
+    pub fn encode_envelope(&mut self, message: &Message, repeat: usize) -> Option<String> {
+        self.encode_parts(message, repeat, false)
+    }
+    fn encode_parts(
+        &mut self,
+        message: &Message,
+        repeat: usize,
+        with_body: bool,
+    ) -> Option<String> {
+        self.added.clear();
+        self.replaced = false;
+        let mut dict = String::new();
+        let mut out = match message {
+            Message::Record {
+                surface,
+                meta,
+                body,
+            } => {
+                let mut out = String::with_capacity(body.len() + 128);
+                out.push('{');
+                if with_body {
+                    out.push_str(body);
+                    out.push(',');
+                }
+                if repeat > 1 {
+                    write!(&mut out, "\"rep\":{repeat},").unwrap();
+                }
+                out.push_str("\"surf\":");
+                self.string(surface, &mut out, &mut dict);
+                let mut tokens = std::mem::take(&mut self.tokens);
+                for token in &mut tokens {
+                    token.clear();
+                }
+                self.string(&meta.target, &mut tokens[0], &mut dict);
+                self.string(&meta.module, &mut tokens[1], &mut dict);
+                let file = meta.file.trim_start_matches('.');
+                self.string(file, &mut tokens[2], &mut dict);
+                write!(&mut tokens[3], "{}", meta.line).unwrap();
+                write!(&mut tokens[4], "{}", meta.tid).unwrap();
+                self.string(&meta.thread, &mut tokens[5], &mut dict);
+                write!(
+                    &mut out,
+                    ",\"meta\":{{\"time\":{},\"wall\":{}",
+                    meta.time, meta.wall
+                )
+                .unwrap();
+                for ((key, token), prev) in META_KEYS.iter().zip(&tokens).zip(&self.prev) {
+                    if !self.compact || token != prev {
+                        write!(&mut out, ",\"{key}\":{token}").unwrap();
+                    }
+                }
+                out.push('}');
+                self.tokens = std::mem::replace(&mut self.prev, tokens);
+                self.replaced = true;
+                out
+            }
+            Message::Sample {
+                surface,
+                series,
+                body,
+            } => {
+                let mut out = String::with_capacity(body.len() + 32);
+                out.push('{');
+                if with_body {
+                    out.push_str(body);
+                    out.push(',');
+                }
+                out.push_str("\"series\":");
+                self.string(series, &mut out, &mut dict);
+                out.push_str(",\"surf\":");
+                self.string(surface, &mut out, &mut dict);
+                out
+            }
+            Message::Clear(surface) => {
+                let mut out = String::from("{\"clear\":1,\"surf\":");
+                self.string(surface, &mut out, &mut dict);
+                out
+            }
+            Message::Setting(json) => {
+                // records passed through, e.g. when restoring a checkpoint, replace the meta
+                // of the previous record in the client, so the next record has to be complete.
+                if json.contains("\"meta\":") {
+                    self.prev.iter_mut().for_each(String::clear);
+                }
+                return Some(json.clone());
+            }
+            Message::Flush => return None,
+        };
+        if !dict.is_empty() {
+            out.push_str(",\"dict\":[");
+            out.push_str(&dict);
+            out.push(']');
+        }
+        out.push('}');
+        Some(out)
+    }
+    /// Forget the strings added to the dictionary by the last call to [`Encoder::encode`].
+    /// This has to be called if the encoded message is not sent to the client.
+    pub fn revert(&mut self) {
+        for s in self.added.drain(..) {
+            self.dict.remove(&s);
+        }
+        if self.replaced {
+            std::mem::swap(&mut self.prev, &mut self.tokens);
+            self.replaced = false;
+        }
+    }
+    /// Write a string value, either as reference into the dictionary or inline.
+    fn string(&mut self, s: &str, out: &mut String, dict: &mut String) {
+        if self.compact {
+            if let Some(id) = self.dict.get(s) {
+                write!(out, "{id}").unwrap();
+                return;
+            }
+            if self.dict.len() < MAX_DICT_LEN {
+                let id = self.dict.len() as u32;
+                self.dict.insert(s.to_owned(), id);
+                self.added.push(s.to_owned());
+                if !dict.is_empty() {
+                    dict.push(',');
+                }
+                write!(dict, "{id},\"{}\"", Escape(s)).unwrap();
+                write!(out, "{id}").unwrap();
+                return;
+            }
+        }
+        write!(out, "\"{}\"", Escape(s)).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::{self, Pos, Value};
+
+    fn record(surface: &str, target: &str, file: &'static str, line: u32, body: String) -> Message {
+        Message::Record {
+            surface: surface.into(),
+            meta: Meta {
+                target: target.into(),
+                module: Cow::Borrowed("my_crate"),
+                file: Cow::Borrowed(file),
+                line,
+                time: 1.5,
+                wall: 1000,
+                tid: 1,
+                thread: Arc::from("main"),
+            },
+            body: body.into(),
+        }
+    }
+
+    /// Decodes the compact encoding like the viewer does.
+    #[derive(Default)]
+    struct Viewer {
+        dict: HashMap<u32, String>,
+        /// The meta of the previous record.
+        meta: Vec<(String, Value)>,
+    }
+
+    impl Viewer {
+        fn decode(&mut self, msg: &str) -> Value {
+            let Some(Value::Object(mut fields)) = json::parse(msg) else {
+                panic!("invalid message {msg}");
+            };
+            if let Some(i) = fields.iter().position(|(k, _)| k == "dict") {
+                let Value::Array(dict) = fields.remove(i).1 else {
+                    panic!("invalid dict in {msg}");
+                };
+                for entry in dict.chunks(2) {
+                    let [Value::Number(id), Value::String(s)] = entry else {
+                        panic!("invalid dict entry in {msg}");
+                    };
+                    self.dict.insert(*id as u32, s.clone());
+                }
+            }
+            for (key, value) in &mut fields {
+                match key.as_str() {
+                    "surf" | "series" => *value = self.resolve(value),
+                    "meta" => {
+                        let Value::Object(meta) = value else {
+                            panic!("invalid meta in {msg}");
+                        };
+                        for (key, value) in meta.drain(..) {
+                            let value = match key.as_str() {
+                                "target" | "module" | "file" | "thread" => self.resolve(&value),
+                                _ => value,
+                            };
+                            match self.meta.iter_mut().find(|(k, _)| *k == key) {
+                                Some((_, prev)) => *prev = value,
+                                None => self.meta.push((key, value)),
+                            }
+                        }
+                        *value = Value::Object(self.meta.clone());
+                    }
+                    _ => {}
+                }
+            }
+            sorted(Value::Object(fields))
+        }
+        fn resolve(&self, value: &Value) -> Value {
+            match value {
+                Value::Number(id) => Value::String(self.dict[&(*id as u32)].clone()),
+                value => value.clone(),
+            }
+        }
+    }
+
+    /// Sort the keys of the objects, as the order of the fields doesn't matter to the viewer.
+    fn sorted(value: Value) -> Value {
+        match value {
+            Value::Object(mut fields) => {
+                fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+                Value::Object(fields.into_iter().map(|(k, v)| (k, sorted(v))).collect())
+            }
+            Value::Array(values) => Value::Array(values.into_iter().map(sorted).collect()),
+            value => value,
+        }
+    }
+
+    /// Encode the messages for one connection and check that the viewer decodes them
+    /// like their basic encoding. Returns the compact messages.
+    fn roundtrip(messages: &[Message]) -> Vec<String> {
+        let (mut compact, mut basic) = (Encoder::new(true), Encoder::new(false));
+        let mut viewer = Viewer::default();
+        let mut sent = vec![];
+        for message in messages {
+            let Some(msg) = compact.encode(message, 1) else {
+                continue;
+            };
+            let expected = json::parse(&basic.encode(message, 1).unwrap()).unwrap();
+            assert_eq!(viewer.decode(&msg), sorted(expected), "{msg}");
+            sent.push(msg);
+        }
+        sent
+    }
+
+    #[test]
+    fn interned_strings() {
+        let point = || "\"pos\":[1,2,0]".to_owned();
+        let sent = roundtrip(&[
+            record("map", "my_crate", "src/main.rs", 1, point()),
+            record("map", "my_crate", "src/main.rs", 1, point()),
+            Message::Sample {
+                surface: "map".into(),
+                series: "speed".to_owned(),
+                body: "\"xy\":[1,2]".to_owned().into(),
+            },
+            Message::Sample {
+                surface: "plots".into(),
+                series: "speed".to_owned(),
+                body: "\"xy\":[2,3]".to_owned().into(),
+            },
+            Message::Clear("map".to_owned()),
+            Message::Clear("empty \"surface\"".to_owned()),
+            record("plots", "other", "src/main.rs", 1, point()),
+        ]);
+        assert!(
+            sent[0].contains("\"dict\":[0,\"map\",1,\"my_crate\""),
+            "{}",
+            sent[0]
+        );
+        assert!(
+            !sent[1].contains("dict") && sent[1].contains("\"surf\":0"),
+            "{}",
+            sent[1]
+        );
+        assert!(sent[3].contains("\"dict\":[5,\"plots\"]"), "{}", sent[3]);
+        assert_eq!(sent[4], "{\"clear\":1,\"surf\":0}");
+        assert!(!sent[6].contains("\"surf\":\""), "{}", sent[6]);
+    }
+
+    #[test]
+    fn full_dictionary_sends_strings_inline() {
+        let messages: Vec<_> = (0..MAX_DICT_LEN + 10)
+            .map(|i| Message::Clear(format!("surface {i}")))
+            .collect();
+        let sent = roundtrip(&messages);
+        assert!(sent[MAX_DICT_LEN - 1].contains("dict"));
+        assert_eq!(
+            sent[MAX_DICT_LEN],
+            format!("{{\"clear\":1,\"surf\":\"surface {MAX_DICT_LEN}\"}}")
+        );
+    }
+
+    #[test]
+    fn unchanged_meta_is_omitted() {
+        let point = || "\"pos\":[1,2,0]".to_owned();
+        let mut restored = Encoder::new(false)
+            .encode(&record("old", "restored", "src/old.rs", 9, point()), 1)
+            .unwrap();
+        restored.insert_str(1, "\"surf\":\"old\",");
+        let sent = roundtrip(&[
+            record("map", "a", "src/a.rs", 1, point()),
+            record("map", "a", "src/a.rs", 1, point()),
+            record("map", "a", "src/a.rs", 2, point()),
+            record("map", "b", "src/a.rs", 2, point()),
+            record("map", "b", "src/b.rs", 2, point()),
+            record("map", "a", "src/a.rs", 1, point()),
+            Message::Clear("map".to_owned()),
+            record("map", "a", "src/a.rs", 1, point()),
+            // a restored record replaces the meta of the previous record in the viewer.
+            Message::Setting(restored),
+            record("map", "a", "src/a.rs", 1, point()),
+        ]);
+        let meta = |msg: &str| {
+            let meta = json::parse(msg).unwrap().get("meta").cloned().unwrap();
+            let Value::Object(fields) = meta else {
+                panic!("invalid meta in {msg}");
+            };
+            fields.into_iter().map(|(k, _)| k).collect::<Vec<_>>()
+        };
+        let all = [
+            "time", "wall", "target", "module", "file", "line", "tid", "thread",
+        ];
+        assert_eq!(meta(&sent[0]), all);
+        assert_eq!(meta(&sent[1]), ["time", "wall"]);
+        assert_eq!(meta(&sent[2]), ["time", "wall", "line"]);
+        assert_eq!(meta(&sent[3]), ["time", "wall", "target"]);
+        assert_eq!(meta(&sent[4]), ["time", "wall", "file"]);
+        assert_eq!(meta(&sent[5]), ["time", "wall", "target", "file", "line"]);
+        // clearing a surface doesn't change the previous record.
+        assert_eq!(meta(&sent[7]), ["time", "wall"]);
+        assert_eq!(meta(&sent[9]), all);
+    }
+
+    #[test]
+    fn reverted_messages_are_sent_again_completely() {
+        let mut encoder = Encoder::new(true);
+        let mut viewer = Viewer::default();
+        let first = record(
+            "map",
+            "my_crate",
+            "src/main.rs",
+            1,
+            "\"pos\":[1,2,0]".to_owned(),
+        );
+        viewer.decode(&encoder.encode(&first, 1).unwrap());
+        // the second message is dropped, e.g. by the frame budget, so the viewer never sees it.
+        let dropped = record(
+            "new",
+            "other",
+            "src/other.rs",
+            2,
+            "\"pos\":[3,4,0]".to_owned(),
+        );
+        encoder.encode(&dropped, 1).unwrap();
+        encoder.revert();
+        let msg = encoder.encode(&dropped, 1).unwrap();
+        let basic = Encoder::new(false).encode(&dropped, 1).unwrap();
+        assert_eq!(viewer.decode(&msg), sorted(json::parse(&basic).unwrap()));
+        // the meta of the first record is still the one of the viewer.
+        let again = encoder.encode(&first, 1).unwrap();
+        encoder.revert();
+        let msg = encoder.encode(&first, 1).unwrap();
+        assert_eq!(again, msg);
+        let basic = Encoder::new(false).encode(&first, 1).unwrap();
+        assert_eq!(viewer.decode(&msg), sorted(json::parse(&basic).unwrap()));
+    }
+
+    #[test]
+    fn reconnected_clients_get_a_new_dictionary() {
+        let messages = [
+            record(
+                "map",
+                "my_crate",
+                "src/main.rs",
+                1,
+                "\"pos\":[1,2,0]".to_owned(),
+            ),
+            Message::Clear("map".to_owned()),
+        ];
+        let first = roundtrip(&messages);
+        // every connection has its own encoder, so a reconnected viewer gets the same messages.
+        assert_eq!(roundtrip(&messages), first);
+    }
+
+    #[test]
+    fn non_finite_numbers_keep_the_connection_intact() {
+        let nan = format!(
+            "\"pos\":{},\"size\":{}",
+            Pos([f64::NAN, 1.0, 0.0]),
+            json::Num(f64::INFINITY)
+        );
+        let sent = roundtrip(&[
+            record("nan", "my_crate::physics", "src/physics.rs", 10, nan),
+            record(
+                "nan",
+                "my_crate::physics",
+                "src/physics.rs",
+                11,
+                "\"pos\":[1,2,0]".to_owned(),
+            ),
+        ]);
+        assert!(
+            sent[0].contains("\"pos\":[null,1,0],\"size\":null"),
+            "{}",
+            sent[0]
+        );
+        // the second record relies on the strings interned and the meta sent by the first one.
+        assert!(
+            !sent[1].contains("dict") && !sent[1].contains("target"),
+            "{}",
+            sent[1]
+        );
+    }
+}
@@ -136,4 +136,36 @@ fn test_init() {
     clear!("just-clear");
 
     v_log::vlogger().flush();
+
+    // after the flush, the server has taken every record, at least the 1200 spam records.
+    // Nothing is dropped, as there is no queue limit or rate limit.
+    let stats = web_vlog::stats();
+    assert!(stats.received >= 1200, "{stats:?}");
+    assert!(stats.bytes_sent > 0, "{stats:?}");
+    assert_eq!(stats.dropped, 0, "{stats:?}");
+    assert_eq!(stats.rate_limited, 0, "{stats:?}");
+}
+
+#[test]
+fn surface_handles() {
+    let handles = web_vlog::surface!("handles/typed");
+    assert_eq!(handles.name(), "handles/typed");
+    assert_eq!(&**handles, "handles/typed");
+    assert_eq!(handles.to_string(), "handles/typed");
+    assert_eq!(handles, web_vlog::Surface::new("handles/typed"));
+    // the handle is used in place of the surface name in the macros of `v_log`.
+    point!(handles, [10., 10.], 10., Base, "-S", "1");
+    clear!(handles);
+    for invalid in [
+        "",
+        " padded",
+        "quoted \"surface\"",
+        "back\\slash",
+        "new\nline",
+    ] {
+        assert!(
+            std::panic::catch_unwind(|| web_vlog::Surface::new(invalid).name().len()).is_err(),
+            "{invalid:?}"
+        );
+    }
 }